@@ -1,14 +1,18 @@
 //!
 //! Builders collection to create [`wgpu::BindGroup`]'s and [`wgpu::BindGroupLayout`]'s.
 //!
-use std::num::NonZeroU64;
+use std::fmt;
+use std::num::{NonZeroU32, NonZeroU64};
+use std::ops::Deref;
 
 use wgpu::{BufferBinding, SamplerBindingType};
 
+use crate::shader_reflection::ShaderReflection;
+
 /// A type aimed at simplifying the creation of a bind group layout.
 #[derive(Debug, Default)]
 pub struct LayoutBuilder {
-    bindings: Vec<(wgpu::ShaderStages, wgpu::BindingType)>,
+    entries: Vec<wgpu::BindGroupLayoutEntry>,
 }
 
 impl LayoutBuilder {
@@ -17,14 +21,69 @@ impl LayoutBuilder {
         Self::default()
     }
 
+    /// Begin building the layout of bind group `group` with all bindings `reflection` declares in
+    /// it.
+    pub fn from_reflection(reflection: &ShaderReflection, group: u32) -> Self {
+        Self {
+            entries: reflection.bind_group_layout_entries(group),
+        }
+    }
+
     /// Specify a new binding.
     ///
-    /// The `binding` position of each binding will be inferred as the index within the order that
-    /// they are added to this builder type. If you require manually specifying the binding
-    /// location, you may be better off not using the `BindGroupLayoutBuilder` and instead
-    /// constructing the `BindGroupLayout` and `BindGroup` manually.
-    pub fn binding(mut self, visibility: wgpu::ShaderStages, ty: wgpu::BindingType) -> Self {
-        self.bindings.push((visibility, ty));
+    /// The `binding` position is inferred as one past the previously added binding, starting
+    /// from 0. Use [`LayoutBuilder::binding_at`] to specify it manually.
+    pub fn binding(self, visibility: wgpu::ShaderStages, ty: wgpu::BindingType) -> Self {
+        let binding = next_binding(self.entries.iter().map(|e| e.binding));
+        self.binding_at(binding, visibility, ty)
+    }
+
+    /// Specify a new binding at given `binding` position.
+    ///
+    /// Bindings added after this one continue from `binding + 1`.
+    pub fn binding_at(
+        mut self,
+        binding: u32,
+        visibility: wgpu::ShaderStages,
+        ty: wgpu::BindingType,
+    ) -> Self {
+        self.entries.push(wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty,
+            count: None,
+        });
+        self
+    }
+
+    /// Specify a new binding array of `count` elements.
+    ///
+    /// Requires `Features::TEXTURE_BINDING_ARRAY` for textures and samplers and
+    /// `Features::BUFFER_BINDING_ARRAY` for buffers.
+    pub fn binding_array(
+        self,
+        visibility: wgpu::ShaderStages,
+        ty: wgpu::BindingType,
+        count: NonZeroU32,
+    ) -> Self {
+        let binding = next_binding(self.entries.iter().map(|e| e.binding));
+        self.binding_array_at(binding, visibility, ty, count)
+    }
+
+    /// Specify a new binding array of `count` elements at given `binding` position.
+    pub fn binding_array_at(
+        mut self,
+        binding: u32,
+        visibility: wgpu::ShaderStages,
+        ty: wgpu::BindingType,
+        count: NonZeroU32,
+    ) -> Self {
+        self.entries.push(wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty,
+            count: Some(count),
+        });
         self
     }
 
@@ -62,7 +121,7 @@ impl LayoutBuilder {
 
     /// Add a non-filtering sampler binding to the layout.
     pub fn non_filtering_sampler(self, visibility: wgpu::ShaderStages) -> Self {
-        let ty = wgpu::BindingType::Sampler(SamplerBindingType::Filtering);
+        let ty = wgpu::BindingType::Sampler(SamplerBindingType::NonFiltering);
         self.binding(visibility, ty)
     }
 
@@ -88,6 +147,37 @@ impl LayoutBuilder {
         self.binding(visibility, ty)
     }
 
+    /// Add an array of `count` textures to the layout.
+    ///
+    /// Requires `Features::TEXTURE_BINDING_ARRAY`.
+    pub fn texture_array(
+        self,
+        visibility: wgpu::ShaderStages,
+        multisampled: bool,
+        view_dimension: wgpu::TextureViewDimension,
+        sample_type: wgpu::TextureSampleType,
+        count: NonZeroU32,
+    ) -> Self {
+        let ty = wgpu::BindingType::Texture {
+            multisampled,
+            view_dimension,
+            sample_type,
+        };
+        self.binding_array(visibility, ty, count)
+    }
+
+    /// Add an array of `count` samplers to the layout.
+    ///
+    /// Requires `Features::TEXTURE_BINDING_ARRAY`.
+    pub fn sampler_array(
+        self,
+        visibility: wgpu::ShaderStages,
+        ty: SamplerBindingType,
+        count: NonZeroU32,
+    ) -> Self {
+        self.binding_array(visibility, wgpu::BindingType::Sampler(ty), count)
+    }
+
     /// Add a storage texture binding to the layout.
     pub fn storage_texture(
         self,
@@ -104,30 +194,71 @@ impl LayoutBuilder {
         self.binding(visibility, ty)
     }
 
+    /// Entries of the layout in the order they were added.
+    pub fn entries(&self) -> &[wgpu::BindGroupLayoutEntry] {
+        &self.entries
+    }
+
     /// Build the bind group layout from the specified parameters.
     pub fn build(self, device: &wgpu::Device, label: Option<&str>) -> wgpu::BindGroupLayout {
-        let mut entries = Vec::with_capacity(self.bindings.len());
-        for (i, (visibility, ty)) in self.bindings.into_iter().enumerate() {
-            let layout_binding = wgpu::BindGroupLayoutEntry {
-                binding: i as u32,
-                visibility,
-                ty,
-                count: None,
-            };
-            entries.push(layout_binding);
-        }
         let descriptor = wgpu::BindGroupLayoutDescriptor {
             label,
-            entries: &entries,
+            entries: &self.entries,
         };
         device.create_bind_group_layout(&descriptor)
     }
+
+    /// Like [`LayoutBuilder::build`], but checks the entries first and keeps them along the
+    /// layout, so bind groups can be validated against it with [`Builder::try_build`].
+    pub fn try_build(
+        self,
+        device: &wgpu::Device,
+        label: Option<&str>,
+    ) -> Result<Layout, BindGroupError> {
+        check_duplicates(self.entries.iter().map(|e| e.binding))?;
+        for entry in &self.entries {
+            if entry.count.is_some() {
+                check_array_features(device.features(), entry.binding, &entry.ty)?;
+            }
+        }
+
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label,
+            entries: &self.entries,
+        });
+        Ok(Layout {
+            layout,
+            entries: self.entries,
+        })
+    }
+}
+
+/// Bind group layout that remembers entries it was created from.
+#[derive(Debug)]
+pub struct Layout {
+    layout: wgpu::BindGroupLayout,
+    entries: Vec<wgpu::BindGroupLayoutEntry>,
+}
+
+impl Layout {
+    /// Entries the layout was created from.
+    pub fn entries(&self) -> &[wgpu::BindGroupLayoutEntry] {
+        &self.entries
+    }
+}
+
+impl Deref for Layout {
+    type Target = wgpu::BindGroupLayout;
+
+    fn deref(&self) -> &Self::Target {
+        &self.layout
+    }
 }
 
 /// Simplified creation of a bind group.
 #[derive(Debug, Default)]
 pub struct Builder<'a> {
-    resources: Vec<wgpu::BindingResource<'a>>,
+    resources: Vec<(u32, wgpu::BindingResource<'a>)>,
 }
 
 impl<'a> Builder<'a> {
@@ -138,12 +269,18 @@ impl<'a> Builder<'a> {
 
     /// Specify a new binding.
     ///
-    /// The `binding` position of each binding will be inferred as the index within the order that
-    /// they are added to this builder type. If you require manually specifying the binding
-    /// location, you may be better off not using the `BindGroupBuilder` and instead constructing
-    /// the `BindGroupLayout` and `BindGroup` manually.
-    pub fn binding(mut self, resource: wgpu::BindingResource<'a>) -> Self {
-        self.resources.push(resource);
+    /// The `binding` position is inferred as one past the previously added binding, starting
+    /// from 0. Use [`Builder::binding_at`] to specify it manually.
+    pub fn binding(self, resource: wgpu::BindingResource<'a>) -> Self {
+        let binding = next_binding(self.resources.iter().map(|(binding, _)| *binding));
+        self.binding_at(binding, resource)
+    }
+
+    /// Specify a new binding at given `binding` position.
+    ///
+    /// Bindings added after this one continue from `binding + 1`.
+    pub fn binding_at(mut self, binding: u32, resource: wgpu::BindingResource<'a>) -> Self {
+        self.resources.push((binding, resource));
         self
     }
 
@@ -193,6 +330,51 @@ impl<'a> Builder<'a> {
         self.binding(resource)
     }
 
+    /// Specify an array of texture views to be bound.
+    ///
+    /// Requires `Features::TEXTURE_BINDING_ARRAY`.
+    pub fn texture_view_array(self, views: &'a [&'a wgpu::TextureView]) -> Self {
+        let resource = wgpu::BindingResource::TextureViewArray(views);
+        self.binding(resource)
+    }
+
+    /// Specify an array of samplers to be bound.
+    ///
+    /// Requires `Features::TEXTURE_BINDING_ARRAY`.
+    pub fn sampler_array(self, samplers: &'a [&'a wgpu::Sampler]) -> Self {
+        let resource = wgpu::BindingResource::SamplerArray(samplers);
+        self.binding(resource)
+    }
+
+    /// Check that specified resources match layout `entries`: there is exactly one resource for
+    /// every entry, of a compatible type and, for arrays, of the right length.
+    pub fn validate(
+        &self,
+        entries: &[wgpu::BindGroupLayoutEntry],
+        features: wgpu::Features,
+    ) -> Result<(), BindGroupError> {
+        check_duplicates(self.resources.iter().map(|(binding, _)| *binding))?;
+        if self.resources.len() != entries.len() {
+            return Err(BindGroupError::CountMismatch {
+                expected: entries.len(),
+                found: self.resources.len(),
+            });
+        }
+
+        let mut entries = entries.to_vec();
+        entries.sort_by_key(|entry| entry.binding);
+        let mut resources: Vec<_> = self.resources.iter().collect();
+        resources.sort_by_key(|(binding, _)| *binding);
+
+        for (entry, (binding, resource)) in entries.iter().zip(resources) {
+            if entry.binding != *binding {
+                return Err(BindGroupError::MissingResource(entry.binding));
+            }
+            check_resource(entry, resource, features)?;
+        }
+        Ok(())
+    }
+
     /// Build the bind group with the specified resources.
     pub fn build(
         self,
@@ -200,14 +382,11 @@ impl<'a> Builder<'a> {
         layout: &wgpu::BindGroupLayout,
         label: Option<&str>,
     ) -> wgpu::BindGroup {
-        let mut entries = Vec::with_capacity(self.resources.len());
-        for (i, resource) in self.resources.into_iter().enumerate() {
-            let binding = wgpu::BindGroupEntry {
-                binding: i as u32,
-                resource,
-            };
-            entries.push(binding);
-        }
+        let entries: Vec<_> = self
+            .resources
+            .into_iter()
+            .map(|(binding, resource)| wgpu::BindGroupEntry { binding, resource })
+            .collect();
         let descriptor = wgpu::BindGroupDescriptor {
             label,
             layout,
@@ -215,4 +394,164 @@ impl<'a> Builder<'a> {
         };
         device.create_bind_group(&descriptor)
     }
+
+    /// Like [`Builder::build`], but returns an error instead of failing wgpu validation when
+    /// resources do not match the `layout`.
+    pub fn try_build(
+        self,
+        device: &wgpu::Device,
+        layout: &Layout,
+        label: Option<&str>,
+    ) -> Result<wgpu::BindGroup, BindGroupError> {
+        self.validate(layout.entries(), device.features())?;
+        Ok(self.build(device, layout, label))
+    }
+}
+
+/// Mismatch between bind group resources and layout entries.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BindGroupError {
+    /// Same binding position is used twice.
+    DuplicateBinding(u32),
+    /// Binding arrays are used without the device features they need.
+    MissingFeatures {
+        binding: u32,
+        features: wgpu::Features,
+    },
+    /// Different number of resources and layout entries.
+    CountMismatch { expected: usize, found: usize },
+    /// Layout entry has no resource at its binding position.
+    MissingResource(u32),
+    /// Resource type does not fit the layout entry.
+    TypeMismatch {
+        binding: u32,
+        expected: wgpu::BindingType,
+        found: &'static str,
+    },
+    /// Resource array length differs from layout entry `count`.
+    ArrayLengthMismatch {
+        binding: u32,
+        expected: u32,
+        found: usize,
+    },
+}
+
+impl fmt::Display for BindGroupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DuplicateBinding(binding) => write!(f, "binding {} is used twice", binding),
+            Self::MissingFeatures { binding, features } => write!(
+                f,
+                "binding array at {} requires features {:?}",
+                binding, features
+            ),
+            Self::CountMismatch { expected, found } => write!(
+                f,
+                "layout has {} entries, but {} resources were given",
+                expected, found
+            ),
+            Self::MissingResource(binding) => write!(f, "no resource for binding {}", binding),
+            Self::TypeMismatch {
+                binding,
+                expected,
+                found,
+            } => write!(
+                f,
+                "binding {} expects {:?}, but {} was given",
+                binding, expected, found
+            ),
+            Self::ArrayLengthMismatch {
+                binding,
+                expected,
+                found,
+            } => write!(
+                f,
+                "binding {} expects array of {} elements, but {} were given",
+                binding, expected, found
+            ),
+        }
+    }
+}
+
+impl std::error::Error for BindGroupError {}
+
+fn next_binding(bindings: impl DoubleEndedIterator<Item = u32>) -> u32 {
+    bindings.last().map_or(0, |binding| binding + 1)
+}
+
+fn check_duplicates(bindings: impl Iterator<Item = u32>) -> Result<(), BindGroupError> {
+    let mut bindings: Vec<_> = bindings.collect();
+    bindings.sort_unstable();
+    match bindings.windows(2).find(|pair| pair[0] == pair[1]) {
+        Some(pair) => Err(BindGroupError::DuplicateBinding(pair[0])),
+        None => Ok(()),
+    }
+}
+
+fn check_array_features(
+    features: wgpu::Features,
+    binding: u32,
+    ty: &wgpu::BindingType,
+) -> Result<(), BindGroupError> {
+    let required = match ty {
+        wgpu::BindingType::Buffer { .. } => wgpu::Features::BUFFER_BINDING_ARRAY,
+        _ => wgpu::Features::TEXTURE_BINDING_ARRAY,
+    };
+    if features.contains(required) {
+        Ok(())
+    } else {
+        Err(BindGroupError::MissingFeatures {
+            binding,
+            features: required - features,
+        })
+    }
+}
+
+fn check_resource(
+    entry: &wgpu::BindGroupLayoutEntry,
+    resource: &wgpu::BindingResource,
+    features: wgpu::Features,
+) -> Result<(), BindGroupError> {
+    use wgpu::BindingResource as R;
+    use wgpu::BindingType as T;
+
+    let (name, len) = match resource {
+        R::Buffer(_) => ("buffer", None),
+        R::BufferArray(buffers) => ("buffer array", Some(buffers.len())),
+        R::Sampler(_) => ("sampler", None),
+        R::SamplerArray(samplers) => ("sampler array", Some(samplers.len())),
+        R::TextureView(_) => ("texture view", None),
+        R::TextureViewArray(views) => ("texture view array", Some(views.len())),
+        _ => ("unknown resource", None),
+    };
+    let type_matches = matches!(
+        (&entry.ty, resource),
+        (T::Buffer { .. }, R::Buffer(_) | R::BufferArray(_))
+            | (T::Sampler(_), R::Sampler(_) | R::SamplerArray(_))
+            | (
+                T::Texture { .. } | T::StorageTexture { .. },
+                R::TextureView(_) | R::TextureViewArray(_)
+            )
+    );
+    if !type_matches || entry.count.is_some() != len.is_some() {
+        return Err(BindGroupError::TypeMismatch {
+            binding: entry.binding,
+            expected: entry.ty,
+            found: name,
+        });
+    }
+
+    if let (Some(count), Some(len)) = (entry.count, len) {
+        check_array_features(features, entry.binding, &entry.ty)?;
+        let partially_bound = features.contains(wgpu::Features::PARTIALLY_BOUND_BINDING_ARRAY);
+        let count = count.get();
+        if len > count as usize || (!partially_bound && len != count as usize) {
+            return Err(BindGroupError::ArrayLengthMismatch {
+                binding: entry.binding,
+                expected: count,
+                found: len,
+            });
+        }
+    }
+    Ok(())
 }
@@ -0,0 +1,391 @@
+//!
+//! Deduplication of bind group layouts and bind groups
+//!
+//! Every call to `create_bind_group_layout` returns a new object, even for identical entries, so
+//! two materials of the same type would otherwise not share layouts and bind groups. A
+//! [`BindGroupCache`] belongs to a single [`wgpu::Device`] and must always be used with it.
+//!
+//! wgpu objects carry no identity we could hash, so bind groups are keyed by the address of the
+//! [`Arc`] holding each resource. The cache keeps a [`Weak`] to every resource, which both keeps
+//! the address from being reused and tells when a bind group may be evicted.
+//!
+//! # Examples
+//!
+//! ```ignore
+//! let mut cache = BindGroupCache::new();
+//!
+//! let entries = LayoutBuilder::new()
+//!     .uniform_buffer(wgpu::ShaderStages::FRAGMENT, false)
+//!     .entries()
+//!     .to_vec();
+//! let layout = cache.layout(&device, &entries, Some("Color layout"));
+//!
+//! let buffer = Arc::new(buffer);
+//! let bind_group = cache.bind_group(
+//!     &device,
+//!     &layout,
+//!     &[(0, CachedResource::buffer(&buffer))],
+//!     Some("Color bind group"),
+//! );
+//!
+//! drop(buffer);
+//! assert_eq!(cache.evict(), 1);
+//! ```
+use std::collections::HashMap;
+use std::sync::{Arc, Weak};
+
+/// Resource of a cached bind group, shared through [`Arc`] so its identity can be tracked.
+#[derive(Debug, Clone)]
+pub enum CachedResource {
+    /// Range of a buffer, `size` of `None` binds the rest of the buffer.
+    Buffer {
+        buffer: Arc<wgpu::Buffer>,
+        offset: wgpu::BufferAddress,
+        size: Option<wgpu::BufferSize>,
+    },
+    /// Texture view.
+    TextureView(Arc<wgpu::TextureView>),
+    /// Sampler.
+    Sampler(Arc<wgpu::Sampler>),
+}
+
+impl CachedResource {
+    /// Bind the whole `buffer`.
+    pub fn buffer(buffer: &Arc<wgpu::Buffer>) -> Self {
+        Self::Buffer {
+            buffer: Arc::clone(buffer),
+            offset: 0,
+            size: None,
+        }
+    }
+
+    /// Bind `texture_view`.
+    pub fn texture_view(texture_view: &Arc<wgpu::TextureView>) -> Self {
+        Self::TextureView(Arc::clone(texture_view))
+    }
+
+    /// Bind `sampler`.
+    pub fn sampler(sampler: &Arc<wgpu::Sampler>) -> Self {
+        Self::Sampler(Arc::clone(sampler))
+    }
+
+    fn key(&self) -> ResourceKey {
+        match self {
+            Self::Buffer {
+                buffer,
+                offset,
+                size,
+            } => ResourceKey::Buffer(Arc::as_ptr(buffer) as usize, *offset, *size),
+            Self::TextureView(view) => ResourceKey::TextureView(Arc::as_ptr(view) as usize),
+            Self::Sampler(sampler) => ResourceKey::Sampler(Arc::as_ptr(sampler) as usize),
+        }
+    }
+
+    fn downgrade(&self) -> WeakResource {
+        match self {
+            Self::Buffer { buffer, .. } => WeakResource::Buffer(Arc::downgrade(buffer)),
+            Self::TextureView(view) => WeakResource::TextureView(Arc::downgrade(view)),
+            Self::Sampler(sampler) => WeakResource::Sampler(Arc::downgrade(sampler)),
+        }
+    }
+
+    fn binding_resource(&self) -> wgpu::BindingResource<'_> {
+        match self {
+            Self::Buffer {
+                buffer,
+                offset,
+                size,
+            } => wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                buffer,
+                offset: *offset,
+                size: *size,
+            }),
+            Self::TextureView(view) => wgpu::BindingResource::TextureView(view),
+            Self::Sampler(sampler) => wgpu::BindingResource::Sampler(sampler),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum ResourceKey {
+    Buffer(usize, wgpu::BufferAddress, Option<wgpu::BufferSize>),
+    TextureView(usize),
+    Sampler(usize),
+}
+
+#[derive(Debug)]
+enum WeakResource {
+    Buffer(Weak<wgpu::Buffer>),
+    TextureView(Weak<wgpu::TextureView>),
+    Sampler(Weak<wgpu::Sampler>),
+}
+
+impl WeakResource {
+    fn is_alive(&self) -> bool {
+        match self {
+            Self::Buffer(buffer) => buffer.strong_count() > 0,
+            Self::TextureView(view) => view.strong_count() > 0,
+            Self::Sampler(sampler) => sampler.strong_count() > 0,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Hash)]
+struct BindGroupKey {
+    layout: usize,
+    resources: Vec<(u32, ResourceKey)>,
+}
+
+#[derive(Debug)]
+struct CachedBindGroup {
+    bind_group: Arc<wgpu::BindGroup>,
+    layout: Weak<wgpu::BindGroupLayout>,
+    resources: Vec<WeakResource>,
+}
+
+impl CachedBindGroup {
+    fn is_alive(&self) -> bool {
+        self.layout.strong_count() > 0 && self.resources.iter().all(WeakResource::is_alive)
+    }
+}
+
+/// Cache of bind group layouts and bind groups for one device.
+#[derive(Debug, Default)]
+pub struct BindGroupCache {
+    layouts: HashMap<Vec<wgpu::BindGroupLayoutEntry>, Arc<wgpu::BindGroupLayout>>,
+    bind_groups: HashMap<BindGroupKey, CachedBindGroup>,
+}
+
+impl BindGroupCache {
+    /// Create an empty cache.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the layout with given `entries`, creating it on first request.
+    ///
+    /// Order of entries does not matter. `label` is only used when the layout is created.
+    /// Layouts are small and are kept for the lifetime of the cache.
+    pub fn layout(
+        &mut self,
+        device: &wgpu::Device,
+        entries: &[wgpu::BindGroupLayoutEntry],
+        label: Option<&str>,
+    ) -> Arc<wgpu::BindGroupLayout> {
+        let key = layout_key(entries);
+        let layout = self.layouts.entry(key).or_insert_with_key(|entries| {
+            Arc::new(
+                device
+                    .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor { label, entries }),
+            )
+        });
+        Arc::clone(layout)
+    }
+
+    /// Get the bind group of `layout` with given `resources`, creating it on first request.
+    ///
+    /// Resources are given with their binding index, which may leave gaps like the layout
+    /// entries. `label` is only used when the bind group is created. A cached bind group whose
+    /// layout or resources were dropped since is created again.
+    pub fn bind_group(
+        &mut self,
+        device: &wgpu::Device,
+        layout: &Arc<wgpu::BindGroupLayout>,
+        resources: &[(u32, CachedResource)],
+        label: Option<&str>,
+    ) -> Arc<wgpu::BindGroup> {
+        let key = BindGroupKey {
+            layout: Arc::as_ptr(layout) as usize,
+            resources: resources
+                .iter()
+                .map(|(binding, resource)| (*binding, resource.key()))
+                .collect(),
+        };
+
+        match self.bind_groups.get(&key) {
+            Some(cached) if cached.is_alive() => Arc::clone(&cached.bind_group),
+            _ => {
+                let cached = create_bind_group(device, layout, resources, label);
+                let bind_group = Arc::clone(&cached.bind_group);
+                self.bind_groups.insert(key, cached);
+                bind_group
+            }
+        }
+    }
+
+    /// Drop bind groups whose layout or any of resources has been dropped.
+    ///
+    /// Meant to be called once per frame. Returns the number of evicted bind groups.
+    pub fn evict(&mut self) -> usize {
+        let before = self.bind_groups.len();
+        self.bind_groups.retain(|_, cached| cached.is_alive());
+        before - self.bind_groups.len()
+    }
+
+    /// Number of cached layouts.
+    pub fn layout_count(&self) -> usize {
+        self.layouts.len()
+    }
+
+    /// Number of cached bind groups.
+    pub fn bind_group_count(&self) -> usize {
+        self.bind_groups.len()
+    }
+}
+
+// Entries sorted by binding, so their order does not matter.
+fn layout_key(entries: &[wgpu::BindGroupLayoutEntry]) -> Vec<wgpu::BindGroupLayoutEntry> {
+    let mut key = entries.to_vec();
+    key.sort_by_key(|entry| entry.binding);
+    key
+}
+
+fn create_bind_group(
+    device: &wgpu::Device,
+    layout: &Arc<wgpu::BindGroupLayout>,
+    resources: &[(u32, CachedResource)],
+    label: Option<&str>,
+) -> CachedBindGroup {
+    let entries: Vec<_> = resources
+        .iter()
+        .map(|(binding, resource)| wgpu::BindGroupEntry {
+            binding: *binding,
+            resource: resource.binding_resource(),
+        })
+        .collect();
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label,
+        layout,
+        entries: &entries,
+    });
+    CachedBindGroup {
+        bind_group: Arc::new(bind_group),
+        layout: Arc::downgrade(layout),
+        resources: resources
+            .iter()
+            .map(|(_, resource)| resource.downgrade())
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bind_group_builder::LayoutBuilder;
+
+    const VISIBILITY: wgpu::ShaderStages = wgpu::ShaderStages::FRAGMENT;
+
+    fn device() -> wgpu::Device {
+        let instance = wgpu::Instance::new(wgpu::Backends::all());
+        let adapter = pollster::block_on(instance.request_adapter(&Default::default()))
+            .expect("no adapter to test with");
+        let (device, _) = pollster::block_on(adapter.request_device(&Default::default(), None))
+            .expect("failed to create device");
+        device
+    }
+
+    fn buffer(device: &wgpu::Device) -> Arc<wgpu::Buffer> {
+        Arc::new(device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: 16,
+            usage: wgpu::BufferUsages::UNIFORM,
+            mapped_at_creation: false,
+        }))
+    }
+
+    fn entries() -> Vec<wgpu::BindGroupLayoutEntry> {
+        LayoutBuilder::new()
+            .uniform_buffer(VISIBILITY, false)
+            .filtering_sampler(VISIBILITY)
+            .entries()
+            .to_vec()
+    }
+
+    #[test]
+    fn layout_key_ignores_entry_order() {
+        let entries = entries();
+        let reversed: Vec<_> = entries.iter().rev().copied().collect();
+        assert_eq!(layout_key(&entries), layout_key(&reversed));
+        assert_ne!(layout_key(&entries), layout_key(&entries[..1]));
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter"]
+    fn deduplicates_layouts() {
+        let device = device();
+        let mut cache = BindGroupCache::new();
+        let entries = entries();
+        let reversed: Vec<_> = entries.iter().rev().copied().collect();
+
+        let layout = cache.layout(&device, &entries, None);
+        assert!(Arc::ptr_eq(
+            &layout,
+            &cache.layout(&device, &reversed, None)
+        ));
+        assert_eq!(cache.layout_count(), 1);
+
+        let other = cache.layout(&device, &entries[..1], None);
+        assert!(!Arc::ptr_eq(&layout, &other));
+        assert_eq!(cache.layout_count(), 2);
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter"]
+    fn evicts_bind_groups_of_dropped_resources() {
+        let device = device();
+        let mut cache = BindGroupCache::new();
+        let layout = cache.layout(&device, &entries()[..1], None);
+        let kept = buffer(&device);
+        let dropped = buffer(&device);
+
+        let bind_group = cache.bind_group(
+            &device,
+            &layout,
+            &[(0, CachedResource::buffer(&kept))],
+            None,
+        );
+        let again = cache.bind_group(
+            &device,
+            &layout,
+            &[(0, CachedResource::buffer(&kept))],
+            None,
+        );
+        assert!(Arc::ptr_eq(&bind_group, &again));
+        cache.bind_group(
+            &device,
+            &layout,
+            &[(0, CachedResource::buffer(&dropped))],
+            None,
+        );
+        assert_eq!(cache.bind_group_count(), 2);
+
+        drop(dropped);
+        assert_eq!(cache.evict(), 1);
+        assert_eq!(cache.bind_group_count(), 1);
+        assert_eq!(cache.evict(), 0);
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter"]
+    fn evicts_bind_groups_of_dropped_layouts() {
+        let device = device();
+        let mut cache = BindGroupCache::new();
+        let layout = Arc::new(
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: None,
+                entries: &entries()[..1],
+            }),
+        );
+        let buffer = buffer(&device);
+        cache.bind_group(
+            &device,
+            &layout,
+            &[(0, CachedResource::buffer(&buffer))],
+            None,
+        );
+
+        drop(layout);
+        assert_eq!(cache.evict(), 1);
+        assert_eq!(cache.bind_group_count(), 0);
+    }
+}
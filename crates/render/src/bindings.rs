@@ -0,0 +1,299 @@
+//!
+//! Bind groups shared by every pipeline of the engine
+//!
+//! Shaders see four bind groups, ordered from the least to the most frequently changing one:
+//!
+//! - [`FRAME_GROUP`]: [`FrameUniform`], globals like time, bound once per frame.
+//! - [`VIEW_GROUP`]: [`ViewUniform`], camera matrices, bound once per view.
+//! - [`MATERIAL_GROUP`]: parameters of the material, defined by the material itself.
+//! - [`OBJECT_GROUP`]: [`ObjectUniform`], transform of the drawn object.
+//!
+//! Materials are only responsible for the layout of their own group, the pipeline layout is
+//! assembled by [`BindingLayouts::with_material`]. In WGSL, `#import render::bindings` declares
+//! the `frame`, `view` and `object` variables, see [`shader_module`].
+//!
+//! # Examples
+//!
+//! ```ignore
+//! let layouts = BindingLayouts::new(&device, &mut bind_groups);
+//! let material_layout = BaseMaterial::cached_bind_group_layout(&device, &mut bind_groups);
+//! let builder = RenderPipelineBuilder::from_shared_layouts(&layouts.with_material(&material_layout), &shader);
+//!
+//! let frame = bindings::uniform(&device, &mut bind_groups, FrameUniform::default(), "Frame");
+//! let object = bindings::uniform(&device, &mut bind_groups, ObjectUniform::default(), "Object");
+//! render_pass.set_bind_group(FRAME_GROUP, &frame.bind_group, &[]);
+//! render_pass.set_bind_group(OBJECT_GROUP, &object.bind_group, &[]);
+//! ```
+use std::sync::Arc;
+
+use bytemuck::{Pod, Zeroable};
+
+use crate::bind_group_builder::LayoutBuilder;
+use crate::bind_group_cache::BindGroupCache;
+use crate::buffers::uniform::UniformBuffer;
+use crate::layout::{self, WgslType};
+use crate::shader_preprocessor::Preprocessor;
+
+/// Index of the per-frame bind group.
+pub const FRAME_GROUP: u32 = 0;
+/// Index of the per-view bind group.
+pub const VIEW_GROUP: u32 = 1;
+/// Index of the material bind group.
+pub const MATERIAL_GROUP: u32 = 2;
+/// Index of the per-object bind group.
+pub const OBJECT_GROUP: u32 = 3;
+
+/// Name of the WGSL module registered by [`shader_module`].
+pub const SHADER_MODULE: &str = "render::bindings";
+
+const VISIBILITY: wgpu::ShaderStages = wgpu::ShaderStages::VERTEX_FRAGMENT;
+
+/// Column major identity matrix.
+pub const IDENTITY: [[f32; 4]; 4] = [
+    [1.0, 0.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0, 0.0],
+    [0.0, 0.0, 1.0, 0.0],
+    [0.0, 0.0, 0.0, 1.0],
+];
+
+/// Globals of [`FRAME_GROUP`], `Frame` in WGSL.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Pod, Zeroable, WgslType)]
+#[wgsl(name = "Frame", uniform)]
+pub struct FrameUniform {
+    /// Seconds since the start of the game.
+    pub time: f32,
+    /// Seconds since the previous frame.
+    pub delta_time: f32,
+    /// Number of the frame.
+    pub index: u32,
+    _padding: u32,
+}
+
+impl FrameUniform {
+    pub fn new(time: f32, delta_time: f32, index: u32) -> Self {
+        Self {
+            time,
+            delta_time,
+            index,
+            _padding: 0,
+        }
+    }
+}
+
+/// Camera of [`VIEW_GROUP`], `View` in WGSL.
+///
+/// Matrices are column major.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Pod, Zeroable, WgslType)]
+#[wgsl(name = "View", uniform)]
+pub struct ViewUniform {
+    /// World to view space.
+    pub view: [[f32; 4]; 4],
+    /// View to clip space.
+    pub projection: [[f32; 4]; 4],
+    /// World to clip space, `projection * view`.
+    pub view_projection: [[f32; 4]; 4],
+    /// Position of the camera in world space.
+    pub position: [f32; 3],
+    _padding: f32,
+}
+
+impl ViewUniform {
+    /// View at `position` with given matrices, `view_projection` is computed from them.
+    pub fn new(view: [[f32; 4]; 4], projection: [[f32; 4]; 4], position: [f32; 3]) -> Self {
+        Self {
+            view,
+            projection,
+            view_projection: multiply(&projection, &view),
+            position,
+            _padding: 0.0,
+        }
+    }
+}
+
+impl Default for ViewUniform {
+    fn default() -> Self {
+        Self::new(IDENTITY, IDENTITY, [0.0; 3])
+    }
+}
+
+/// Transform of [`OBJECT_GROUP`], `Object` in WGSL.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Pod, Zeroable, WgslType)]
+#[wgsl(name = "Object", uniform)]
+pub struct ObjectUniform {
+    /// Object to world space, column major.
+    pub model: [[f32; 4]; 4],
+}
+
+impl ObjectUniform {
+    pub fn new(model: [[f32; 4]; 4]) -> Self {
+        Self { model }
+    }
+}
+
+impl Default for ObjectUniform {
+    fn default() -> Self {
+        Self::new(IDENTITY)
+    }
+}
+
+/// Layouts of the frame, view and object bind groups.
+#[derive(Debug, Clone)]
+pub struct BindingLayouts {
+    pub frame: Arc<wgpu::BindGroupLayout>,
+    pub view: Arc<wgpu::BindGroupLayout>,
+    pub object: Arc<wgpu::BindGroupLayout>,
+}
+
+impl BindingLayouts {
+    /// Get the layouts, shared through `cache`.
+    pub fn new(device: &wgpu::Device, cache: &mut BindGroupCache) -> Self {
+        Self {
+            frame: uniform_layout(device, cache, "Frame"),
+            view: uniform_layout(device, cache, "View"),
+            object: uniform_layout(device, cache, "Object"),
+        }
+    }
+
+    /// Bind group layouts of a pipeline drawing with a material of layout `material`.
+    pub fn with_material(
+        &self,
+        material: &Arc<wgpu::BindGroupLayout>,
+    ) -> [Arc<wgpu::BindGroupLayout>; 4] {
+        [
+            Arc::clone(&self.frame),
+            Arc::clone(&self.view),
+            Arc::clone(material),
+            Arc::clone(&self.object),
+        ]
+    }
+}
+
+/// Create the buffer and bind group of `data` for the frame, view or object group.
+pub fn uniform<T>(
+    device: &wgpu::Device,
+    cache: &mut BindGroupCache,
+    data: T,
+    label: &str,
+) -> UniformBuffer<T>
+where
+    T: Pod,
+{
+    UniformBuffer::init_cached(device, cache, data, VISIBILITY, label)
+}
+
+/// Register the `render::bindings` module declaring `frame`, `view` and `object`, for `#import`.
+pub fn shader_module(preprocessor: Preprocessor) -> Preprocessor {
+    let source = format!(
+        "{}{}{}\n\
+         @group({}) @binding(0) var<uniform> frame: {};\n\
+         @group({}) @binding(0) var<uniform> view: {};\n\
+         @group({}) @binding(0) var<uniform> object: {};\n",
+        layout::wgsl_declaration::<FrameUniform>(),
+        layout::wgsl_declaration::<ViewUniform>(),
+        layout::wgsl_declaration::<ObjectUniform>(),
+        FRAME_GROUP,
+        FrameUniform::WGSL_NAME,
+        VIEW_GROUP,
+        ViewUniform::WGSL_NAME,
+        OBJECT_GROUP,
+        ObjectUniform::WGSL_NAME,
+    );
+    preprocessor.module(SHADER_MODULE, &source)
+}
+
+// Frame, view and object groups share a layout with a single uniform buffer.
+fn uniform_layout(
+    device: &wgpu::Device,
+    cache: &mut BindGroupCache,
+    label: &str,
+) -> Arc<wgpu::BindGroupLayout> {
+    let entries = LayoutBuilder::new().uniform_buffer(VISIBILITY, false);
+    cache.layout(device, entries.entries(), Some(label))
+}
+
+// Product of column major matrices.
+fn multiply(a: &[[f32; 4]; 4], b: &[[f32; 4]; 4]) -> [[f32; 4]; 4] {
+    let mut result = [[0.0; 4]; 4];
+    for (column, b_column) in result.iter_mut().zip(b) {
+        for (row, value) in column.iter_mut().enumerate() {
+            *value = (0..4).map(|k| a[k][row] * b_column[k]).sum();
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uniforms_match_shader_module() {
+        let source = shader_module(Preprocessor::new())
+            .process("main.wgsl", &format!("#import {}\n", SHADER_MODULE))
+            .unwrap()
+            .source;
+        layout::check_wgsl::<FrameUniform>(&source).unwrap();
+        layout::check_wgsl::<ViewUniform>(&source).unwrap();
+        layout::check_wgsl::<ObjectUniform>(&source).unwrap();
+    }
+
+    const IDENTITY: [[f32; 4]; 4] = [
+        [1.0, 0.0, 0.0, 0.0],
+        [0.0, 1.0, 0.0, 0.0],
+        [0.0, 0.0, 1.0, 0.0],
+        [0.0, 0.0, 0.0, 1.0],
+    ];
+
+    #[test]
+    fn multiplies_by_identity() {
+        let m = [
+            [1.0, 2.0, 3.0, 4.0],
+            [5.0, 6.0, 7.0, 8.0],
+            [9.0, 10.0, 11.0, 12.0],
+            [13.0, 14.0, 15.0, 16.0],
+        ];
+
+        assert_eq!(multiply(&IDENTITY, &m), m);
+        assert_eq!(multiply(&m, &IDENTITY), m);
+    }
+
+    #[test]
+    fn multiplies_column_major() {
+        let translation = [
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [1.0, 2.0, 3.0, 1.0],
+        ];
+        let scale = [
+            [2.0, 0.0, 0.0, 0.0],
+            [0.0, 2.0, 0.0, 0.0],
+            [0.0, 0.0, 2.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ];
+
+        // Scales first, then translates.
+        assert_eq!(
+            multiply(&translation, &scale),
+            [
+                [2.0, 0.0, 0.0, 0.0],
+                [0.0, 2.0, 0.0, 0.0],
+                [0.0, 0.0, 2.0, 0.0],
+                [1.0, 2.0, 3.0, 1.0],
+            ]
+        );
+        // Translates first, so the translation is scaled too.
+        assert_eq!(
+            multiply(&scale, &translation),
+            [
+                [2.0, 0.0, 0.0, 0.0],
+                [0.0, 2.0, 0.0, 0.0],
+                [0.0, 0.0, 2.0, 0.0],
+                [2.0, 4.0, 6.0, 1.0],
+            ]
+        );
+    }
+}
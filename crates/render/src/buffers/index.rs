@@ -14,8 +14,11 @@ impl<T: Pod> IndexBuffer<T> {
     ///
     /// # Examples
     ///
-    /// ```
-    /// use render::revengine_wgpu::buffers::vertex::IndexBuffer;
+    /// ```no_run
+    /// use render::buffers::index::IndexBuffer;
+    ///
+    /// # let device: wgpu::Device = unimplemented!();
+    /// const INDEX_DATA: [u32; 3] = [0, 1, 2];
     ///
     /// let result = IndexBuffer::new(&device, &INDEX_DATA, Some("Index buffer"));
     /// ```
@@ -31,6 +34,10 @@ impl<T: Pod> IndexBuffer<T> {
     pub fn len(&self) -> usize {
         self.len
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
 }
 
 impl<T: Pod> Deref for IndexBuffer<T> {
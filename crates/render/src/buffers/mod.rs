@@ -2,6 +2,7 @@
 //! Wrappers around wgpu's Buffer type
 //!
 pub mod index;
+pub mod storage;
 pub mod uniform;
 pub mod vertex;
 pub mod vertices;
@@ -24,8 +25,11 @@ impl<T: Copy + Pod> Buffer<T> {
     ///
     /// # Examples
     ///
-    /// ```
-    /// use render::revengine_wgpu::buffers::Buffer;
+    /// ```no_run
+    /// use render::buffers::Buffer;
+    ///
+    /// # let device: wgpu::Device = unimplemented!();
+    /// const VERTEX_DATA: [f32; 6] = [0.0, 0.5, -0.5, -0.5, 0.5, -0.5];
     ///
     /// let result = Buffer::new(&device, wgpu::BufferUsages::VERTEX, &VERTEX_DATA, Some("Vertex buffer"));
     /// ```
@@ -0,0 +1,335 @@
+//!
+//! Module to ease work with storage buffers
+//!
+use std::num::NonZeroU64;
+
+use crate::bind_group_builder;
+
+/// Wrapper around Buffer to store a runtime-sized array of `T`.
+///
+/// Maps to `var<storage, read>` or `var<storage, read_write>` holding an `array<T>` in WGSL.
+/// Exactly [`StorageBuffer::len`] elements are bound, so `arrayLength` in the shader reports the
+/// current length rather than the allocated capacity.
+pub struct StorageBuffer<T> {
+    buffer: wgpu::Buffer,
+    len: usize,
+    capacity: usize,
+    read_only: bool,
+    label: String,
+    pub bind_group_layout: wgpu::BindGroupLayout,
+    pub bind_group: wgpu::BindGroup,
+    phantom: std::marker::PhantomData<T>,
+}
+
+impl<T> StorageBuffer<T>
+where
+    T: bytemuck::Pod,
+{
+    /// Like [`StorageBuffer::init`], but creates a buffer that shaders can only read from.
+    pub fn read_only(
+        device: &wgpu::Device,
+        data: &[T],
+        visibility: wgpu::ShaderStages,
+        label: &str,
+    ) -> Self {
+        Self::init(device, data, visibility, true, label)
+    }
+
+    /// Like [`StorageBuffer::init`], but creates a buffer that shaders can both read and write.
+    pub fn read_write(
+        device: &wgpu::Device,
+        data: &[T],
+        visibility: wgpu::ShaderStages,
+        label: &str,
+    ) -> Self {
+        Self::init(device, data, visibility, false, label)
+    }
+
+    /// Generate storage buffer from given data.
+    ///
+    /// # Panics
+    ///
+    /// Panics if size of `T` is not a multiple of [`wgpu::COPY_BUFFER_ALIGNMENT`], which holds for
+    /// any type that has a WGSL counterpart.
+    ///
+    /// # Examples
+    ///
+    /// ```ignore
+    /// use render::buffers::storage::StorageBuffer;
+    ///
+    /// #[repr(C)]
+    /// #[derive(Copy, Clone, Pod, Zeroable)]
+    /// struct PointLight {
+    ///     position: [f32; 4],
+    ///     color: [f32; 4],
+    /// }
+    ///
+    /// let lights = StorageBuffer::init(&device, &LIGHTS, wgpu::ShaderStages::FRAGMENT, true, "Lights");
+    /// ```
+    pub fn init(
+        device: &wgpu::Device,
+        data: &[T],
+        visibility: wgpu::ShaderStages,
+        read_only: bool,
+        label: &str,
+    ) -> Self {
+        // Keeps every write of whole elements aligned.
+        assert_eq!(
+            std::mem::size_of::<T>() as wgpu::BufferAddress % wgpu::COPY_BUFFER_ALIGNMENT,
+            0,
+            "size of storage buffer element must be a multiple of {} bytes",
+            wgpu::COPY_BUFFER_ALIGNMENT
+        );
+        let capacity = data.len().max(1);
+        let buffer = Self::create_buffer(device, capacity, true, label);
+        {
+            let contents: &[u8] = bytemuck::cast_slice(data);
+            buffer.slice(..).get_mapped_range_mut()[..contents.len()].copy_from_slice(contents);
+        }
+        buffer.unmap();
+
+        let bind_group_layout = Self::create_layout(device, visibility, read_only, label);
+        let bind_group =
+            Self::create_bind_group(device, &buffer, &bind_group_layout, data.len(), label);
+
+        Self {
+            buffer,
+            len: data.len(),
+            capacity,
+            read_only,
+            label: label.to_string(),
+            bind_group_layout,
+            bind_group,
+            phantom: std::marker::PhantomData,
+        }
+    }
+
+    /// Returns number of elements visible to shaders.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if buffer holds no elements.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns number of elements buffer can hold without reallocation.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Returns `true` if shaders can only read from buffer.
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Binding resource covering the first [`StorageBuffer::len`] elements.
+    pub fn binding(&self) -> wgpu::BindingResource<'_> {
+        wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+            buffer: &self.buffer,
+            offset: 0,
+            size: Self::binding_size(self.len),
+        })
+    }
+
+    /// Write `data` into buffer on GPU, starting at element `offset`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if written range goes past [`StorageBuffer::len`].
+    pub fn copy_to_gpu(&self, queue: &wgpu::Queue, offset: usize, data: &[T]) {
+        assert!(
+            offset + data.len() <= self.len,
+            "write of {} elements at {} is out of bounds of storage buffer of length {}",
+            data.len(),
+            offset,
+            self.len
+        );
+        queue.write_buffer(
+            &self.buffer,
+            Self::byte_size(offset),
+            bytemuck::cast_slice(data),
+        );
+    }
+
+    /// Replace whole contents of buffer with `data`, growing it if needed.
+    pub fn set_data(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, data: &[T]) {
+        if data.len() > self.capacity {
+            self.reallocate(device, queue, data.len(), 0);
+        }
+        if data.len() != self.len {
+            self.len = data.len();
+            self.rebind(device);
+        }
+        if !data.is_empty() {
+            queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(data));
+        }
+    }
+
+    /// Change number of elements in buffer.
+    ///
+    /// Existing elements are preserved up to `new_len`, new elements are zeroed. Buffer is
+    /// reallocated when `new_len` exceeds [`StorageBuffer::capacity`]. Either way
+    /// [`StorageBuffer::bind_group`] is recreated, so it has to be fetched again.
+    pub fn resize(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, new_len: usize) {
+        if new_len == self.len {
+            return;
+        }
+
+        if new_len > self.capacity {
+            let capacity = new_len.max(self.capacity * 2);
+            self.reallocate(device, queue, capacity, self.len);
+        } else if new_len > self.len {
+            let zeroes = vec![T::zeroed(); new_len - self.len];
+            queue.write_buffer(
+                &self.buffer,
+                Self::byte_size(self.len),
+                bytemuck::cast_slice(&zeroes),
+            );
+        }
+
+        self.len = new_len;
+        self.rebind(device);
+    }
+
+    /// Read contents of buffer back from GPU.
+    ///
+    /// Blocks until all previously submitted work that touches the buffer is finished.
+    pub fn read_from_gpu(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> Result<Vec<T>, wgpu::BufferAsyncError> {
+        if self.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let size = Self::byte_size(self.len);
+        let aligned_size = Self::aligned_size(size);
+        let staging = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(&format!("{} readback", self.label)),
+            size: aligned_size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Storage buffer readback encoder"),
+        });
+        encoder.copy_buffer_to_buffer(&self.buffer, 0, &staging, 0, aligned_size);
+        queue.submit(Some(encoder.finish()));
+
+        let slice = staging.slice(..);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            // Receiver is alive until poll below returns.
+            let _ = sender.send(result);
+        });
+        device.poll(wgpu::Maintain::Wait);
+        receiver
+            .recv()
+            .expect("map_async callback was not called after device poll")?;
+
+        let data = bytemuck::cast_slice(&slice.get_mapped_range()[..size as usize]).to_vec();
+        staging.unmap();
+
+        Ok(data)
+    }
+
+    /// Generate layout for storage buffer.
+    pub fn create_layout(
+        device: &wgpu::Device,
+        visibility: wgpu::ShaderStages,
+        read_only: bool,
+        label: &str,
+    ) -> wgpu::BindGroupLayout {
+        bind_group_builder::LayoutBuilder::new()
+            .storage_buffer(visibility, false, read_only)
+            .build(device, Some(label))
+    }
+
+    fn create_buffer(
+        device: &wgpu::Device,
+        capacity: usize,
+        mapped_at_creation: bool,
+        label: &str,
+    ) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(label),
+            size: Self::aligned_size(Self::byte_size(capacity)),
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_DST
+                | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation,
+        })
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        buffer: &wgpu::Buffer,
+        layout: &wgpu::BindGroupLayout,
+        len: usize,
+        label: &str,
+    ) -> wgpu::BindGroup {
+        bind_group_builder::Builder::new()
+            .buffer_bytes(buffer, 0, Self::binding_size(len))
+            .build(device, layout, Some(label))
+    }
+
+    // Moves first `keep` elements into a new buffer of given capacity.
+    fn reallocate(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        capacity: usize,
+        keep: usize,
+    ) {
+        let buffer = Self::create_buffer(device, capacity, false, &self.label);
+
+        if keep > 0 {
+            let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Storage buffer resize encoder"),
+            });
+            let size = Self::aligned_size(Self::byte_size(keep));
+            encoder.copy_buffer_to_buffer(&self.buffer, 0, &buffer, 0, size);
+            queue.submit(Some(encoder.finish()));
+        }
+
+        self.buffer = buffer;
+        self.capacity = capacity;
+    }
+
+    fn rebind(&mut self, device: &wgpu::Device) {
+        self.bind_group = Self::create_bind_group(
+            device,
+            &self.buffer,
+            &self.bind_group_layout,
+            self.len,
+            &self.label,
+        );
+    }
+
+    fn byte_size(len: usize) -> wgpu::BufferAddress {
+        (len * std::mem::size_of::<T>()) as wgpu::BufferAddress
+    }
+
+    // Copies and buffer sizes must be multiple of `wgpu::COPY_BUFFER_ALIGNMENT`.
+    fn aligned_size(size: wgpu::BufferAddress) -> wgpu::BufferAddress {
+        let align = wgpu::COPY_BUFFER_ALIGNMENT;
+        size.div_ceil(align) * align
+    }
+
+    // Empty bindings are invalid, so empty buffer still exposes one (zeroed) element.
+    fn binding_size(len: usize) -> Option<wgpu::BufferSize> {
+        NonZeroU64::new(Self::byte_size(len.max(1)))
+    }
+}
+
+impl<T> std::ops::Deref for StorageBuffer<T> {
+    type Target = wgpu::Buffer;
+
+    fn deref(&self) -> &Self::Target {
+        &self.buffer
+    }
+}
@@ -1,14 +1,22 @@
 //!
 //! Module to ease work with uniforms
 //!
+use std::sync::Arc;
+
 use crate::bind_group_builder;
+use crate::bind_group_cache::BindGroupCache;
+use crate::buffers::storage::StorageBuffer;
+use crate::layout::{self, WgslType};
+use crate::texture::Texture;
 use wgpu::util::DeviceExt;
 
+pub use render_derive::AsBindGroup;
+
 // TODO: this is rarely used
 /// Wrapper around Buffer to store Uniform.
 pub struct UniformBuffer<T> {
     buffer: wgpu::Buffer,
-    pub bind_group_layout: wgpu::BindGroupLayout,
+    pub bind_group_layout: Arc<wgpu::BindGroupLayout>,
     pub bind_group: wgpu::BindGroup,
     phantom: std::marker::PhantomData<T>,
 }
@@ -34,9 +42,11 @@ where
     ///
     /// # Examples
     ///
-    /// ```
-    /// use render::revengine_wgpu::buffers::uniform::UniformBuffer;
+    /// ```no_run
+    /// use bytemuck::{Pod, Zeroable};
+    /// use render::buffers::uniform::UniformBuffer;
     ///
+    /// # let device: wgpu::Device = unimplemented!();
     /// #[repr(C)]
     /// #[derive(Copy, Clone, Pod, Zeroable)]
     /// struct Example {
@@ -53,9 +63,31 @@ where
         visibility: wgpu::ShaderStages,
         label: &str,
     ) -> Self {
-        let buffer = Self::create_buffer(device, &initial_data, label);
+        let bind_group_layout = Arc::new(Self::create_layout(device, visibility, label));
+        Self::with_layout(device, initial_data, bind_group_layout, label)
+    }
 
-        let bind_group_layout = Self::create_layout(device, visibility, label);
+    /// Like [`UniformBuffer::init`], but shares the layout with other uniforms of the same
+    /// visibility through `cache`.
+    pub fn init_cached(
+        device: &wgpu::Device,
+        cache: &mut BindGroupCache,
+        initial_data: T,
+        visibility: wgpu::ShaderStages,
+        label: &str,
+    ) -> Self {
+        let entries = bind_group_builder::LayoutBuilder::new().uniform_buffer(visibility, false);
+        let bind_group_layout = cache.layout(device, entries.entries(), Some(label));
+        Self::with_layout(device, initial_data, bind_group_layout, label)
+    }
+
+    fn with_layout(
+        device: &wgpu::Device,
+        initial_data: T,
+        bind_group_layout: Arc<wgpu::BindGroupLayout>,
+        label: &str,
+    ) -> Self {
+        let buffer = Self::create_buffer(device, &initial_data, label);
 
         let bind_group = bind_group_builder::Builder::new()
             .buffer::<T>(&buffer, 0..1)
@@ -96,7 +128,144 @@ where
     }
 }
 
+/// Bind group created by [`AsBindGroup::prepare_bind_group`], with the uniform buffers it owns.
+#[derive(Debug)]
+pub struct PreparedBindGroup {
+    pub bind_group: wgpu::BindGroup,
+    /// Uniform buffers of the bind group.
+    pub uniforms: Vec<PreparedUniform>,
+}
+
+/// Uniform buffer of a [`PreparedBindGroup`].
+#[derive(Debug)]
+pub struct PreparedUniform {
+    pub binding: u32,
+    pub buffer: wgpu::Buffer,
+    /// [`WgslType::SIZE`] of the field the buffer holds.
+    pub size: u64,
+}
+
+impl PreparedBindGroup {
+    /// Uniform buffer at `binding`.
+    pub fn uniform(&self, binding: u32) -> Option<&wgpu::Buffer> {
+        self.prepared_uniform(binding)
+            .map(|uniform| &uniform.buffer)
+    }
+
+    /// Write `value` into the uniform buffer at `binding` through `queue`.
+    ///
+    /// # Panics
+    ///
+    /// If there is no uniform at `binding`, or its field is of a type with another size than `T`.
+    pub fn write<T: WgslType>(&self, queue: &wgpu::Queue, binding: u32, value: &T) {
+        let uniform = self
+            .prepared_uniform(binding)
+            .unwrap_or_else(|| panic!("no uniform buffer at binding {}", binding));
+        assert_eq!(
+            uniform.size,
+            T::SIZE,
+            "uniform at binding {} is not a `{}`",
+            binding,
+            T::WGSL_NAME
+        );
+        queue.write_buffer(&uniform.buffer, 0, &layout::to_bytes(value));
+    }
+
+    fn prepared_uniform(&self, binding: u32) -> Option<&PreparedUniform> {
+        self.uniforms
+            .iter()
+            .find(|uniform| uniform.binding == binding)
+    }
+}
+
+/// Types that know how to bind themselves to a shader.
+///
+/// Can be derived, see [`render_derive::AsBindGroup`].
 pub trait AsBindGroup {
-    fn bind_group(&self, device: &wgpu::Device, layout: &wgpu::BindGroupLayout) -> wgpu::BindGroup;
-    fn bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout;
+    /// Create the bind group, keeping its uniform buffers so they can be updated later.
+    fn prepare_bind_group(
+        &self,
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+    ) -> PreparedBindGroup;
+
+    /// Write uniform fields of `self` into the buffers of `prepared`, e.g. after animating them.
+    ///
+    /// Does nothing by default, for types without uniform fields.
+    fn write_uniforms(&self, _queue: &wgpu::Queue, _prepared: &PreparedBindGroup) {}
+
+    fn bind_group(&self, device: &wgpu::Device, layout: &wgpu::BindGroupLayout) -> wgpu::BindGroup {
+        self.prepare_bind_group(device, layout).bind_group
+    }
+
+    /// Layout created from [`AsBindGroup::layout_entries`].
+    fn bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some(std::any::type_name::<Self>()),
+            entries: &Self::layout_entries(),
+        })
+    }
+
+    /// Entries of the layout the bind group is created for.
+    fn layout_entries() -> Vec<wgpu::BindGroupLayoutEntry>;
+
+    /// Layout shared by all values of this type created through the same `cache`.
+    fn cached_bind_group_layout(
+        device: &wgpu::Device,
+        cache: &mut BindGroupCache,
+    ) -> Arc<wgpu::BindGroupLayout> {
+        let label = std::any::type_name::<Self>();
+        cache.layout(device, &Self::layout_entries(), Some(label))
+    }
+}
+
+/// Types that can be bound as `#[texture(..)]` by [`AsBindGroup`] derive.
+pub trait AsTextureView {
+    fn texture_view(&self) -> &wgpu::TextureView;
+}
+
+/// Types that can be bound as `#[sampler(..)]` by [`AsBindGroup`] derive.
+pub trait AsSampler {
+    fn sampler(&self) -> &wgpu::Sampler;
+}
+
+/// Types that can be bound as `#[storage(..)]` by [`AsBindGroup`] derive.
+pub trait AsStorageBinding {
+    fn storage_binding(&self) -> wgpu::BindingResource<'_>;
+}
+
+impl AsTextureView for wgpu::TextureView {
+    fn texture_view(&self) -> &wgpu::TextureView {
+        self
+    }
+}
+
+impl AsTextureView for Texture {
+    fn texture_view(&self) -> &wgpu::TextureView {
+        &self.view
+    }
+}
+
+impl AsSampler for wgpu::Sampler {
+    fn sampler(&self) -> &wgpu::Sampler {
+        self
+    }
+}
+
+impl AsSampler for Texture {
+    fn sampler(&self) -> &wgpu::Sampler {
+        &self.sampler
+    }
+}
+
+impl AsStorageBinding for wgpu::Buffer {
+    fn storage_binding(&self) -> wgpu::BindingResource<'_> {
+        self.as_entire_binding()
+    }
+}
+
+impl<T: bytemuck::Pod> AsStorageBinding for StorageBuffer<T> {
+    fn storage_binding(&self) -> wgpu::BindingResource<'_> {
+        self.binding()
+    }
 }
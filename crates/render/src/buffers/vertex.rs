@@ -15,8 +15,16 @@ impl<T: Vertex + Pod> VertexBuffer<T> {
     ///
     /// # Examples
     ///
-    /// ```
-    /// use render::revengine_wgpu::buffers::vertex::VertexBuffer;
+    /// ```no_run
+    /// use render::buffers::vertex::VertexBuffer;
+    /// use render::mesh::MeshVertex;
+    ///
+    /// # let device: wgpu::Device = unimplemented!();
+    /// const VERTEX_DATA: [MeshVertex; 1] = [MeshVertex {
+    ///     position: [0.0; 3],
+    ///     texcoords: [0.0; 2],
+    ///     normal: [0.0, 0.0, 1.0],
+    /// }];
     ///
     /// let result = VertexBuffer::new(&device, &VERTEX_DATA, Some("Vertex buffer"));
     /// ```
@@ -32,6 +40,10 @@ impl<T: Vertex + Pod> VertexBuffer<T> {
     pub fn len(&self) -> usize {
         self.len
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
 }
 
 impl<T: Vertex + Pod> Deref for VertexBuffer<T> {
@@ -1,7 +1,62 @@
 //! Vertex objects is cool
 
+pub use render_derive::Vertex;
+
 /// Trait to describe Vertex-like object.
+///
+/// Can be derived, see [`render_derive::Vertex`].
 pub trait Vertex {
     /// Vertex-like object need to specify its alyout.
     fn desc<'a>() -> wgpu::VertexBufferLayout<'a>;
 }
+
+/// Types that can be stored in a field of [`Vertex`].
+pub trait VertexField {
+    /// Formats of attributes the field is split into, each one takes its own shader location.
+    const FORMATS: &'static [wgpu::VertexFormat];
+}
+
+macro_rules! impl_vertex_field {
+    ($($ty:ty => [$($format:ident),*]),* $(,)?) => {
+        $(
+            impl VertexField for $ty {
+                const FORMATS: &'static [wgpu::VertexFormat] = &[$(wgpu::VertexFormat::$format),*];
+            }
+        )*
+    };
+}
+
+impl_vertex_field!(
+    f32 => [Float32],
+    [f32; 1] => [Float32],
+    [f32; 2] => [Float32x2],
+    [f32; 3] => [Float32x3],
+    [f32; 4] => [Float32x4],
+    f64 => [Float64],
+    [f64; 1] => [Float64],
+    [f64; 2] => [Float64x2],
+    [f64; 3] => [Float64x3],
+    [f64; 4] => [Float64x4],
+    u32 => [Uint32],
+    [u32; 1] => [Uint32],
+    [u32; 2] => [Uint32x2],
+    [u32; 3] => [Uint32x3],
+    [u32; 4] => [Uint32x4],
+    i32 => [Sint32],
+    [i32; 1] => [Sint32],
+    [i32; 2] => [Sint32x2],
+    [i32; 3] => [Sint32x3],
+    [i32; 4] => [Sint32x4],
+    [u16; 2] => [Uint16x2],
+    [u16; 4] => [Uint16x4],
+    [i16; 2] => [Sint16x2],
+    [i16; 4] => [Sint16x4],
+    [u8; 2] => [Uint8x2],
+    [u8; 4] => [Uint8x4],
+    [i8; 2] => [Sint8x2],
+    [i8; 4] => [Sint8x4],
+    // Matrices take one location per column.
+    [[f32; 2]; 2] => [Float32x2, Float32x2],
+    [[f32; 3]; 3] => [Float32x3, Float32x3, Float32x3],
+    [[f32; 4]; 4] => [Float32x4, Float32x4, Float32x4, Float32x4],
+);
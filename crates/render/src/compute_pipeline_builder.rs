@@ -0,0 +1,158 @@
+//!
+//! Builders to create compute pipelines and dispatch them.
+//!
+use crate::render_pipleine_builder::{
+    reflected_layout, shared_layout, IntoPipelineLayoutDescriptor, Layout,
+};
+use crate::shader::ShaderRef;
+use crate::shader_reflection::ShaderReflection;
+
+/// Compute pipeline builder, the compute counterpart of
+/// [`RenderPipelineBuilder`](crate::render_pipleine_builder::RenderPipelineBuilder).
+///
+/// Examples
+/// ```ignore
+/// let pipeline = ComputePipelineBuilder::from_layout(&pipeline_layout, &c_shader)
+///            .entry_point("cull")
+///            .build(device, Some("Culling pipeline"));
+/// ```
+#[derive(Debug)]
+pub struct ComputePipelineBuilder<'a> {
+    layout: Layout<'a>,
+    cs_mod: ShaderRef<'a>,
+    entry_point: &'a str,
+}
+
+impl<'a> ComputePipelineBuilder<'a> {
+    // Defaults
+    pub const DEFAULT_ENTRY_POINT: &'static str = "compute";
+
+    // Constructors
+
+    /// Begin building the compute pipeline for the given pipeline layout and the compute shader
+    /// module.
+    pub fn from_layout(layout: &'a wgpu::PipelineLayout, cs_mod: impl Into<ShaderRef<'a>>) -> Self {
+        let layout = Layout::Created(layout);
+        Self::new_inner(layout, cs_mod.into())
+    }
+
+    /// Begin building the compute pipeline for a pipeline with the given layout descriptor and
+    /// the compute shader module.
+    pub fn from_layout_descriptor<T>(layout_desc: T, cs_mod: impl Into<ShaderRef<'a>>) -> Self
+    where
+        T: IntoPipelineLayoutDescriptor<'a>,
+    {
+        let desc = layout_desc.into_pipeline_layout_descriptor();
+        let layout = Layout::Descriptor(desc);
+        Self::new_inner(layout, cs_mod.into())
+    }
+
+    /// Begin building the compute pipeline for the compute shader module, with the layout derived
+    /// from its reflection, see [`ShaderReflection`].
+    pub fn from_reflection(cs_mod: impl Into<ShaderRef<'a>>) -> Self {
+        Self::new_inner(Layout::Reflected, cs_mod.into())
+    }
+
+    // Shared between constructors.
+    fn new_inner(layout: Layout<'a>, cs_mod: ShaderRef<'a>) -> Self {
+        ComputePipelineBuilder {
+            layout,
+            cs_mod,
+            entry_point: Self::DEFAULT_ENTRY_POINT,
+        }
+    }
+
+    // Builders
+
+    /// Name of the function in the shader module to run, `compute` by default.
+    pub fn entry_point(mut self, entry_point: &'a str) -> Self {
+        self.entry_point = entry_point;
+        self
+    }
+
+    // Finalising methods.
+
+    /// Build the pipeline layout, if only a descriptor was given, and the compute pipeline.
+    ///
+    /// **Panic!**s if a [`Shader`](crate::shader::Shader) not created for compute stage is used,
+    /// or if the layout should be reflected from a shader that fails to or has no source.
+    pub fn build(self, device: &wgpu::Device, label: Option<&str>) -> wgpu::ComputePipeline {
+        assert!(
+            self.cs_mod.supports(wgpu::ShaderStages::COMPUTE),
+            "compute shader was not created for compute stage"
+        );
+        let layout = match self.layout {
+            Layout::Descriptor(ref desc) => &device.create_pipeline_layout(desc),
+            Layout::Created(layout) => layout,
+            Layout::Shared(ref layout) => layout,
+            Layout::SharedBindGroups(ref bind_group_layouts) => {
+                &shared_layout(device, bind_group_layouts, label)
+            }
+            Layout::Reflected => {
+                let source = self
+                    .cs_mod
+                    .source
+                    .expect("compute shader has no source to reflect");
+                let reflection = ShaderReflection::from_wgsl(source)
+                    .unwrap_or_else(|err| panic!("failed to reflect compute shader: {}", err));
+                &reflected_layout(device, &reflection, label)
+            }
+        };
+        device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label,
+            layout: Some(layout),
+            module: self.cs_mod.module,
+            entry_point: self.entry_point,
+        })
+    }
+}
+
+/// Number of workgroups of `workgroup_size` invocations needed to cover `size` items.
+///
+/// # Panics
+///
+/// Panics if `workgroup_size` is zero.
+pub const fn workgroup_count(size: u32, workgroup_size: u32) -> u32 {
+    size.div_ceil(workgroup_size)
+}
+
+/// Like [`workgroup_count`], for every dimension of a 3D problem.
+pub const fn workgroup_counts(size: [u32; 3], workgroup_size: [u32; 3]) -> [u32; 3] {
+    [
+        workgroup_count(size[0], workgroup_size[0]),
+        workgroup_count(size[1], workgroup_size[1]),
+        workgroup_count(size[2], workgroup_size[2]),
+    ]
+}
+
+/// Dispatch enough workgroups of `workgroup_size`, which must match `@workgroup_size` of the
+/// shader, to cover `size` items.
+///
+/// Shader still has to skip invocations past `size`, since the last workgroup may be partial.
+pub fn dispatch(pass: &mut wgpu::ComputePass, size: [u32; 3], workgroup_size: [u32; 3]) {
+    let [x, y, z] = workgroup_counts(size, workgroup_size);
+    if x == 0 || y == 0 || z == 0 {
+        return;
+    }
+    pass.dispatch_workgroups(x, y, z);
+}
+
+/// Like [`dispatch`], for a 1D problem of `size` items.
+pub fn dispatch_linear(pass: &mut wgpu::ComputePass, size: u32, workgroup_size: u32) {
+    dispatch(pass, [size, 1, 1], [workgroup_size, 1, 1]);
+}
+
+/// Begin a compute pass, set `pipeline` and bind `bind_groups` to consecutive groups from 0.
+pub fn begin<'a>(
+    encoder: &'a mut wgpu::CommandEncoder,
+    pipeline: &'a wgpu::ComputePipeline,
+    bind_groups: &[&'a wgpu::BindGroup],
+    label: Option<&str>,
+) -> wgpu::ComputePass<'a> {
+    let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label });
+    pass.set_pipeline(pipeline);
+    for (i, bind_group) in bind_groups.iter().enumerate() {
+        pass.set_bind_group(i as u32, bind_group, &[]);
+    }
+    pass
+}
@@ -0,0 +1,561 @@
+//!
+//! WGSL memory layout of types shared between host and shaders
+//!
+//! Rust and WGSL disagree on how `vec3<f32>`, matrices and nested structs are laid out, so
+//! `#[repr(C)]` structs uploaded with `bytemuck` silently break as soon as padding is involved.
+//! [`WgslType`] describes the layout WGSL expects, and `#[derive(WgslType)]` computes it for
+//! user structs, inserting padding when writing and emitting the matching WGSL declaration.
+//!
+//! # Examples
+//!
+//! ```
+//! use render::layout::{self, WgslType};
+//!
+//! #[derive(WgslType)]
+//! #[wgsl(uniform)]
+//! struct Light {
+//!     position: [f32; 3],
+//!     intensity: f32,
+//!     color: [f32; 3],
+//! }
+//!
+//! assert_eq!(Light::SIZE, 32);
+//! assert_eq!(Light::MEMBERS[2].offset, 16);
+//!
+//! let light = Light { position: [0.0; 3], intensity: 1.0, color: [1.0; 3] };
+//! assert_eq!(layout::to_bytes(&light).len(), 32);
+//!
+//! assert_eq!(
+//!     layout::wgsl_declaration::<Light>(),
+//!     "struct Light {\n    position: vec3<f32>,\n    intensity: f32,\n    color: vec3<f32>,\n};\n",
+//! );
+//! ```
+use std::fmt;
+
+pub use render_derive::WgslType;
+
+/// Placement of a struct member in WGSL memory layout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WgslMember {
+    /// Name of the member in WGSL.
+    pub name: &'static str,
+    /// Offset from the beginning of the struct in bytes.
+    pub offset: u64,
+    /// Size of the member in bytes, including `@size` padding.
+    pub size: u64,
+}
+
+/// Types that can be shared with WGSL shaders through uniform and storage buffers.
+///
+/// Usually derived, see [module level documentation](self).
+pub trait WgslType {
+    /// Name of the type in WGSL source.
+    const WGSL_NAME: &'static str;
+    /// Alignment of the type in bytes (`AlignOf` in WGSL specification).
+    const ALIGN: u64;
+    /// Size of the type in bytes (`SizeOf` in WGSL specification).
+    const SIZE: u64;
+    /// Members of the type, empty unless type is a struct.
+    const MEMBERS: &'static [WgslMember] = &[];
+    /// Whether type satisfies extra layout constraints of the uniform address space.
+    const UNIFORM_COMPATIBLE: bool = true;
+
+    /// Write value into `bytes` using WGSL layout.
+    ///
+    /// `bytes` is at least [`WgslType::SIZE`] long. Padding is left untouched.
+    fn write_bytes(&self, bytes: &mut [u8]);
+
+    /// Append WGSL declarations this type needs, dependencies first.
+    fn wgsl_declarations(_declarations: &mut Vec<String>) {}
+}
+
+/// Round `n` up to a multiple of `align`.
+pub const fn round_up(align: u64, n: u64) -> u64 {
+    n.div_ceil(align) * align
+}
+
+/// Larger of two values, usable in constants.
+pub const fn max(a: u64, b: u64) -> u64 {
+    if a > b {
+        a
+    } else {
+        b
+    }
+}
+
+/// Distance between elements of `array<T>`.
+pub const fn array_stride<T: WgslType>() -> u64 {
+    round_up(T::ALIGN, T::SIZE)
+}
+
+/// Serialize value into bytes laid out the way WGSL expects, padding is zeroed.
+pub fn to_bytes<T: WgslType>(value: &T) -> Vec<u8> {
+    let mut bytes = vec![0; T::SIZE as usize];
+    value.write_bytes(&mut bytes);
+    bytes
+}
+
+/// Serialize slice into bytes of WGSL `array<T>`, padding is zeroed.
+pub fn slice_to_bytes<T: WgslType>(values: &[T]) -> Vec<u8> {
+    let stride = array_stride::<T>() as usize;
+    let mut bytes = vec![0; stride * values.len()];
+    for (value, chunk) in values.iter().zip(bytes.chunks_mut(stride)) {
+        value.write_bytes(chunk);
+    }
+    bytes
+}
+
+/// WGSL declarations of `T` and all structs it depends on.
+pub fn wgsl_declaration<T: WgslType>() -> String {
+    let mut declarations = Vec::new();
+    T::wgsl_declarations(&mut declarations);
+    declarations.concat()
+}
+
+/// Check that struct with the same name in WGSL `source` has the layout of `T`.
+///
+/// Meant to be called from tests, so a Rust struct and the shader it is used with cannot drift
+/// apart unnoticed. Only members of `T` itself are compared, check nested structs separately.
+pub fn check_wgsl<T: WgslType>(source: &str) -> Result<(), LayoutError> {
+    let module = naga::front::wgsl::parse_str(source)
+        .map_err(|err| LayoutError::Parse(err.emit_to_string(source)))?;
+
+    let (members, span) = module
+        .types
+        .iter()
+        .find_map(|(_, ty)| match (&ty.name, &ty.inner) {
+            (Some(name), naga::TypeInner::Struct { members, span }) if name == T::WGSL_NAME => {
+                Some((members, *span))
+            }
+            _ => None,
+        })
+        .ok_or_else(|| LayoutError::StructNotFound(T::WGSL_NAME.to_string()))?;
+
+    if members.len() != T::MEMBERS.len() {
+        return Err(LayoutError::MemberCount {
+            name: T::WGSL_NAME.to_string(),
+            expected: T::MEMBERS.len(),
+            found: members.len(),
+        });
+    }
+
+    for (expected, found) in T::MEMBERS.iter().zip(members) {
+        let found_name = found.name.clone().unwrap_or_default();
+        if found_name != expected.name || u64::from(found.offset) != expected.offset {
+            return Err(LayoutError::MemberMismatch {
+                name: T::WGSL_NAME.to_string(),
+                expected: *expected,
+                found_name,
+                found_offset: found.offset.into(),
+            });
+        }
+    }
+
+    if u64::from(span) != T::SIZE {
+        return Err(LayoutError::SizeMismatch {
+            name: T::WGSL_NAME.to_string(),
+            expected: T::SIZE,
+            found: span.into(),
+        });
+    }
+
+    Ok(())
+}
+
+/// Reasons for Rust type not matching its WGSL counterpart.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LayoutError {
+    /// WGSL source failed to parse, contains formatted parser error.
+    Parse(String),
+    /// No struct with given name in WGSL source.
+    StructNotFound(String),
+    /// Struct has different number of members.
+    MemberCount {
+        name: String,
+        expected: usize,
+        found: usize,
+    },
+    /// Member has different name or offset.
+    MemberMismatch {
+        name: String,
+        expected: WgslMember,
+        found_name: String,
+        found_offset: u64,
+    },
+    /// Struct has different size.
+    SizeMismatch {
+        name: String,
+        expected: u64,
+        found: u64,
+    },
+}
+
+impl fmt::Display for LayoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Parse(err) => write!(f, "failed to parse WGSL: {}", err),
+            Self::StructNotFound(name) => write!(f, "struct `{}` not found in WGSL", name),
+            Self::MemberCount {
+                name,
+                expected,
+                found,
+            } => write!(
+                f,
+                "struct `{}` has {} members in Rust and {} in WGSL",
+                name, expected, found
+            ),
+            Self::MemberMismatch {
+                name,
+                expected,
+                found_name,
+                found_offset,
+            } => write!(
+                f,
+                "struct `{}` has `{}` at offset {} in Rust and `{}` at offset {} in WGSL",
+                name, expected.name, expected.offset, found_name, found_offset
+            ),
+            Self::SizeMismatch {
+                name,
+                expected,
+                found,
+            } => write!(
+                f,
+                "struct `{}` is {} bytes in Rust and {} bytes in WGSL",
+                name, expected, found
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LayoutError {}
+
+macro_rules! impl_scalar {
+    ($($ty:ty => $name:literal),*) => {
+        $(
+            impl WgslType for $ty {
+                const WGSL_NAME: &'static str = $name;
+                const ALIGN: u64 = 4;
+                const SIZE: u64 = 4;
+
+                fn write_bytes(&self, bytes: &mut [u8]) {
+                    bytes[..4].copy_from_slice(bytemuck::bytes_of(self));
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! impl_vector {
+    ($($ty:ty => $name:literal, $align:literal),*) => {
+        $(
+            impl WgslType for $ty {
+                const WGSL_NAME: &'static str = $name;
+                const ALIGN: u64 = $align;
+                const SIZE: u64 = std::mem::size_of::<$ty>() as u64;
+
+                fn write_bytes(&self, bytes: &mut [u8]) {
+                    bytes[..Self::SIZE as usize].copy_from_slice(bytemuck::cast_slice(self));
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! impl_matrix {
+    ($($columns:literal x $rows:literal => $name:literal),*) => {
+        $(
+            impl WgslType for [[f32; $rows]; $columns] {
+                const WGSL_NAME: &'static str = $name;
+                const ALIGN: u64 = <[f32; $rows] as WgslType>::ALIGN;
+                const SIZE: u64 = $columns * array_stride::<[f32; $rows]>();
+
+                fn write_bytes(&self, bytes: &mut [u8]) {
+                    let stride = array_stride::<[f32; $rows]>() as usize;
+                    for (column, chunk) in self.iter().zip(bytes.chunks_mut(stride)) {
+                        column.write_bytes(chunk);
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_scalar!(f32 => "f32", i32 => "i32", u32 => "u32");
+
+impl_vector!(
+    [f32; 2] => "vec2<f32>", 8,
+    [f32; 3] => "vec3<f32>", 16,
+    [f32; 4] => "vec4<f32>", 16,
+    [i32; 2] => "vec2<i32>", 8,
+    [i32; 3] => "vec3<i32>", 16,
+    [i32; 4] => "vec4<i32>", 16,
+    [u32; 2] => "vec2<u32>", 8,
+    [u32; 3] => "vec3<u32>", 16,
+    [u32; 4] => "vec4<u32>", 16
+);
+
+impl_matrix!(
+    2 x 2 => "mat2x2<f32>",
+    2 x 3 => "mat2x3<f32>",
+    2 x 4 => "mat2x4<f32>",
+    3 x 2 => "mat3x2<f32>",
+    3 x 3 => "mat3x3<f32>",
+    3 x 4 => "mat3x4<f32>",
+    4 x 2 => "mat4x2<f32>",
+    4 x 3 => "mat4x3<f32>",
+    4 x 4 => "mat4x4<f32>"
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(WgslType)]
+    struct Packed {
+        normal: [f32; 3],
+        strength: f32,
+    }
+
+    #[derive(WgslType)]
+    struct Padded {
+        scale: f32,
+        direction: [f32; 3],
+        count: u32,
+    }
+
+    #[derive(WgslType)]
+    struct Attributes {
+        #[wgsl(align = 16)]
+        a: f32,
+        #[wgsl(size = 32)]
+        b: f32,
+        c: [f32; 2],
+    }
+
+    #[derive(WgslType)]
+    struct Arrays {
+        #[wgsl(array)]
+        weights: [f32; 3],
+        #[wgsl(array)]
+        points: [[f32; 3]; 2],
+    }
+
+    #[derive(WgslType)]
+    struct Colors {
+        #[wgsl(array)]
+        colors: [[f32; 4]; 2],
+        intensity: f32,
+    }
+
+    #[derive(WgslType)]
+    struct Transform {
+        normal: [[f32; 3]; 3],
+        scale: f32,
+    }
+
+    #[derive(WgslType)]
+    struct Inner {
+        value: f32,
+    }
+
+    #[derive(WgslType)]
+    struct Outer {
+        flag: u32,
+        inner: Inner,
+        tail: f32,
+    }
+
+    #[derive(WgslType)]
+    struct Light {
+        color: [f32; 3],
+        intensity: f32,
+    }
+
+    #[derive(WgslType)]
+    #[wgsl(name = "Scene")]
+    struct SceneUniform {
+        ambient: [f32; 4],
+        sun: Light,
+        exposure: f32,
+    }
+
+    #[derive(WgslType)]
+    struct Gap {
+        ambient: [f32; 4],
+        inner: Inner,
+        tail: f32,
+    }
+
+    #[derive(WgslType)]
+    struct Twice {
+        a: Inner,
+        b: Inner,
+    }
+
+    fn offsets<T: WgslType>() -> Vec<u64> {
+        T::MEMBERS.iter().map(|member| member.offset).collect()
+    }
+
+    fn floats(bytes: &[u8]) -> Vec<f32> {
+        bytemuck::pod_collect_to_vec(bytes)
+    }
+
+    #[test]
+    fn packs_scalar_after_vec3() {
+        assert_eq!(offsets::<Packed>(), [0, 12]);
+        assert_eq!(Packed::ALIGN, 16);
+        assert_eq!(Packed::SIZE, 16);
+    }
+
+    #[test]
+    fn pads_members_to_alignment() {
+        assert_eq!(offsets::<Padded>(), [0, 16, 28]);
+        assert_eq!(Padded::SIZE, 32);
+
+        let padded = Padded {
+            scale: 2.0,
+            direction: [1.0; 3],
+            count: 3,
+        };
+        let bytes = to_bytes(&padded);
+        assert_eq!(floats(&bytes[..16]), [2.0, 0.0, 0.0, 0.0]);
+        assert_eq!(floats(&bytes[16..28]), [1.0; 3]);
+        assert_eq!(bytemuck::pod_read_unaligned::<u32>(&bytes[28..]), 3);
+    }
+
+    #[test]
+    fn applies_size_and_align_attributes() {
+        assert_eq!(offsets::<Attributes>(), [0, 4, 40]);
+        assert_eq!(Attributes::MEMBERS[1].size, 32);
+        assert_eq!(Attributes::ALIGN, 16);
+        assert_eq!(Attributes::SIZE, 48);
+        assert_eq!(
+            wgsl_declaration::<Attributes>(),
+            "struct Attributes {\n    @align(16) a: f32,\n    @size(32) b: f32,\n    c: vec2<f32>,\n};\n"
+        );
+    }
+
+    #[test]
+    fn strides_arrays() {
+        assert_eq!(array_stride::<f32>(), 4);
+        assert_eq!(array_stride::<[f32; 3]>(), 16);
+        assert_eq!(offsets::<Arrays>(), [0, 16]);
+        assert_eq!(Arrays::MEMBERS[1].size, 32);
+        assert_eq!(Arrays::SIZE, 48);
+        assert!(wgsl_declaration::<Arrays>().contains("    points: array<vec3<f32>, 2>,\n"));
+
+        let arrays = Arrays {
+            weights: [1.0, 2.0, 3.0],
+            points: [[4.0; 3], [5.0; 3]],
+        };
+        assert_eq!(
+            floats(&to_bytes(&arrays)),
+            [1.0, 2.0, 3.0, 0.0, 4.0, 4.0, 4.0, 0.0, 5.0, 5.0, 5.0, 0.0]
+        );
+        assert_eq!(
+            floats(&slice_to_bytes(&[[1.0f32; 3], [2.0; 3]])),
+            [1.0, 1.0, 1.0, 0.0, 2.0, 2.0, 2.0, 0.0]
+        );
+    }
+
+    #[test]
+    fn lays_out_matrices() {
+        assert_eq!(<[[f32; 2]; 2]>::ALIGN, 8);
+        assert_eq!(<[[f32; 2]; 2]>::SIZE, 16);
+        assert_eq!(<[[f32; 2]; 3]>::SIZE, 24);
+        assert_eq!(<[[f32; 3]; 3]>::ALIGN, 16);
+        assert_eq!(<[[f32; 3]; 3]>::SIZE, 48);
+        assert_eq!(<[[f32; 4]; 4]>::SIZE, 64);
+        assert_eq!(offsets::<Transform>(), [0, 48]);
+
+        let matrix = [[1.0, 2.0, 3.0], [4.0, 5.0, 6.0], [7.0, 8.0, 9.0]];
+        assert_eq!(
+            floats(&to_bytes(&matrix)),
+            [1.0, 2.0, 3.0, 0.0, 4.0, 5.0, 6.0, 0.0, 7.0, 8.0, 9.0, 0.0]
+        );
+    }
+
+    #[test]
+    fn nests_structs() {
+        assert_eq!(offsets::<Outer>(), [0, 4, 8]);
+        assert_eq!(Outer::SIZE, 12);
+        assert_eq!(offsets::<SceneUniform>(), [0, 16, 32]);
+        assert_eq!(SceneUniform::SIZE, 48);
+
+        let declaration = wgsl_declaration::<SceneUniform>();
+        assert!(declaration.starts_with("struct Light {"));
+        assert!(declaration.contains("struct Scene {\n    ambient: vec4<f32>,\n    sun: Light,\n"));
+        assert_eq!(
+            wgsl_declaration::<Twice>().matches("struct Inner").count(),
+            1
+        );
+    }
+
+    #[test]
+    fn checks_uniform_rules() {
+        let compatible = [
+            Packed::UNIFORM_COMPATIBLE,
+            Colors::UNIFORM_COMPATIBLE,
+            SceneUniform::UNIFORM_COMPATIBLE,
+            // Array stride of 4 bytes.
+            Arrays::UNIFORM_COMPATIBLE,
+            // Nested struct at offset 4.
+            Outer::UNIFORM_COMPATIBLE,
+            // Member follows nested struct within 16 bytes.
+            Gap::UNIFORM_COMPATIBLE,
+        ];
+        assert_eq!(compatible, [true, true, true, false, false, false]);
+    }
+
+    #[test]
+    fn matches_own_declaration() {
+        check_wgsl::<Packed>(&wgsl_declaration::<Packed>()).unwrap();
+        check_wgsl::<Padded>(&wgsl_declaration::<Padded>()).unwrap();
+        check_wgsl::<Attributes>(&wgsl_declaration::<Attributes>()).unwrap();
+        check_wgsl::<Arrays>(&wgsl_declaration::<Arrays>()).unwrap();
+        check_wgsl::<Colors>(&wgsl_declaration::<Colors>()).unwrap();
+        check_wgsl::<Transform>(&wgsl_declaration::<Transform>()).unwrap();
+        check_wgsl::<Outer>(&wgsl_declaration::<Outer>()).unwrap();
+        check_wgsl::<SceneUniform>(&wgsl_declaration::<SceneUniform>()).unwrap();
+    }
+
+    #[test]
+    fn reports_mismatches() {
+        assert!(matches!(
+            check_wgsl::<Packed>("struct Other { a: f32, };"),
+            Err(LayoutError::StructNotFound(name)) if name == "Packed"
+        ));
+        assert!(matches!(
+            check_wgsl::<Packed>("struct Packed { normal: vec3<f32>, };"),
+            Err(LayoutError::MemberCount {
+                expected: 2,
+                found: 1,
+                ..
+            })
+        ));
+        assert!(matches!(
+            check_wgsl::<Packed>("struct Packed { normal: vec3<f32>, power: f32, };"),
+            Err(LayoutError::MemberMismatch { found_name, found_offset: 12, .. })
+                if found_name == "power"
+        ));
+        assert!(matches!(
+            check_wgsl::<Packed>("struct Packed { normal: vec3<f32>, @align(16) strength: f32, };"),
+            Err(LayoutError::MemberMismatch {
+                found_offset: 16,
+                ..
+            })
+        ));
+        assert!(matches!(
+            check_wgsl::<Packed>("struct Packed { normal: vec3<f32>, @size(20) strength: f32, };"),
+            Err(LayoutError::SizeMismatch {
+                expected: 16,
+                found: 32,
+                ..
+            })
+        ));
+        assert!(matches!(
+            check_wgsl::<Packed>("struct Packed {"),
+            Err(LayoutError::Parse(_))
+        ));
+    }
+}
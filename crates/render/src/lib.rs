@@ -1,34 +1,69 @@
 //!
 //! Wgpu backend for Recengine
 //!
+
+// Lets derive macros refer to `::render` from inside this crate too.
+extern crate self as render;
+
 pub mod bind_group_builder;
+pub mod bind_group_cache;
+pub mod bindings;
 pub mod buffers;
+pub mod compute_pipeline_builder;
+pub mod layout;
+pub mod material_description;
 pub mod mesh;
+pub mod pipeline_cache;
+pub mod pipeline_description;
+pub mod push_constants;
 pub mod render_pass;
 pub mod render_pipleine_builder;
+pub mod render_queue;
 pub mod renderer;
 pub mod shader;
+pub mod shader_preprocessor;
+pub mod shader_reflection;
+pub mod shader_watcher;
 pub mod texture;
 
+pub use wgpu;
+
 pub mod prelude {
 
     //! Convinient re-export of common members
 
     pub use super::bind_group_builder::{Builder as BindGroupBuilder, LayoutBuilder};
+    pub use super::bind_group_cache::{BindGroupCache, CachedResource};
+    pub use super::bindings::{
+        BindingLayouts, FrameUniform, ObjectUniform, ViewUniform, FRAME_GROUP, MATERIAL_GROUP,
+        OBJECT_GROUP, VIEW_GROUP,
+    };
     pub use super::buffers::{
         index::IndexBuffer,
-        uniform::{AsBindGroup, UniformBuffer},
+        storage::StorageBuffer,
+        uniform::{AsBindGroup, PreparedBindGroup, UniformBuffer},
         vertex::VertexBuffer,
         vertices::Vertex as VertexDesc,
         Buffer,
     };
-    pub use super::mesh::{material::BaseMaterial, Mesh, MeshVertex};
+    pub use super::compute_pipeline_builder::ComputePipelineBuilder;
+    pub use super::layout::WgslType;
+    pub use super::material_description::{DynamicMaterial, DynamicMaterialType};
+    pub use super::mesh::{
+        material::{BaseMaterial, MaterialType},
+        Mesh, MeshVertex,
+    };
+    pub use super::pipeline_cache::PipelineCache;
+    pub use super::push_constants::PushConstants;
     pub use super::render_pass::{
         Builder as RenderPassBuilder, ColorAttachmentDescriptorBuilder,
         DepthStencilAttachmentDescriptorBuilder,
     };
-    pub use super::render_pipleine_builder::RenderPipelineBuilder;
-    pub use super::renderer::{Renderable, RenderingContext};
+    pub use super::render_pipleine_builder::{ColorTargetStateBuilder, RenderPipelineBuilder};
+    pub use super::render_queue::{Draw, RenderQueue, RenderStats, SortKey};
+    pub use super::renderer::{RenderPhase, Renderable, Renderer, RenderingContext};
     pub use super::shader::Shader;
+    pub use super::shader_preprocessor::Preprocessor;
+    pub use super::shader_watcher::{HotPipeline, ShaderWatcher};
     pub use super::texture::Texture;
 }
@@ -0,0 +1,1010 @@
+//!
+//! Materials described in RON or TOML files
+//!
+//! A [`MaterialDescription`] names a WGSL shader, lists typed parameters with their defaults and
+//! spells out render state, so new materials need no [`AsBindGroup`](crate::prelude::AsBindGroup)
+//! or [`AsPipeline`](crate::mesh::material::AsPipeline) impl. [`DynamicMaterialType`] builds the
+//! bind group layout, uniform buffer layout and pipeline from it once, then
+//! [`DynamicMaterialType::instance`] creates [`DynamicMaterial`]s whose parameters are set by
+//! name.
+//!
+//! Float, vector and color parameters are packed in file order into a `Material` uniform struct
+//! bound at [`MATERIAL_GROUP`], binding 0. Every texture parameter is followed by its sampler,
+//! named with a `_sampler` suffix. The shader declares none of them itself, it imports them with
+//! `#import render::material` next to `#import render::bindings`, and must have `vertex` and
+//! `fragment` entry points taking [`MeshVertex`].
+//!
+//! # Examples
+//!
+//! ```ron
+//! (
+//!     label: Some("Glass"),
+//!     shader: "glass.wgsl",
+//!     parameters: [
+//!         (name: "tint", value: Color((0.8, 0.9, 1.0, 0.4))),
+//!         (name: "roughness", value: Float(0.1)),
+//!         (name: "albedo", value: Texture(path: Some("glass.png"))),
+//!     ],
+//!     blend: Alpha,
+//!     cull_mode: Some(Back),
+//!     depth_write: Some(false),
+//! )
+//! ```
+//!
+//! ```toml
+//! label = "Glass"
+//! shader = "glass.wgsl"
+//! blend = "Alpha"
+//! cull_mode = "Back"
+//! depth_write = false
+//!
+//! [[parameters]]
+//! name = "tint"
+//! value = { Color = [0.8, 0.9, 1.0, 0.4] }
+//!
+//! [[parameters]]
+//! name = "albedo"
+//! value = { Texture = { path = "glass.png" } }
+//! ```
+//!
+//! ```ignore
+//! let glass = DynamicMaterialType::load(
+//!     &device, &queue, &mut bind_groups, &mut pipelines, "assets/materials/glass.ron",
+//! )?;
+//! let material = glass.instance(&device);
+//! material.set(&queue, "roughness", 0.5)?;
+//! ```
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use image::{DynamicImage, Rgba, RgbaImage};
+use serde::Deserialize;
+use wgpu::util::DeviceExt;
+
+use crate::bind_group_builder::{Builder as BindGroupBuilder, LayoutBuilder};
+use crate::bind_group_cache::BindGroupCache;
+use crate::bindings::{BindingLayouts, MATERIAL_GROUP};
+use crate::buffers::vertices::Vertex;
+use crate::layout::{self, WgslType};
+use crate::mesh::material::Material;
+use crate::mesh::MeshVertex;
+use crate::pipeline_cache::PipelineCache;
+use crate::pipeline_description::{Blend, Face, TextureFormat};
+use crate::render_pipleine_builder::{PipelineError, RenderPipelineBuilder};
+use crate::renderer::RenderPhase;
+use crate::shader::ShaderError;
+use crate::shader_preprocessor::Preprocessor;
+use crate::texture::Texture;
+
+/// Name of the WGSL module declaring material parameters.
+pub const SHADER_MODULE: &str = "render::material";
+
+const VISIBILITY: wgpu::ShaderStages = wgpu::ShaderStages::VERTEX_FRAGMENT;
+
+/// Material loaded from a file.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MaterialDescription {
+    #[serde(default)]
+    pub label: Option<String>,
+    /// Path to WGSL source, relative to the directory passed to [`DynamicMaterialType::new`].
+    pub shader: PathBuf,
+    /// Parameters in declaration order, which is also their order in the uniform struct.
+    #[serde(default)]
+    pub parameters: Vec<ParameterDescription>,
+    /// Blending with the target, [`Blend::Replace`] when omitted. Blended materials are drawn in
+    /// [`RenderPhase::Transparent`].
+    #[serde(default = "default_blend")]
+    pub blend: Blend,
+    #[serde(default)]
+    pub cull_mode: Option<Face>,
+    /// Whether depth is written, written unless disabled. May only be given with `depth_format`.
+    #[serde(default)]
+    pub depth_write: Option<bool>,
+    /// Format of the depth target,
+    /// [`Renderer::DEPTH_FORMAT`](crate::renderer::Renderer::DEPTH_FORMAT) when omitted, which
+    /// renderers draw with. `None` disables depth testing for passes without a depth target.
+    #[serde(default = "default_depth_format")]
+    pub depth_format: Option<TextureFormat>,
+    #[serde(default = "default_color_format")]
+    pub color_format: TextureFormat,
+}
+
+/// Named parameter with its default value.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ParameterDescription {
+    /// Name of the member or texture in WGSL.
+    pub name: String,
+    /// Default of every instance, also gives the type of the parameter.
+    pub value: ParameterValue,
+}
+
+/// Value of a material parameter.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub enum ParameterValue {
+    Float(f32),
+    Vec2([f32; 2]),
+    Vec3([f32; 3]),
+    Vec4([f32; 4]),
+    /// Linear RGBA color, `vec4<f32>` in WGSL.
+    Color([f32; 4]),
+    /// Image file relative to the material file, a white pixel when `path` is omitted.
+    Texture {
+        #[serde(default)]
+        path: Option<PathBuf>,
+    },
+}
+
+/// Type of a material parameter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParameterKind {
+    Float,
+    Vec2,
+    Vec3,
+    Vec4,
+    Color,
+    Texture,
+}
+
+impl ParameterValue {
+    /// Type of the value.
+    pub fn kind(&self) -> ParameterKind {
+        match self {
+            Self::Float(_) => ParameterKind::Float,
+            Self::Vec2(_) => ParameterKind::Vec2,
+            Self::Vec3(_) => ParameterKind::Vec3,
+            Self::Vec4(_) => ParameterKind::Vec4,
+            Self::Color(_) => ParameterKind::Color,
+            Self::Texture { .. } => ParameterKind::Texture,
+        }
+    }
+
+    // Bytes of a uniform value in WGSL layout, `None` for textures.
+    fn to_bytes(&self) -> Option<Vec<u8>> {
+        match self {
+            Self::Float(value) => Some(layout::to_bytes(value)),
+            Self::Vec2(value) => Some(layout::to_bytes(value)),
+            Self::Vec3(value) => Some(layout::to_bytes(value)),
+            Self::Vec4(value) | Self::Color(value) => Some(layout::to_bytes(value)),
+            Self::Texture { .. } => None,
+        }
+    }
+}
+
+impl From<f32> for ParameterValue {
+    fn from(value: f32) -> Self {
+        Self::Float(value)
+    }
+}
+
+impl From<[f32; 2]> for ParameterValue {
+    fn from(value: [f32; 2]) -> Self {
+        Self::Vec2(value)
+    }
+}
+
+impl From<[f32; 3]> for ParameterValue {
+    fn from(value: [f32; 3]) -> Self {
+        Self::Vec3(value)
+    }
+}
+
+impl From<[f32; 4]> for ParameterValue {
+    fn from(value: [f32; 4]) -> Self {
+        Self::Vec4(value)
+    }
+}
+
+impl ParameterKind {
+    /// Whether a value of kind `other` can be assigned to a parameter of this kind.
+    ///
+    /// Colors and `Vec4`s are interchangeable, as both are `vec4<f32>` in WGSL.
+    pub fn accepts(self, other: ParameterKind) -> bool {
+        use ParameterKind::{Color, Vec4};
+        self == other || matches!((self, other), (Color, Vec4) | (Vec4, Color))
+    }
+
+    // WGSL name, alignment and size of uniform kinds.
+    fn wgsl(self) -> Option<(&'static str, u64, u64)> {
+        match self {
+            Self::Float => Some(wgsl_of::<f32>()),
+            Self::Vec2 => Some(wgsl_of::<[f32; 2]>()),
+            Self::Vec3 => Some(wgsl_of::<[f32; 3]>()),
+            Self::Vec4 | Self::Color => Some(wgsl_of::<[f32; 4]>()),
+            Self::Texture => None,
+        }
+    }
+}
+
+impl fmt::Display for ParameterKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Float => "float",
+            Self::Vec2 => "vec2",
+            Self::Vec3 => "vec3",
+            Self::Vec4 => "vec4",
+            Self::Color => "color",
+            Self::Texture => "texture",
+        };
+        f.write_str(name)
+    }
+}
+
+fn wgsl_of<T: WgslType>() -> (&'static str, u64, u64) {
+    (T::WGSL_NAME, T::ALIGN, T::SIZE)
+}
+
+fn default_blend() -> Blend {
+    Blend::Replace
+}
+
+fn default_color_format() -> TextureFormat {
+    TextureFormat::Bgra8UnormSrgb
+}
+
+// Matches `Renderer::DEPTH_FORMAT`.
+fn default_depth_format() -> Option<TextureFormat> {
+    Some(TextureFormat::Depth32Float)
+}
+
+impl MaterialDescription {
+    /// Parse description from RON.
+    pub fn from_ron(source: &str) -> Result<Self, MaterialError> {
+        ron::from_str(source).map_err(|err| MaterialError::Parse(err.to_string()))
+    }
+
+    /// Parse description from TOML.
+    pub fn from_toml(source: &str) -> Result<Self, MaterialError> {
+        toml::from_str(source).map_err(|err| MaterialError::Parse(err.to_string()))
+    }
+
+    /// Read description from a `.ron` or `.toml` file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, MaterialError> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path)
+            .map_err(|err| MaterialError::Io(path.to_path_buf(), err))?;
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("ron") => Self::from_ron(&source),
+            Some("toml") => Self::from_toml(&source),
+            _ => Err(MaterialError::UnknownFormat(path.to_path_buf())),
+        }
+    }
+
+    // Reject render state that can't be built.
+    fn check_render_state(&self) -> Result<(), MaterialError> {
+        if self.depth_write.is_some() && self.depth_format.is_none() {
+            return Err(MaterialError::DepthWriteWithoutDepth);
+        }
+        Ok(())
+    }
+
+    /// Phase instances are drawn in, transparent if the material blends.
+    pub fn phase(&self) -> RenderPhase {
+        match self.blend {
+            Blend::Replace => RenderPhase::Opaque,
+            _ => RenderPhase::Transparent,
+        }
+    }
+}
+
+// Where the value of a parameter lives in the bind group.
+#[derive(Debug, Clone, Copy)]
+enum Slot {
+    /// Byte offset in the uniform buffer.
+    Uniform(u64),
+    /// Index in the textures of an instance.
+    Texture(usize),
+}
+
+// Parameter of a loaded material.
+#[derive(Debug)]
+struct Parameter {
+    name: String,
+    kind: ParameterKind,
+    slot: Slot,
+}
+
+// Layout of the uniform struct and bindings of textures.
+#[derive(Debug)]
+struct ParameterLayout {
+    parameters: Vec<Parameter>,
+    uniform_size: u64,
+    textures: usize,
+}
+
+impl ParameterLayout {
+    fn new(description: &MaterialDescription) -> Result<Self, MaterialError> {
+        let mut parameters: Vec<Parameter> = Vec::new();
+        let mut offset = 0;
+        let mut align = 1;
+        let mut textures = 0;
+
+        for parameter in &description.parameters {
+            let name = &parameter.name;
+            if !is_identifier(name) {
+                return Err(MaterialError::InvalidName(name.clone()));
+            }
+            if parameters.iter().any(|other| other.name == *name) {
+                return Err(MaterialError::DuplicateParameter(name.clone()));
+            }
+
+            let kind = parameter.value.kind();
+            let slot = match kind.wgsl() {
+                Some((_, member_align, size)) => {
+                    let member_offset = layout::round_up(member_align, offset);
+                    offset = member_offset + size;
+                    align = layout::max(align, member_align);
+                    Slot::Uniform(member_offset)
+                }
+                None => {
+                    textures += 1;
+                    Slot::Texture(textures - 1)
+                }
+            };
+            parameters.push(Parameter {
+                name: name.clone(),
+                kind,
+                slot,
+            });
+        }
+
+        Ok(Self {
+            parameters,
+            uniform_size: layout::round_up(align, offset),
+            textures,
+        })
+    }
+
+    fn has_uniforms(&self) -> bool {
+        self.uniform_size > 0
+    }
+
+    fn get(&self, name: &str) -> Result<&Parameter, MaterialError> {
+        self.parameters
+            .iter()
+            .find(|parameter| parameter.name == name)
+            .ok_or_else(|| MaterialError::UnknownParameter(name.to_string()))
+    }
+
+    fn layout_entries(&self) -> LayoutBuilder {
+        let mut entries = LayoutBuilder::new();
+        if self.has_uniforms() {
+            entries = entries.uniform_buffer(VISIBILITY, false);
+        }
+        for _ in 0..self.textures {
+            entries = entries
+                .texture(
+                    VISIBILITY,
+                    false,
+                    wgpu::TextureViewDimension::D2,
+                    wgpu::TextureSampleType::Float { filterable: true },
+                )
+                .filtering_sampler(VISIBILITY);
+        }
+        entries
+    }
+
+    // `render::material` module declaring the uniform struct and textures, bindings are assigned
+    // in the order of `layout_entries`.
+    fn shader_module(&self) -> String {
+        let mut source = String::new();
+        let mut binding = 0;
+        if self.has_uniforms() {
+            source.push_str("struct Material {\n");
+            for parameter in &self.parameters {
+                if let Some((ty, _, _)) = parameter.kind.wgsl() {
+                    source.push_str(&format!("    {}: {},\n", parameter.name, ty));
+                }
+            }
+            source.push_str("};\n");
+            source.push_str(&format!(
+                "@group({}) @binding({}) var<uniform> material: Material;\n",
+                MATERIAL_GROUP, binding
+            ));
+            binding += 1;
+        }
+        for parameter in &self.parameters {
+            if let Slot::Texture(_) = parameter.slot {
+                source.push_str(&format!(
+                    "@group({0}) @binding({1}) var {3}: texture_2d<f32>;\n\
+                     @group({0}) @binding({2}) var {3}_sampler: sampler;\n",
+                    MATERIAL_GROUP,
+                    binding,
+                    binding + 1,
+                    parameter.name
+                ));
+                binding += 2;
+            }
+        }
+        source
+    }
+}
+
+/// Shaders, bind group layout and pipeline built from a [`MaterialDescription`], shared by all
+/// its instances.
+pub struct DynamicMaterialType {
+    description: MaterialDescription,
+    parameters: Arc<ParameterLayout>,
+    layout: Arc<wgpu::BindGroupLayout>,
+    pipeline: Arc<wgpu::RenderPipeline>,
+    defaults: Vec<u8>,
+    textures: Vec<Arc<Texture>>,
+}
+
+impl DynamicMaterialType {
+    /// Load description at `path` and build the material, files it names are resolved relative
+    /// to its directory.
+    pub fn load(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        bind_groups: &mut BindGroupCache,
+        pipelines: &mut PipelineCache,
+        path: impl AsRef<Path>,
+    ) -> Result<Self, MaterialError> {
+        let path = path.as_ref();
+        let description = MaterialDescription::load(path)?;
+        let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+        Self::new(device, queue, bind_groups, pipelines, description, base_dir)
+    }
+
+    /// Build layouts and pipeline of `description`, shared through caches, and upload default
+    /// textures. Paths are resolved relative to `base_dir`.
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        bind_groups: &mut BindGroupCache,
+        pipelines: &mut PipelineCache,
+        description: MaterialDescription,
+        base_dir: impl AsRef<Path>,
+    ) -> Result<Self, MaterialError> {
+        let base_dir = base_dir.as_ref();
+        description.check_render_state()?;
+        let parameters = ParameterLayout::new(&description)?;
+        let label = description.label.as_deref();
+
+        let entries = parameters.layout_entries();
+        let layout = bind_groups.layout(device, entries.entries(), label);
+
+        let preprocessor = crate::mesh::shader_modules(Preprocessor::new())
+            .module(SHADER_MODULE, &parameters.shader_module());
+        let processed = preprocessor
+            .process_file(base_dir.join(&description.shader))
+            .map_err(|err| MaterialError::Shader(err.into()))?;
+        processed
+            .validate()
+            .map_err(|err| MaterialError::Shader(err.into()))?;
+        let shader = pipelines.shader(device, processed.source, VISIBILITY, label);
+
+        let layouts = BindingLayouts::new(device, bind_groups);
+        let bind_group_layouts = layouts.with_material(&layout);
+        let blend = description.blend.state();
+        let mut builder = RenderPipelineBuilder::from_shared_layouts(&bind_group_layouts, &*shader)
+            .fragment_shader(&*shader)
+            .add_vertex_buffer_layout(MeshVertex::desc())
+            .cull_mode(description.cull_mode.map(Into::into))
+            .color_target(description.color_format.into(), |state| state.blend(blend));
+        if let Some(format) = description.depth_format {
+            builder = builder.depth_format(format.into());
+        }
+        if let Some(depth_write) = description.depth_write {
+            builder = builder.depth_write_enabled(depth_write);
+        }
+        let pipeline = pipelines
+            .get_or_build(device, builder, label)
+            .map_err(MaterialError::Pipeline)?;
+
+        let mut defaults = vec![0; parameters.uniform_size as usize];
+        let mut textures = Vec::with_capacity(parameters.textures);
+        for (parameter, slot) in description.parameters.iter().zip(&parameters.parameters) {
+            match &parameter.value {
+                ParameterValue::Texture { path } => {
+                    let image = match path {
+                        Some(path) => load_image(&base_dir.join(path))?,
+                        None => {
+                            DynamicImage::ImageRgba8(RgbaImage::from_pixel(1, 1, Rgba([255; 4])))
+                        }
+                    };
+                    let texture = Texture::new(
+                        device,
+                        queue,
+                        &image,
+                        Some(wgpu::FilterMode::Linear),
+                        Some(wgpu::AddressMode::Repeat),
+                    );
+                    textures.push(Arc::new(texture));
+                }
+                value => {
+                    if let (Slot::Uniform(offset), Some(bytes)) = (slot.slot, value.to_bytes()) {
+                        defaults[offset as usize..][..bytes.len()].copy_from_slice(&bytes);
+                    }
+                }
+            }
+        }
+
+        Ok(Self {
+            description,
+            parameters: Arc::new(parameters),
+            layout,
+            pipeline,
+            defaults,
+            textures,
+        })
+    }
+
+    /// Create an instance with default parameters.
+    pub fn instance(&self, device: &wgpu::Device) -> DynamicMaterial {
+        let label = self.description.label.as_deref();
+        let uniforms = self.parameters.has_uniforms().then(|| {
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label,
+                contents: &self.defaults,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            })
+        });
+        let textures = self.textures.clone();
+        let bind_group = create_bind_group(device, &self.layout, &uniforms, &textures, label);
+
+        DynamicMaterial {
+            parameters: Arc::clone(&self.parameters),
+            layout: Arc::clone(&self.layout),
+            pipeline: Arc::clone(&self.pipeline),
+            phase: self.description.phase(),
+            uniforms,
+            textures,
+            bind_group,
+        }
+    }
+
+    /// Description the material was built from.
+    pub fn description(&self) -> &MaterialDescription {
+        &self.description
+    }
+
+    /// Layout of the material bind group.
+    pub fn layout(&self) -> &Arc<wgpu::BindGroupLayout> {
+        &self.layout
+    }
+
+    /// Pipeline drawing all instances.
+    pub fn pipeline(&self) -> &Arc<wgpu::RenderPipeline> {
+        &self.pipeline
+    }
+}
+
+/// Instance of a [`DynamicMaterialType`] with its own parameter values.
+pub struct DynamicMaterial {
+    parameters: Arc<ParameterLayout>,
+    layout: Arc<wgpu::BindGroupLayout>,
+    pipeline: Arc<wgpu::RenderPipeline>,
+    phase: RenderPhase,
+    uniforms: Option<wgpu::Buffer>,
+    textures: Vec<Arc<Texture>>,
+    bind_group: wgpu::BindGroup,
+}
+
+impl DynamicMaterial {
+    /// Names and types of the parameters, in declaration order.
+    pub fn parameters(&self) -> impl Iterator<Item = (&str, ParameterKind)> {
+        self.parameters
+            .parameters
+            .iter()
+            .map(|parameter| (parameter.name.as_str(), parameter.kind))
+    }
+
+    /// Set float, vector or color parameter `name`, visible from the next submitted frame.
+    pub fn set(
+        &self,
+        queue: &wgpu::Queue,
+        name: &str,
+        value: impl Into<ParameterValue>,
+    ) -> Result<(), MaterialError> {
+        let value = value.into();
+        let parameter = self.parameters.get(name)?;
+        check_kind(parameter, value.kind())?;
+        if let (Slot::Uniform(offset), Some(buffer), Some(bytes)) =
+            (parameter.slot, &self.uniforms, value.to_bytes())
+        {
+            queue.write_buffer(buffer, offset, &bytes);
+        }
+        Ok(())
+    }
+
+    /// Replace texture parameter `name`, recreating the bind group.
+    pub fn set_texture(
+        &mut self,
+        device: &wgpu::Device,
+        name: &str,
+        texture: Arc<Texture>,
+    ) -> Result<(), MaterialError> {
+        let parameter = self.parameters.get(name)?;
+        check_kind(parameter, ParameterKind::Texture)?;
+        if let Slot::Texture(index) = parameter.slot {
+            self.textures[index] = texture;
+            self.bind_group =
+                create_bind_group(device, &self.layout, &self.uniforms, &self.textures, None);
+        }
+        Ok(())
+    }
+}
+
+impl Material for DynamicMaterial {
+    fn pipeline(&self) -> &wgpu::RenderPipeline {
+        &self.pipeline
+    }
+
+    fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group
+    }
+
+    fn phase(&self) -> RenderPhase {
+        self.phase
+    }
+}
+
+fn check_kind(parameter: &Parameter, found: ParameterKind) -> Result<(), MaterialError> {
+    if parameter.kind.accepts(found) {
+        Ok(())
+    } else {
+        Err(MaterialError::ParameterType {
+            name: parameter.name.clone(),
+            expected: parameter.kind,
+            found,
+        })
+    }
+}
+
+// Bindings follow `ParameterLayout::layout_entries`.
+fn create_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    uniforms: &Option<wgpu::Buffer>,
+    textures: &[Arc<Texture>],
+    label: Option<&str>,
+) -> wgpu::BindGroup {
+    let mut builder = BindGroupBuilder::new();
+    if let Some(buffer) = uniforms {
+        builder = builder.buffer_bytes(buffer, 0, None);
+    }
+    for texture in textures {
+        builder = builder
+            .texture_view(&texture.view)
+            .sampler(&texture.sampler);
+    }
+    builder.build(device, layout, label)
+}
+
+fn load_image(path: &Path) -> Result<DynamicImage, MaterialError> {
+    let image = image::open(path).map_err(|err| MaterialError::Image(path.to_path_buf(), err))?;
+    Ok(DynamicImage::ImageRgba8(image.to_rgba8()))
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|first| first.is_ascii_alphabetic() || first == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Reasons for a material to fail loading or a parameter to be rejected.
+#[derive(Debug)]
+pub enum MaterialError {
+    /// File could not be read.
+    Io(PathBuf, std::io::Error),
+    /// File extension is neither `ron` nor `toml`.
+    UnknownFormat(PathBuf),
+    /// Description failed to parse, contains formatted parser error.
+    Parse(String),
+    /// Parameter name is not a WGSL identifier.
+    InvalidName(String),
+    /// Two parameters share a name.
+    DuplicateParameter(String),
+    /// `depth_write` is given without `depth_format`.
+    DepthWriteWithoutDepth,
+    /// Texture could not be loaded.
+    Image(PathBuf, image::ImageError),
+    /// Shader could not be read, preprocessed or validated.
+    Shader(ShaderError),
+    /// Render state is invalid for the shader.
+    Pipeline(PipelineError),
+    /// Material has no parameter with given name.
+    UnknownParameter(String),
+    /// Value has a different type than the parameter.
+    ParameterType {
+        name: String,
+        expected: ParameterKind,
+        found: ParameterKind,
+    },
+}
+
+impl fmt::Display for MaterialError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(path, err) => write!(f, "failed to read {}: {}", path.display(), err),
+            Self::UnknownFormat(path) => write!(
+                f,
+                "{} is neither a `.ron` nor a `.toml` file",
+                path.display()
+            ),
+            Self::Parse(err) => write!(f, "failed to parse material description: {}", err),
+            Self::InvalidName(name) => {
+                write!(f, "parameter name `{}` is not a WGSL identifier", name)
+            }
+            Self::DuplicateParameter(name) => write!(f, "parameter `{}` is declared twice", name),
+            Self::DepthWriteWithoutDepth => {
+                write!(f, "`depth_write` is given, but there's no `depth_format`")
+            }
+            Self::Image(path, err) => write!(f, "failed to load {}: {}", path.display(), err),
+            Self::Shader(err) => write!(f, "invalid material shader: {}", err),
+            Self::Pipeline(err) => write!(f, "invalid material pipeline: {}", err),
+            Self::UnknownParameter(name) => write!(f, "unknown material parameter `{}`", name),
+            Self::ParameterType {
+                name,
+                expected,
+                found,
+            } => write!(
+                f,
+                "parameter `{}` is a {}, but a {} was given",
+                name, expected, found
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MaterialError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(_, err) => Some(err),
+            Self::Image(_, err) => Some(err),
+            Self::Shader(err) => Some(err),
+            Self::Pipeline(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RON: &str = r#"(
+    label: Some("Glass"),
+    shader: "glass.wgsl",
+    parameters: [
+        (name: "tint", value: Color((0.8, 0.9, 1.0, 0.4))),
+        (name: "roughness", value: Float(0.1)),
+        (name: "albedo", value: Texture(path: Some("glass.png"))),
+    ],
+    blend: Alpha,
+    cull_mode: Some(Back),
+    depth_format: Some(Depth32Float),
+    depth_write: Some(false),
+)"#;
+
+    const TOML: &str = r#"
+label = "Glass"
+shader = "glass.wgsl"
+blend = "Alpha"
+cull_mode = "Back"
+depth_format = "Depth32Float"
+depth_write = false
+
+[[parameters]]
+name = "tint"
+value = { Color = [0.8, 0.9, 1.0, 0.4] }
+
+[[parameters]]
+name = "albedo"
+value = { Texture = { path = "glass.png" } }
+"#;
+
+    fn description(parameters: &[(&str, ParameterValue)]) -> MaterialDescription {
+        MaterialDescription {
+            label: None,
+            shader: PathBuf::from("material.wgsl"),
+            parameters: parameters
+                .iter()
+                .map(|(name, value)| ParameterDescription {
+                    name: name.to_string(),
+                    value: value.clone(),
+                })
+                .collect(),
+            blend: default_blend(),
+            cull_mode: None,
+            depth_write: None,
+            depth_format: default_depth_format(),
+            color_format: default_color_format(),
+        }
+    }
+
+    fn texture() -> ParameterValue {
+        ParameterValue::Texture { path: None }
+    }
+
+    fn offsets(layout: &ParameterLayout) -> Vec<Option<u64>> {
+        layout
+            .parameters
+            .iter()
+            .map(|parameter| match parameter.slot {
+                Slot::Uniform(offset) => Some(offset),
+                Slot::Texture(_) => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn packs_scalar_after_vec3() {
+        let layout = ParameterLayout::new(&description(&[
+            ("normal", [0.0; 3].into()),
+            ("strength", 1.0.into()),
+        ]))
+        .unwrap();
+        assert_eq!(offsets(&layout), [Some(0), Some(12)]);
+        assert_eq!(layout.uniform_size, 16);
+    }
+
+    #[test]
+    fn aligns_uniform_members() {
+        let layout = ParameterLayout::new(&description(&[
+            ("glow", 1.0.into()),
+            ("albedo", texture()),
+            ("offset", [0.0; 2].into()),
+            ("tint", ParameterValue::Color([1.0; 4])),
+            ("scale", 1.0.into()),
+        ]))
+        .unwrap();
+        assert_eq!(
+            offsets(&layout),
+            [Some(0), None, Some(8), Some(16), Some(32)]
+        );
+        // Struct size rounds up to the alignment of `vec4<f32>`.
+        assert_eq!(layout.uniform_size, 48);
+        assert_eq!(layout.textures, 1);
+    }
+
+    #[test]
+    fn numbers_bindings_after_uniforms() {
+        let layout = ParameterLayout::new(&description(&[
+            ("albedo", texture()),
+            ("glow", 1.0.into()),
+            ("normal", texture()),
+        ]))
+        .unwrap();
+        let bindings: Vec<_> = layout
+            .layout_entries()
+            .entries()
+            .iter()
+            .map(|entry| entry.binding)
+            .collect();
+        assert_eq!(bindings, [0, 1, 2, 3, 4]);
+
+        let module = layout.shader_module();
+        assert!(module.contains("@binding(0) var<uniform> material: Material;"));
+        assert!(module.contains("@binding(1) var albedo: texture_2d<f32>;"));
+        assert!(module.contains("@binding(2) var albedo_sampler: sampler;"));
+        assert!(module.contains("@binding(3) var normal: texture_2d<f32>;"));
+        assert!(module.contains("@binding(4) var normal_sampler: sampler;"));
+    }
+
+    #[test]
+    fn numbers_textures_from_zero_without_uniforms() {
+        let layout = ParameterLayout::new(&description(&[("albedo", texture())])).unwrap();
+        assert_eq!(layout.uniform_size, 0);
+        assert_eq!(layout.layout_entries().entries().len(), 2);
+
+        let module = layout.shader_module();
+        assert!(!module.contains("struct Material"));
+        assert!(module.contains("@binding(0) var albedo: texture_2d<f32>;"));
+        assert!(module.contains("@binding(1) var albedo_sampler: sampler;"));
+    }
+
+    #[test]
+    fn rejects_bad_parameter_names() {
+        let err = ParameterLayout::new(&description(&[("2d", 1.0.into())])).unwrap_err();
+        assert!(matches!(err, MaterialError::InvalidName(name) if name == "2d"));
+        let err =
+            ParameterLayout::new(&description(&[("a", 1.0.into()), ("a", texture())])).unwrap_err();
+        assert!(matches!(err, MaterialError::DuplicateParameter(name) if name == "a"));
+    }
+
+    #[test]
+    fn parses_ron() {
+        let description = MaterialDescription::from_ron(RON).unwrap();
+        assert_eq!(description.label.as_deref(), Some("Glass"));
+        assert_eq!(description.shader, Path::new("glass.wgsl"));
+        assert_eq!(description.parameters.len(), 3);
+        assert_eq!(
+            description.parameters[0].value,
+            ParameterValue::Color([0.8, 0.9, 1.0, 0.4])
+        );
+        assert_eq!(description.parameters[1].value, ParameterValue::Float(0.1));
+        assert_eq!(
+            description.parameters[2].value,
+            ParameterValue::Texture {
+                path: Some(PathBuf::from("glass.png"))
+            }
+        );
+        assert!(matches!(description.blend, Blend::Alpha));
+        assert!(matches!(description.cull_mode, Some(Face::Back)));
+        assert!(matches!(
+            description.depth_format,
+            Some(TextureFormat::Depth32Float)
+        ));
+        assert_eq!(description.depth_write, Some(false));
+        assert_eq!(description.phase(), RenderPhase::Transparent);
+        description.check_render_state().unwrap();
+    }
+
+    #[test]
+    fn parses_toml() {
+        let description = MaterialDescription::from_toml(TOML).unwrap();
+        assert_eq!(description.label.as_deref(), Some("Glass"));
+        assert_eq!(description.parameters.len(), 2);
+        assert_eq!(
+            description.parameters[0].value,
+            ParameterValue::Color([0.8, 0.9, 1.0, 0.4])
+        );
+        assert_eq!(
+            description.parameters[1].value,
+            ParameterValue::Texture {
+                path: Some(PathBuf::from("glass.png"))
+            }
+        );
+        assert!(matches!(description.blend, Blend::Alpha));
+        assert_eq!(description.depth_write, Some(false));
+        description.check_render_state().unwrap();
+    }
+
+    #[test]
+    fn applies_defaults() {
+        let description = MaterialDescription::from_ron(r#"(shader: "plain.wgsl")"#).unwrap();
+        assert!(description.parameters.is_empty());
+        assert!(matches!(description.blend, Blend::Replace));
+        assert!(matches!(
+            description.color_format,
+            TextureFormat::Bgra8UnormSrgb
+        ));
+        assert_eq!(description.depth_write, None);
+        assert_eq!(description.phase(), RenderPhase::Opaque);
+    }
+
+    #[test]
+    fn defaults_to_renderer_depth_format() {
+        let description = MaterialDescription::from_ron(r#"(shader: "plain.wgsl")"#).unwrap();
+        let format = description.depth_format.map(wgpu::TextureFormat::from);
+        assert_eq!(format, Some(crate::renderer::Renderer::DEPTH_FORMAT));
+
+        let description = MaterialDescription::from_toml(r#"shader = "plain.wgsl""#).unwrap();
+        let format = description.depth_format.map(wgpu::TextureFormat::from);
+        assert_eq!(format, Some(crate::renderer::Renderer::DEPTH_FORMAT));
+        description.check_render_state().unwrap();
+    }
+
+    #[test]
+    fn disables_depth_testing() {
+        let description =
+            MaterialDescription::from_ron(r#"(shader: "plain.wgsl", depth_format: None)"#).unwrap();
+        assert!(description.depth_format.is_none());
+        description.check_render_state().unwrap();
+    }
+
+    #[test]
+    fn rejects_depth_write_without_depth_format() {
+        let description = MaterialDescription::from_ron(
+            r#"(shader: "plain.wgsl", depth_format: None, depth_write: Some(true))"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            description.check_render_state(),
+            Err(MaterialError::DepthWriteWithoutDepth)
+        ));
+    }
+
+    #[test]
+    fn rejects_unknown_fields() {
+        let err = MaterialDescription::from_ron(r#"(shader: "plain.wgsl", depth: true)"#);
+        assert!(matches!(err, Err(MaterialError::Parse(_))));
+    }
+}
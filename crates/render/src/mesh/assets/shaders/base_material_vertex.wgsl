@@ -1,11 +1,5 @@
-struct VertexOutput {
-    @location(0) tex_coord: vec2<f32>,
-    @builtin(position) position: vec4<f32>,
-};
-
-@group(0)
-@binding(1)
-var<uniform> transform: mat4x4<f32>;
+#import render::bindings
+#import mesh::vertex_output
 
 @vertex
 fn vertex(
@@ -15,6 +9,6 @@ fn vertex(
 ) -> VertexOutput {
     var result: VertexOutput;
     result.tex_coord = tex_coord;
-    result.position = transform * vec4(position, 1.0);
+    result.position = view.view_projection * object.model * vec4(position, 1.0);
     return result;
 }
\ No newline at end of file
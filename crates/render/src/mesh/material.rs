@@ -1,209 +1,321 @@
-use wgpu::{
-    Color, LoadOp, PipelineLayout, RenderPassColorAttachment, RenderPassDescriptor, RenderPipeline,
-};
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use wgpu::{BindGroupLayout, PipelineLayout, RenderPipeline};
 
 use super::GpuMesh;
+use crate::bindings;
 use crate::prelude::*;
+use crate::render_queue;
 
 pub struct ObjectGpu {
     meshes: Vec<GpuMesh>,
-    material: Box<dyn Material>,
+    material: Arc<dyn Material>,
+    model: [[f32; 4]; 4],
+    transform: UniformBuffer<ObjectUniform>,
 }
 
 impl Renderable for ObjectGpu {
+    fn phase(&self) -> RenderPhase {
+        self.material.phase()
+    }
+
     fn update(&mut self, _context: &mut RenderingContext) {}
 
-    fn render(&mut self, context: &mut RenderingContext) {
-        let mut encoder = context.create_encoder("Base obj encoder");
-
-        {
-            // let mut rend_pass = RenderPassBuilder::new()
-            //     .color_attachment(context.output, |col_builer| {
-            //         col_builer
-            //             .load_op(wgpu::LoadOp::Clear(wgpu::Color::BLUE))
-            //             .store_op(true)
-            //     })
-            //     .begin(&mut encoder);
-
-            // TODO: WHAT THE FUCK IS DESCRIPTOR
-
-            let anime = RenderPassColorAttachment {
-                view: context.output,
-                resolve_target: None,
-                ops: wgpu::Operations {
-                    load: LoadOp::Clear(Color::TRANSPARENT),
-                    store: true,
-                },
-            };
-
-            let sex = RenderPassDescriptor {
-                label: Some("Base render Pass"),
-                color_attachments: &[Some(anime)],
-                depth_stencil_attachment: None,
-            };
-
-            let mut rend_pass = self
-                .material
-                .begin_render_pass(context.device, &mut encoder, &sex);
-
-            for mesh in &self.meshes {
-                rend_pass.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
-                if let Some(indicies) = &mesh.index_buffer {
-                    rend_pass.set_index_buffer(indicies.slice(..), wgpu::IndexFormat::Uint32);
-                    rend_pass.draw_indexed(0..indicies.len() as u32, 0, 0..1);
-                } else {
-                    rend_pass.draw(0..mesh.vertex_buffer.len() as u32, 0..1);
-                }
-            }
+    fn render<'a>(&'a self, queue: &mut RenderQueue<'a>) {
+        let pipeline = self.material.pipeline();
+        let bind_group = self.material.bind_group();
+        let position = [self.model[3][0], self.model[3][1], self.model[3][2]];
+        let key = SortKey::new(
+            self.phase(),
+            render_queue::resource_id(pipeline),
+            render_queue::resource_id(bind_group),
+            queue.depth(position),
+        );
 
-            // rend_pass.draw(0..VERTICES.len() as u32, 0..1);
+        for mesh in &self.meshes {
+            let draw = Draw::new(
+                key,
+                pipeline,
+                &mesh.vertex_buffer,
+                0..mesh.vertex_buffer.len() as u32,
+            )
+            .bind_group(MATERIAL_GROUP, bind_group)
+            .bind_group(OBJECT_GROUP, &self.transform.bind_group);
+            match &mesh.index_buffer {
+                Some(indicies) => queue.push(draw.indexed(
+                    indicies,
+                    wgpu::IndexFormat::Uint32,
+                    0..indicies.len() as u32,
+                )),
+                None => queue.push(draw),
+            }
         }
-
-        context.submit(encoder);
     }
 }
 
 impl ObjectGpu {
-    pub fn new(meshes: Vec<GpuMesh>, material: Box<dyn Material>) -> Self {
-        Self { meshes, material }
+    /// Create an object drawn at `transform`, its object bind group is shared through `cache`.
+    pub fn new(
+        device: &wgpu::Device,
+        cache: &mut BindGroupCache,
+        meshes: Vec<GpuMesh>,
+        material: Arc<dyn Material>,
+        transform: [[f32; 4]; 4],
+    ) -> Self {
+        let model = transform;
+        let transform =
+            bindings::uniform(device, cache, ObjectUniform::new(model), "Object transform");
+        Self {
+            meshes,
+            material,
+            model,
+            transform,
+        }
+    }
+
+    /// Move the object to `transform`.
+    pub fn set_transform(&mut self, queue: &wgpu::Queue, transform: [[f32; 4]; 4]) {
+        self.model = transform;
+        self.transform
+            .copy_to_gpu(queue, &ObjectUniform::new(transform));
     }
 }
 
+/// Material uploaded to the GPU.
 pub trait Material {
-    fn begin_render_pass<'a>(
-        &'a self,
-        device: &wgpu::Device,
-        encoder: &'a mut wgpu::CommandEncoder,
-        rp_desc: &'a wgpu::RenderPassDescriptor,
-        // transform_data: &TransformBindGroup,
-        // lighting_data: &LightingBindGroup,
-    ) -> wgpu::RenderPass<'a>;
+    /// Pipeline drawing with the material.
+    fn pipeline(&self) -> &wgpu::RenderPipeline;
+
+    /// Bind group of the material, bound at [`MATERIAL_GROUP`].
+    fn bind_group(&self) -> &wgpu::BindGroup;
+
+    /// Phase objects drawn with the material belong to.
+    fn phase(&self) -> RenderPhase {
+        RenderPhase::Opaque
+    }
 }
 
 pub trait AsPipeline {
+    /// Create a pipeline layout from `bind_group_layouts` and push constant ranges per stage.
+    ///
+    /// # Panics
+    ///
+    /// If `push_constant_ranges` is not empty and the device doesn't support
+    /// [`wgpu::Features::PUSH_CONSTANTS`], see [`PushConstants`] for a fallback.
     fn pipeline_layout(
         device: &wgpu::Device,
-        layout: &wgpu::BindGroupLayout,
+        bind_group_layouts: &[&wgpu::BindGroupLayout],
+        push_constant_ranges: &[wgpu::PushConstantRange],
         label: Option<&str>,
     ) -> PipelineLayout {
+        assert!(
+            push_constant_ranges.is_empty()
+                || device.features().contains(wgpu::Features::PUSH_CONSTANTS),
+            "push constants are not supported by the device"
+        );
         device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label,
-            bind_group_layouts: &[&layout],
-            push_constant_ranges: &[],
+            bind_group_layouts,
+            push_constant_ranges,
         })
     }
 
-    fn pipeline(&self, device: &wgpu::Device, layout: &PipelineLayout) -> RenderPipeline;
+    /// Get the pipeline drawing with `bind_group_layouts`, shared through `cache`.
+    ///
+    /// The pipeline only depends on the type, so it can be shared by all its values.
+    fn pipeline(
+        device: &wgpu::Device,
+        cache: &mut PipelineCache,
+        bind_group_layouts: &[Arc<BindGroupLayout>],
+    ) -> Arc<RenderPipeline>;
+}
+
+pub trait AsMaterial: AsBindGroup + AsPipeline + Sized {
+    /// Material on the GPU.
+    type Gpu: Material;
+
+    /// Upload parameters of the material to the GPU, drawing with the pipeline and bind group
+    /// layout of `material_type`.
+    ///
+    /// The result can be wrapped in an [`Arc`] and shared by objects, while the game keeps a
+    /// handle to update its parameters.
+    fn instance(&self, device: &wgpu::Device, material_type: &MaterialType<Self>) -> Self::Gpu;
+
+    /// Upload material to the GPU, looking up its [`MaterialType`] through caches.
+    ///
+    /// Creating the type preprocesses the shaders again to find them in the cache, prefer
+    /// [`MaterialType::instance`] when creating many materials of the same type.
+    fn material(
+        &self,
+        device: &wgpu::Device,
+        bind_groups: &mut BindGroupCache,
+        pipelines: &mut PipelineCache,
+    ) -> Self::Gpu {
+        MaterialType::new(device, bind_groups, pipelines).instance(device, self)
+    }
+}
+
+/// Shaders, bind group layout and pipeline shared by all instances of material `M`.
+///
+/// Instances only own their parameters and bind group, so hundreds of them with different
+/// parameters are drawn with a single pipeline and sorted next to each other.
+///
+/// # Examples
+///
+/// ```ignore
+/// let base = MaterialType::<BaseMaterial>::new(&device, &mut bind_groups, &mut pipelines);
+/// let red = Arc::new(base.instance(&device, &BaseMaterial::new([1.0, 0.0, 0.0])));
+/// let green = Arc::new(base.instance(&device, &BaseMaterial::new([0.0, 1.0, 0.0])));
+/// ```
+pub struct MaterialType<M> {
+    layout: Arc<BindGroupLayout>,
+    pipeline: Arc<RenderPipeline>,
+    phantom: PhantomData<fn() -> M>,
+}
+
+impl<M: AsMaterial> MaterialType<M> {
+    /// Create the layout and pipeline of `M`, shared through caches.
+    pub fn new(
+        device: &wgpu::Device,
+        bind_groups: &mut BindGroupCache,
+        pipelines: &mut PipelineCache,
+    ) -> Self {
+        let layout = M::cached_bind_group_layout(device, bind_groups);
+        let layouts = BindingLayouts::new(device, bind_groups);
+        let pipeline = M::pipeline(device, pipelines, &layouts.with_material(&layout));
+        Self {
+            layout,
+            pipeline,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Upload `material` as an instance of this type.
+    pub fn instance(&self, device: &wgpu::Device, material: &M) -> M::Gpu {
+        material.instance(device, self)
+    }
+
+    /// Create the bind group of `material` with the layout of this type.
+    pub fn prepare_bind_group(&self, device: &wgpu::Device, material: &M) -> PreparedBindGroup {
+        material.prepare_bind_group(device, &self.layout)
+    }
+
+    /// Layout of the material bind group.
+    pub fn layout(&self) -> &Arc<BindGroupLayout> {
+        &self.layout
+    }
+
+    /// Pipeline drawing all instances.
+    pub fn pipeline(&self) -> &Arc<RenderPipeline> {
+        &self.pipeline
+    }
 }
 
-pub trait AsMaterial: AsBindGroup + AsPipeline {
-    fn material(&self, device: &wgpu::Device) -> Box<dyn Material>;
+impl<M> Clone for MaterialType<M> {
+    fn clone(&self) -> Self {
+        Self {
+            layout: self.layout.clone(),
+            pipeline: self.pipeline.clone(),
+            phantom: PhantomData,
+        }
+    }
 }
 
 // TODO: naming
+#[derive(Clone, AsBindGroup)]
 pub struct BaseMaterial {
-    color: [f32; 3],
-    // TODO: change NOW
-    m: [f32; 16],
+    #[uniform(0, visibility(fragment))]
+    pub color: [f32; 3],
 }
 
 impl BaseMaterial {
-    pub fn new(color: [f32; 3], m: [f32; 16]) -> Self {
-        Self { color, m }
+    pub fn new(color: [f32; 3]) -> Self {
+        Self { color }
     }
 }
 
 pub struct BaseMaterialGpu {
-    pipeline: wgpu::RenderPipeline,
-    bind_group: wgpu::BindGroup,
+    pipeline: Arc<wgpu::RenderPipeline>,
+    bind_group: PreparedBindGroup,
 }
 
-impl AsBindGroup for BaseMaterial {
-    fn bind_group(&self, device: &wgpu::Device, layout: &wgpu::BindGroupLayout) -> wgpu::BindGroup {
-        let uniform_buffer = Buffer::new(
-            device,
-            wgpu::BufferUsages::UNIFORM,
-            &[self.color],
-            Some("BaseMaterial color bind group"),
-        );
-
-        let t_buffer = Buffer::new(
-            device,
-            wgpu::BufferUsages::UNIFORM,
-            &[self.m],
-            Some("BaseMaterial transform bind group"),
-        );
-
-        BindGroupBuilder::new()
-            .buffer::<[f32; 3]>(&uniform_buffer, 0..1)
-            .buffer::<[f32; 16]>(&t_buffer, 0..1)
-            .build(device, layout, Some("BaseMaterial bind group"))
+impl BaseMaterialGpu {
+    /// Change color of the material, visible from the next submitted frame.
+    pub fn set_color(&self, queue: &wgpu::Queue, color: [f32; 3]) {
+        self.bind_group.write(queue, 0, &color);
     }
 
-    fn bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
-        LayoutBuilder::new()
-            .uniform_buffer(wgpu::ShaderStages::FRAGMENT, false)
-            .uniform_buffer(wgpu::ShaderStages::VERTEX, false)
-            .build(device, Some("BaseMaterial layout"))
+    /// Upload all parameters of `material`.
+    pub fn update(&self, queue: &wgpu::Queue, material: &BaseMaterial) {
+        material.write_uniforms(queue, &self.bind_group);
     }
 }
 
 impl AsPipeline for BaseMaterial {
-    fn pipeline(&self, device: &wgpu::Device, layout: &PipelineLayout) -> RenderPipeline {
-        let v_shader = Shader::from_string(
+    fn pipeline(
+        device: &wgpu::Device,
+        cache: &mut PipelineCache,
+        bind_group_layouts: &[Arc<BindGroupLayout>],
+    ) -> Arc<RenderPipeline> {
+        let v_shader = cache.shader(
             device,
-            include_str!("./assets/shaders/base_material_vertex.wgsl"),
+            preprocess(
+                "base_material_vertex.wgsl",
+                include_str!("./assets/shaders/base_material_vertex.wgsl"),
+            ),
             wgpu::ShaderStages::VERTEX,
             Some("BaseMaterial vertex shader"),
         );
 
-        let f_shader = Shader::from_string(
+        let f_shader = cache.shader(
             device,
-            include_str!("./assets/shaders/base_material_fragment.wgsl"),
+            preprocess(
+                "base_material_fragment.wgsl",
+                include_str!("./assets/shaders/base_material_fragment.wgsl"),
+            ),
             wgpu::ShaderStages::FRAGMENT,
             Some("BaseMaterial fragment shader"),
         );
 
-        RenderPipelineBuilder::from_layout(&layout, &v_shader)
+        let builder = RenderPipelineBuilder::from_shared_layouts(bind_group_layouts, &*v_shader)
             .color_format(wgpu::TextureFormat::Bgra8UnormSrgb)
             .add_vertex_buffer_layout(MeshVertex::desc())
-            .fragment_shader(&f_shader)
+            .fragment_shader(&*f_shader)
             .cull_mode(Some(wgpu::Face::Back))
-            .multisample(wgpu::MultisampleState::default())
-            .build(&device, Some("Base material pipeline"))
+            .depth_format(Renderer::DEPTH_FORMAT)
+            .multisample(wgpu::MultisampleState::default());
+        cache
+            .get_or_build(device, builder, Some("Base material pipeline"))
+            .unwrap_or_else(|err| panic!("failed to build base material pipeline: {}", err))
     }
 }
 
 impl AsMaterial for BaseMaterial {
-    fn material(&self, device: &wgpu::Device) -> Box<dyn Material> {
-        let bind_layout = Self::bind_group_layout(device);
-        let bind_group = self.bind_group(device, &bind_layout);
-
-        let pipeline_layout =
-            Self::pipeline_layout(device, &bind_layout, Some("BaseMaterial pipeline layout"));
-        let pipeline = self.pipeline(device, &pipeline_layout);
+    type Gpu = BaseMaterialGpu;
 
-        Box::new(BaseMaterialGpu {
-            pipeline,
-            bind_group,
-        })
+    fn instance(&self, device: &wgpu::Device, material_type: &MaterialType<Self>) -> Self::Gpu {
+        BaseMaterialGpu {
+            pipeline: material_type.pipeline().clone(),
+            bind_group: material_type.prepare_bind_group(device, self),
+        }
     }
 }
 
 impl Material for BaseMaterialGpu {
-    fn begin_render_pass<'a>(
-        &'a self,
-        _device: &wgpu::Device,
-        encoder: &'a mut wgpu::CommandEncoder,
-        rp_desc: &'a wgpu::RenderPassDescriptor,
-        // transform_data: &TransformBindGroup,
-        // lighting_data: &LightingBindGroup,
-    ) -> wgpu::RenderPass<'a> {
-        let mut rend_pass = encoder.begin_render_pass(rp_desc);
-
-        rend_pass.set_pipeline(&self.pipeline);
-        rend_pass.set_bind_group(0, &self.bind_group, &[]);
+    fn pipeline(&self) -> &wgpu::RenderPipeline {
+        &self.pipeline
+    }
 
-        rend_pass
+    fn bind_group(&self) -> &wgpu::BindGroup {
+        &self.bind_group.bind_group
     }
 }
+
+// Expand imports of the base material shaders.
+fn preprocess(file: &str, source: &str) -> String {
+    super::shader_modules(Preprocessor::new())
+        .process(file, source)
+        .unwrap_or_else(|err| panic!("failed to preprocess base material shader: {}", err))
+        .source
+}
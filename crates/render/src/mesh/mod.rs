@@ -7,10 +7,22 @@ pub mod pbr;
 use bytemuck::{Pod, Zeroable};
 use wgpu::Device;
 
-use super::prelude::{IndexBuffer, VertexBuffer, VertexDesc};
+use super::buffers::vertices::Vertex;
+use super::prelude::{IndexBuffer, VertexBuffer};
+use super::shader_preprocessor::Preprocessor;
+
+/// Register shader modules of the mesh renderer, e.g. `mesh::vertex_output`, for `#import`.
+///
+/// Also registers `render::bindings`, see [`crate::bindings::shader_module`].
+pub fn shader_modules(preprocessor: Preprocessor) -> Preprocessor {
+    crate::bindings::shader_module(preprocessor).module(
+        "mesh::vertex_output",
+        include_str!("./assets/shaders/vertex_output.wgsl"),
+    )
+}
 
 #[repr(C)]
-#[derive(Copy, Clone, Pod, Zeroable)]
+#[derive(Copy, Clone, Pod, Zeroable, Vertex)]
 pub struct MeshVertex {
     pub position: [f32; 3],
     pub texcoords: [f32; 2],
@@ -19,47 +31,6 @@ pub struct MeshVertex {
     // pub color: [f32; 4],
 }
 
-impl VertexDesc for MeshVertex {
-    fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
-        wgpu::VertexBufferLayout {
-            array_stride: std::mem::size_of::<MeshVertex>() as wgpu::BufferAddress,
-            step_mode: wgpu::VertexStepMode::Vertex,
-            attributes: &[
-                wgpu::VertexAttribute {
-                    // position
-                    format: wgpu::VertexFormat::Float32x3,
-                    offset: 0,
-                    shader_location: 0,
-                },
-                wgpu::VertexAttribute {
-                    // texcoord
-                    format: wgpu::VertexFormat::Float32x2,
-                    offset: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
-                    shader_location: 1,
-                },
-                wgpu::VertexAttribute {
-                    // normal
-                    format: wgpu::VertexFormat::Float32x3,
-                    offset: std::mem::size_of::<[f32; 5]>() as wgpu::BufferAddress,
-                    shader_location: 2,
-                },
-                // wgpu::VertexAttribute {
-                //     // tangent
-                //     format: wgpu::VertexFormat::Float32x4,
-                //     offset: std::mem::size_of::<[f32; 8]>() as wgpu::BufferAddress,
-                //     shader_location: 3,
-                // },
-                // wgpu::VertexAttribute {
-                //     // color
-                //     format: wgpu::VertexFormat::Float32x4,
-                //     offset: std::mem::size_of::<[f32; 12]>() as wgpu::BufferAddress,
-                //     shader_location: 4,
-                // },
-            ],
-        }
-    }
-}
-
 pub struct Mesh {
     verticies: Vec<MeshVertex>,
     // TODO: decide if it's always a u32
@@ -79,7 +50,7 @@ impl Mesh {
         let index_buffer = self
             .indicies
             .as_ref()
-            .map(|i| IndexBuffer::new(device, &i, Some("Index buffer")));
+            .map(|i| IndexBuffer::new(device, i, Some("Index buffer")));
 
         GpuMesh {
             vertex_buffer,
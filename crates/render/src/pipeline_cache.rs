@@ -0,0 +1,277 @@
+//!
+//! Deduplication of shaders and render pipelines
+//!
+//! Materials of the same type describe the same pipeline, so building it once per material
+//! instance wastes both shader compilation and GPU memory. A [`PipelineCache`] compiles every
+//! shader source once and builds every distinct [`RenderPipelineBuilder`] state once, handing
+//! out shared [`Arc`]s. It belongs to a single [`wgpu::Device`] and must always be used with it.
+//!
+//! # Examples
+//!
+//! ```ignore
+//! let mut cache = PipelineCache::new();
+//!
+//! let shader = cache.shader(device, SOURCE, wgpu::ShaderStages::VERTEX_FRAGMENT, Some("Shader"));
+//! let builder = RenderPipelineBuilder::from_shared_layouts(&bind_group_layouts, &*shader)
+//!     .add_vertex_buffer_layout(MeshVertex::desc());
+//! let pipeline = cache.get_or_build(device, builder, Some("Pipeline"))?;
+//!
+//! assert_eq!(cache.stats().misses, 1);
+//! ```
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::render_pipleine_builder::{PipelineError, PipelineKey, RenderPipelineBuilder};
+use crate::shader::Shader;
+
+/// Counters of [`PipelineCache`] usage.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PipelineCacheStats {
+    /// Requests answered with an existing pipeline.
+    pub hits: u64,
+    /// Requests that built a new pipeline.
+    pub misses: u64,
+    /// Requests with builders without a [`RenderPipelineBuilder::key`], built without caching.
+    pub uncached: u64,
+    /// Number of cached pipelines.
+    pub pipelines: usize,
+    /// Number of cached shaders.
+    pub shaders: usize,
+}
+
+/// Cache of shaders and render pipelines for one device.
+#[derive(Debug, Default)]
+pub struct PipelineCache {
+    shaders: HashMap<(String, wgpu::ShaderStages), Arc<Shader>>,
+    pipelines: HashMap<PipelineKey, Arc<wgpu::RenderPipeline>>,
+    hits: u64,
+    misses: u64,
+    uncached: u64,
+}
+
+impl PipelineCache {
+    /// Create an empty cache.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the shader compiled from WGSL `source` for `stage`, compiling it on first request.
+    ///
+    /// `label` is only used when the shader is compiled.
+    pub fn shader(
+        &mut self,
+        device: &wgpu::Device,
+        source: impl Into<Cow<'static, str>>,
+        stage: wgpu::ShaderStages,
+        label: Option<&str>,
+    ) -> Arc<Shader> {
+        let source = source.into();
+        let key = (source.to_string(), stage);
+        let shader = self
+            .shaders
+            .entry(key)
+            .or_insert_with(|| Arc::new(Shader::from_string(device, source, stage, label)));
+        Arc::clone(shader)
+    }
+
+    /// Get the pipeline `builder` describes, building it on first request.
+    ///
+    /// `label` is only used when the pipeline is built. Failed builds are not cached, neither are
+    /// builders without a [`RenderPipelineBuilder::key`], which are built on every request and
+    /// counted as [`PipelineCacheStats::uncached`] rather than misses.
+    pub fn get_or_build(
+        &mut self,
+        device: &wgpu::Device,
+        builder: RenderPipelineBuilder,
+        label: Option<&str>,
+    ) -> Result<Arc<wgpu::RenderPipeline>, PipelineError> {
+        let Some(key) = builder.key() else {
+            self.uncached += 1;
+            return builder.try_build(device, label).map(Arc::new);
+        };
+        if let Some(pipeline) = self.pipelines.get(&key) {
+            self.hits += 1;
+            return Ok(Arc::clone(pipeline));
+        }
+
+        self.misses += 1;
+        let pipeline = Arc::new(builder.try_build(device, label)?);
+        self.pipelines.insert(key, Arc::clone(&pipeline));
+        Ok(pipeline)
+    }
+
+    /// Usage counters and cache size.
+    pub fn stats(&self) -> PipelineCacheStats {
+        PipelineCacheStats {
+            hits: self.hits,
+            misses: self.misses,
+            uncached: self.uncached,
+            pipelines: self.pipelines.len(),
+            shaders: self.shaders.len(),
+        }
+    }
+
+    /// Reset request counters.
+    pub fn reset_stats(&mut self) {
+        self.hits = 0;
+        self.misses = 0;
+        self.uncached = 0;
+    }
+
+    /// Drop all cached shaders and pipelines, e.g. after shaders were reloaded.
+    pub fn clear(&mut self) {
+        self.shaders.clear();
+        self.pipelines.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shader_preprocessor::Preprocessor;
+
+    const SOURCE: &str = "
+        @vertex
+        fn vertex(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+            return vec4<f32>(f32(index), 0.0, 0.0, 1.0);
+        }
+
+        @fragment
+        fn fragment() -> @location(0) vec4<f32> {
+        #ifdef RED
+            return vec4<f32>(1.0, 0.0, 0.0, 1.0);
+        #else
+            return vec4<f32>(1.0);
+        #endif
+        }
+    ";
+
+    fn device() -> wgpu::Device {
+        let instance = wgpu::Instance::new(wgpu::Backends::all());
+        let adapter = pollster::block_on(instance.request_adapter(&Default::default()))
+            .expect("no adapter to test with");
+        let (device, _) = pollster::block_on(adapter.request_device(&Default::default(), None))
+            .expect("failed to create device");
+        device
+    }
+
+    fn shader(
+        cache: &mut PipelineCache,
+        device: &wgpu::Device,
+        preprocessor: Preprocessor,
+    ) -> Arc<Shader> {
+        let source = preprocessor.process("shader.wgsl", SOURCE).unwrap().source;
+        cache.shader(device, source, wgpu::ShaderStages::VERTEX_FRAGMENT, None)
+    }
+
+    fn builder(shader: &Shader) -> RenderPipelineBuilder<'_> {
+        RenderPipelineBuilder::from_reflection(shader)
+            .vertex_entry_point("vertex")
+            .fragment_entry_point("fragment")
+            .color_format(wgpu::TextureFormat::Rgba8Unorm)
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter"]
+    fn identical_builders_have_equal_keys() {
+        let device = device();
+        let mut cache = PipelineCache::new();
+        let a = shader(&mut cache, &device, Preprocessor::new());
+        let b = shader(&mut cache, &device, Preprocessor::new());
+
+        assert!(Arc::ptr_eq(&a, &b));
+        assert_eq!(cache.stats().shaders, 1);
+        assert!(builder(&a).key().is_some());
+        assert_eq!(builder(&a).key(), builder(&b).key());
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter"]
+    fn changed_state_changes_key() {
+        let device = device();
+        let mut cache = PipelineCache::new();
+        let shader = shader(&mut cache, &device, Preprocessor::new());
+        let key = builder(&shader).key();
+
+        assert_ne!(
+            builder(&shader).cull_mode(Some(wgpu::Face::Back)).key(),
+            key
+        );
+        assert_ne!(
+            builder(&shader)
+                .color_format(wgpu::TextureFormat::Bgra8Unorm)
+                .key(),
+            key
+        );
+        assert_ne!(builder(&shader).depth_bias_slope_scale(1.0).key(), key);
+        assert_ne!(
+            builder(&shader)
+                .add_vertex_buffer_layout(wgpu::VertexBufferLayout {
+                    array_stride: 16,
+                    step_mode: wgpu::VertexStepMode::Vertex,
+                    attributes: &[],
+                })
+                .key(),
+            key
+        );
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter"]
+    fn changed_defines_change_key() {
+        let device = device();
+        let mut cache = PipelineCache::new();
+        let white = shader(&mut cache, &device, Preprocessor::new());
+        let red = shader(&mut cache, &device, Preprocessor::new().define("RED", ""));
+
+        assert_eq!(cache.stats().shaders, 2);
+        assert_ne!(builder(&white).key(), builder(&red).key());
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter"]
+    fn counts_hits_and_misses() {
+        let device = device();
+        let mut cache = PipelineCache::new();
+        let shader = shader(&mut cache, &device, Preprocessor::new());
+
+        let a = cache.get_or_build(&device, builder(&shader), None).unwrap();
+        let b = cache.get_or_build(&device, builder(&shader), None).unwrap();
+
+        assert!(Arc::ptr_eq(&a, &b));
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.uncached), (1, 1, 0));
+        assert_eq!(stats.pipelines, 1);
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter"]
+    fn counts_keyless_builders_as_uncached() {
+        let device = device();
+        let mut cache = PipelineCache::new();
+        let shader = shader(&mut cache, &device, Preprocessor::new());
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[],
+            push_constant_ranges: &[],
+        });
+        let keyless = || {
+            RenderPipelineBuilder::from_layout(&layout, &*shader)
+                .vertex_entry_point("vertex")
+                .fragment_entry_point("fragment")
+                .color_format(wgpu::TextureFormat::Rgba8Unorm)
+        };
+
+        assert_eq!(keyless().key(), None);
+        cache.get_or_build(&device, keyless(), None).unwrap();
+        cache.get_or_build(&device, keyless(), None).unwrap();
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.uncached), (0, 0, 2));
+        assert_eq!(stats.pipelines, 0);
+
+        cache.reset_stats();
+        assert_eq!(cache.stats().uncached, 0);
+    }
+}
@@ -0,0 +1,809 @@
+//!
+//! Render pipelines described in RON or TOML files
+//!
+//! A [`PipelineDescription`] names shader files, entry points and vertex layouts and spells out
+//! primitive, blend, depth and multisample state, so it can be tweaked without recompiling. Field
+//! and variant names follow [`RenderPipelineBuilder`] methods and `wgpu` types. Everything but
+//! the vertex shader may be omitted to use builder defaults.
+//!
+//! # Examples
+//!
+//! ```ron
+//! (
+//!     label: Some("Base material"),
+//!     vertex: (path: "base_material_vertex.wgsl"),
+//!     fragment: Some((path: "base_material_fragment.wgsl", entry_point: Some("fragment"))),
+//!     vertex_layouts: ["MeshVertex"],
+//!     primitive: (cull_mode: Some(Back)),
+//!     color_targets: [(format: Bgra8UnormSrgb, blend: Alpha, write_mask: "rgba")],
+//!     depth: Some((format: Depth32Float, compare: LessEqual)),
+//! )
+//! ```
+//!
+//! ```toml
+//! label = "Base material"
+//! vertex_layouts = ["MeshVertex"]
+//!
+//! [vertex]
+//! path = "base_material_vertex.wgsl"
+//!
+//! [fragment]
+//! path = "base_material_fragment.wgsl"
+//!
+//! [primitive]
+//! cull_mode = "Back"
+//!
+//! [[color_targets]]
+//! format = "Bgra8UnormSrgb"
+//! blend = "Alpha"
+//! ```
+//!
+//! ```ignore
+//! let description = PipelineDescription::load("assets/pipelines/base_material.ron")?;
+//! let shaders = description.load_shaders(device, &mut pipeline_cache, "assets/pipelines")?;
+//! let builder = description.builder(&bind_group_layouts, &shaders, &VertexLayouts::new())?;
+//! let pipeline = pipeline_cache.get_or_build(device, builder, description.label.as_deref())?;
+//! ```
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use serde::Deserialize;
+
+use crate::buffers::vertices::Vertex;
+use crate::mesh::MeshVertex;
+use crate::pipeline_cache::PipelineCache;
+use crate::render_pipleine_builder::RenderPipelineBuilder;
+use crate::shader::{Shader, ShaderError};
+use crate::shader_preprocessor::Preprocessor;
+
+/// Render pipeline state loaded from a file.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PipelineDescription {
+    #[serde(default)]
+    pub label: Option<String>,
+    pub vertex: ShaderDescription,
+    #[serde(default)]
+    pub fragment: Option<ShaderDescription>,
+    /// Names of vertex buffer layouts registered in [`VertexLayouts`], in slot order.
+    #[serde(default)]
+    pub vertex_layouts: Vec<String>,
+    #[serde(default)]
+    pub primitive: PrimitiveDescription,
+    /// Color targets in attachment order, empty for a single default target.
+    #[serde(default)]
+    pub color_targets: Vec<ColorTargetDescription>,
+    #[serde(default)]
+    pub depth: Option<DepthDescription>,
+    #[serde(default = "default_sample_count")]
+    pub sample_count: u32,
+}
+
+/// WGSL file and entry point of a shader stage.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ShaderDescription {
+    /// Path to WGSL source, relative to the directory passed to
+    /// [`PipelineDescription::load_shaders`].
+    pub path: PathBuf,
+    /// Entry point, builder default when omitted.
+    #[serde(default)]
+    pub entry_point: Option<String>,
+}
+
+/// Primitive assembly and rasterization state.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PrimitiveDescription {
+    pub topology: PrimitiveTopology,
+    pub front_face: FrontFace,
+    pub cull_mode: Option<Face>,
+    pub polygon_mode: PolygonMode,
+}
+
+/// Format, blending and write mask of one color target.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ColorTargetDescription {
+    pub format: TextureFormat,
+    #[serde(default)]
+    pub blend: Blend,
+    /// Written channels as a subset of `"rgba"`.
+    #[serde(default = "default_write_mask")]
+    pub write_mask: String,
+}
+
+/// Blending of a color target.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub enum Blend {
+    /// Blending of [`RenderPipelineBuilder::DEFAULT_BLEND_STATE`].
+    #[default]
+    Default,
+    /// No blending, output replaces the target.
+    Replace,
+    /// Standard alpha blending.
+    Alpha,
+    /// Alpha blending with color premultiplied by alpha.
+    PremultipliedAlpha,
+    /// Output is added to the target.
+    Additive,
+    /// Fully specified blending.
+    Custom {
+        color: BlendComponent,
+        alpha: BlendComponent,
+    },
+}
+
+/// Blending of color or alpha channels.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BlendComponent {
+    pub src_factor: BlendFactor,
+    pub dst_factor: BlendFactor,
+    #[serde(default)]
+    pub operation: BlendOperation,
+}
+
+/// Depth test and depth bias.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DepthDescription {
+    pub format: TextureFormat,
+    pub write_enabled: bool,
+    pub compare: CompareFunction,
+    pub bias_constant: i32,
+    pub bias_slope_scale: f32,
+    pub bias_clamp: f32,
+}
+
+impl Default for DepthDescription {
+    fn default() -> Self {
+        type Builder<'a> = RenderPipelineBuilder<'a>;
+        Self {
+            format: TextureFormat::Depth32Float,
+            write_enabled: Builder::DEFAULT_DEPTH_WRITE_ENABLED,
+            compare: CompareFunction::LessEqual,
+            bias_constant: Builder::DEFAULT_DEPTH_BIAS_CONSTANT,
+            bias_slope_scale: Builder::DEFAULT_DEPTH_BIAS_SLOPE_SCALE,
+            bias_clamp: Builder::DEFAULT_DEPTH_BIAS_CLAMP,
+        }
+    }
+}
+
+fn default_sample_count() -> u32 {
+    RenderPipelineBuilder::DEFAULT_SAMPLE_COUNT
+}
+
+fn default_write_mask() -> String {
+    "rgba".to_string()
+}
+
+/// Shaders of a [`PipelineDescription`], compiled and ready to be borrowed by a builder.
+#[derive(Debug, Clone)]
+pub struct PipelineShaders {
+    pub vertex: Arc<Shader>,
+    pub fragment: Option<Arc<Shader>>,
+}
+
+/// Vertex buffer layouts that descriptions may refer to by name.
+#[derive(Debug, Clone)]
+pub struct VertexLayouts {
+    layouts: HashMap<String, wgpu::VertexBufferLayout<'static>>,
+}
+
+impl VertexLayouts {
+    /// Registry with vertex types of this crate, e.g. `MeshVertex`.
+    pub fn new() -> Self {
+        Self {
+            layouts: HashMap::new(),
+        }
+        .register::<MeshVertex>("MeshVertex")
+    }
+
+    /// Make layout of `V` available under `name`.
+    pub fn register<V: Vertex>(self, name: &str) -> Self {
+        self.register_layout(name, V::desc())
+    }
+
+    /// Make `layout` available under `name`.
+    pub fn register_layout(
+        mut self,
+        name: &str,
+        layout: wgpu::VertexBufferLayout<'static>,
+    ) -> Self {
+        self.layouts.insert(name.to_string(), layout);
+        self
+    }
+
+    /// Layout registered under `name`.
+    pub fn get(&self, name: &str) -> Option<&wgpu::VertexBufferLayout<'static>> {
+        self.layouts.get(name)
+    }
+}
+
+impl Default for VertexLayouts {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PipelineDescription {
+    /// Parse description from RON.
+    pub fn from_ron(source: &str) -> Result<Self, DescriptionError> {
+        ron::from_str(source).map_err(|err| DescriptionError::Parse(err.to_string()))
+    }
+
+    /// Parse description from TOML.
+    pub fn from_toml(source: &str) -> Result<Self, DescriptionError> {
+        toml::from_str(source).map_err(|err| DescriptionError::Parse(err.to_string()))
+    }
+
+    /// Read description from a `.ron` or `.toml` file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, DescriptionError> {
+        let path = path.as_ref();
+        let source = read(path)?;
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("ron") => Self::from_ron(&source),
+            Some("toml") => Self::from_toml(&source),
+            _ => Err(DescriptionError::UnknownFormat(path.to_path_buf())),
+        }
+    }
+
+    /// Read, preprocess and compile shaders, paths are resolved relative to `base_dir`.
+    ///
+    /// Shaders may `#import` the mesh modules, see [`crate::mesh::shader_modules`], and are
+    /// validated with naga before compiling. Vertex and fragment shaders from the same file share
+    /// one module.
+    pub fn load_shaders(
+        &self,
+        device: &wgpu::Device,
+        cache: &mut PipelineCache,
+        base_dir: impl AsRef<Path>,
+    ) -> Result<PipelineShaders, DescriptionError> {
+        let base_dir = base_dir.as_ref();
+        let preprocessor = crate::mesh::shader_modules(Preprocessor::new());
+        let fragment_path = self.fragment.as_ref().map(|fragment| &fragment.path);
+        let shared = fragment_path == Some(&self.vertex.path);
+
+        let stage = if shared {
+            wgpu::ShaderStages::VERTEX_FRAGMENT
+        } else {
+            wgpu::ShaderStages::VERTEX
+        };
+        let path = base_dir.join(&self.vertex.path);
+        let label = path.to_string_lossy();
+        let source = process(&preprocessor, &path)?;
+        let vertex = cache.shader(device, source, stage, Some(&label));
+
+        let fragment = match fragment_path {
+            Some(_) if shared => Some(Arc::clone(&vertex)),
+            Some(fragment_path) => {
+                let path = base_dir.join(fragment_path);
+                let label = path.to_string_lossy();
+                let stage = wgpu::ShaderStages::FRAGMENT;
+                let source = process(&preprocessor, &path)?;
+                Some(cache.shader(device, source, stage, Some(&label)))
+            }
+            None => None,
+        };
+
+        Ok(PipelineShaders { vertex, fragment })
+    }
+
+    /// Create builder with the described state, using `shaders` loaded from this description.
+    ///
+    /// `bind_group_layouts` are given in group order and kept alive by the builder, so the
+    /// pipeline can be shared through [`PipelineCache`].
+    pub fn builder<'a>(
+        &'a self,
+        bind_group_layouts: &[Arc<wgpu::BindGroupLayout>],
+        shaders: &'a PipelineShaders,
+        vertex_layouts: &VertexLayouts,
+    ) -> Result<RenderPipelineBuilder<'a>, DescriptionError> {
+        let mut builder =
+            RenderPipelineBuilder::from_shared_layouts(bind_group_layouts, &*shaders.vertex);
+        if let Some(entry_point) = &self.vertex.entry_point {
+            builder = builder.vertex_entry_point(entry_point);
+        }
+        if let (Some(fragment), Some(shader)) = (&self.fragment, &shaders.fragment) {
+            builder = builder.fragment_shader(&**shader);
+            if let Some(entry_point) = &fragment.entry_point {
+                builder = builder.fragment_entry_point(entry_point);
+            }
+        }
+
+        for layout in self.vertex_buffer_layouts(vertex_layouts)? {
+            builder = builder.add_vertex_buffer_layout(layout);
+        }
+
+        let primitive = &self.primitive;
+        builder = builder
+            .primitive_topology(primitive.topology.into())
+            .front_face(primitive.front_face.into())
+            .cull_mode(primitive.cull_mode.map(Into::into))
+            .polygon_mode(primitive.polygon_mode.into());
+
+        for target in &self.color_targets {
+            let write_mask = write_mask(&target.write_mask)?;
+            let blend = target.blend.state();
+            builder = builder.color_target(target.format.into(), |state| {
+                state.blend(blend).write_mask(write_mask)
+            });
+        }
+
+        if let Some(depth) = &self.depth {
+            builder = builder
+                .depth_format(depth.format.into())
+                .depth_write_enabled(depth.write_enabled)
+                .depth_compare(depth.compare.into())
+                .depth_bias(wgpu::DepthBiasState {
+                    constant: depth.bias_constant,
+                    slope_scale: depth.bias_slope_scale,
+                    clamp: depth.bias_clamp,
+                });
+        }
+
+        Ok(builder.sample_count(self.sample_count))
+    }
+
+    // Layouts of `vertex_layouts` named by the description, in slot order.
+    fn vertex_buffer_layouts(
+        &self,
+        vertex_layouts: &VertexLayouts,
+    ) -> Result<Vec<wgpu::VertexBufferLayout<'static>>, DescriptionError> {
+        self.vertex_layouts
+            .iter()
+            .map(|name| {
+                vertex_layouts
+                    .get(name)
+                    .cloned()
+                    .ok_or_else(|| DescriptionError::UnknownVertexLayout(name.clone()))
+            })
+            .collect()
+    }
+}
+
+impl Blend {
+    /// Blend state of a color target, `None` for [`Blend::Replace`].
+    pub fn state(&self) -> Option<wgpu::BlendState> {
+        match self {
+            Self::Default => Some(RenderPipelineBuilder::DEFAULT_BLEND_STATE),
+            Self::Replace => None,
+            Self::Alpha => Some(wgpu::BlendState::ALPHA_BLENDING),
+            Self::PremultipliedAlpha => Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+            Self::Additive => {
+                let additive = wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::One,
+                    dst_factor: wgpu::BlendFactor::One,
+                    operation: wgpu::BlendOperation::Add,
+                };
+                Some(wgpu::BlendState {
+                    color: additive,
+                    alpha: additive,
+                })
+            }
+            Self::Custom { color, alpha } => Some(wgpu::BlendState {
+                color: (*color).into(),
+                alpha: (*alpha).into(),
+            }),
+        }
+    }
+}
+
+impl From<BlendComponent> for wgpu::BlendComponent {
+    fn from(component: BlendComponent) -> Self {
+        Self {
+            src_factor: component.src_factor.into(),
+            dst_factor: component.dst_factor.into(),
+            operation: component.operation.into(),
+        }
+    }
+}
+
+fn write_mask(channels: &str) -> Result<wgpu::ColorWrites, DescriptionError> {
+    let mut mask = wgpu::ColorWrites::empty();
+    for channel in channels.chars() {
+        mask |= match channel {
+            'r' => wgpu::ColorWrites::RED,
+            'g' => wgpu::ColorWrites::GREEN,
+            'b' => wgpu::ColorWrites::BLUE,
+            'a' => wgpu::ColorWrites::ALPHA,
+            _ => return Err(DescriptionError::WriteMask(channels.to_string())),
+        };
+    }
+    Ok(mask)
+}
+
+fn read(path: &Path) -> Result<String, DescriptionError> {
+    std::fs::read_to_string(path).map_err(|err| DescriptionError::Io(path.to_path_buf(), err))
+}
+
+// Expand directives of shader at `path` and validate the result.
+fn process(preprocessor: &Preprocessor, path: &Path) -> Result<String, DescriptionError> {
+    let processed = preprocessor
+        .process_file(path)
+        .map_err(|err| DescriptionError::Shader(err.into()))?;
+    processed
+        .validate()
+        .map_err(|err| DescriptionError::Shader(err.into()))?;
+    Ok(processed.source)
+}
+
+/// Reasons for a pipeline description to fail loading.
+#[derive(Debug)]
+pub enum DescriptionError {
+    /// File could not be read.
+    Io(PathBuf, std::io::Error),
+    /// File extension is neither `ron` nor `toml`.
+    UnknownFormat(PathBuf),
+    /// Description failed to parse, contains formatted parser error.
+    Parse(String),
+    /// Shader failed to preprocess or is invalid WGSL.
+    Shader(ShaderError),
+    /// No vertex layout registered under given name.
+    UnknownVertexLayout(String),
+    /// Write mask contains characters other than `rgba`.
+    WriteMask(String),
+}
+
+impl fmt::Display for DescriptionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(path, err) => write!(f, "failed to read {}: {}", path.display(), err),
+            Self::UnknownFormat(path) => write!(
+                f,
+                "{} is neither a `.ron` nor a `.toml` file",
+                path.display()
+            ),
+            Self::Parse(err) => write!(f, "failed to parse pipeline description: {}", err),
+            Self::Shader(err) => write!(f, "invalid pipeline shader: {}", err),
+            Self::UnknownVertexLayout(name) => write!(f, "unknown vertex layout `{}`", name),
+            Self::WriteMask(mask) => write!(
+                f,
+                "write mask `{}` may only contain `r`, `g`, `b` and `a`",
+                mask
+            ),
+        }
+    }
+}
+
+impl std::error::Error for DescriptionError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(_, err) => Some(err),
+            Self::Shader(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+// Deserializable copies of `wgpu` enums, which only implement serde with `replay` feature.
+macro_rules! mirror_enum {
+    ($(#[$meta:meta])* $name:ident => $wgpu:ident { $($(#[$vmeta:meta])* $variant:ident),* $(,)? }) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+        pub enum $name {
+            $($(#[$vmeta])* $variant),*
+        }
+
+        impl From<$name> for wgpu::$wgpu {
+            fn from(value: $name) -> Self {
+                match value {
+                    $($name::$variant => wgpu::$wgpu::$variant),*
+                }
+            }
+        }
+    };
+}
+
+mirror_enum!(
+    /// Mirror of [`wgpu::TextureFormat`], limited to formats usable as render targets.
+    TextureFormat => TextureFormat {
+        R8Unorm, R8Uint, R8Sint, R16Uint, R16Sint, R16Float, Rg8Unorm, Rg8Uint, Rg8Sint,
+        R32Uint, R32Sint, R32Float, Rg16Uint, Rg16Sint, Rg16Float, Rgba8Unorm, Rgba8UnormSrgb,
+        Rgba8Uint, Rgba8Sint, Bgra8Unorm, Bgra8UnormSrgb, Rgb10a2Unorm, Rg11b10Float, Rg32Uint,
+        Rg32Sint, Rg32Float, Rgba16Uint, Rgba16Sint, Rgba16Float, Rgba32Uint, Rgba32Sint,
+        Rgba32Float, Depth32Float, Depth32FloatStencil8, Depth24Plus, Depth24PlusStencil8,
+        Depth24UnormStencil8,
+    }
+);
+
+mirror_enum!(
+    /// Mirror of [`wgpu::PrimitiveTopology`].
+    #[derive(Default)]
+    PrimitiveTopology => PrimitiveTopology {
+        PointList, LineList, LineStrip, #[default] TriangleList, TriangleStrip,
+    }
+);
+
+mirror_enum!(
+    /// Mirror of [`wgpu::FrontFace`].
+    #[derive(Default)]
+    FrontFace => FrontFace { #[default] Ccw, Cw }
+);
+
+mirror_enum!(
+    /// Mirror of [`wgpu::Face`].
+    Face => Face { Front, Back }
+);
+
+mirror_enum!(
+    /// Mirror of [`wgpu::PolygonMode`].
+    #[derive(Default)]
+    PolygonMode => PolygonMode { #[default] Fill, Line, Point }
+);
+
+mirror_enum!(
+    /// Mirror of [`wgpu::CompareFunction`].
+    CompareFunction => CompareFunction {
+        Never, Less, Equal, LessEqual, Greater, NotEqual, GreaterEqual, Always,
+    }
+);
+
+mirror_enum!(
+    /// Mirror of [`wgpu::BlendFactor`].
+    BlendFactor => BlendFactor {
+        Zero, One, Src, OneMinusSrc, SrcAlpha, OneMinusSrcAlpha, Dst, OneMinusDst, DstAlpha,
+        OneMinusDstAlpha, SrcAlphaSaturated, Constant, OneMinusConstant,
+    }
+);
+
+mirror_enum!(
+    /// Mirror of [`wgpu::BlendOperation`].
+    #[derive(Default)]
+    BlendOperation => BlendOperation { #[default] Add, Subtract, ReverseSubtract, Min, Max }
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RON: &str = r#"(
+    label: Some("Base material"),
+    vertex: (path: "base_material_vertex.wgsl"),
+    fragment: Some((path: "base_material_fragment.wgsl", entry_point: Some("fragment"))),
+    vertex_layouts: ["MeshVertex"],
+    primitive: (cull_mode: Some(Back)),
+    color_targets: [(format: Bgra8UnormSrgb, blend: Alpha, write_mask: "rgb")],
+    depth: Some((format: Depth32Float, compare: Less)),
+    sample_count: 4,
+)"#;
+
+    const TOML: &str = r#"
+label = "Base material"
+vertex_layouts = ["MeshVertex"]
+
+[vertex]
+path = "base_material_vertex.wgsl"
+entry_point = "vertex"
+
+[fragment]
+path = "base_material_fragment.wgsl"
+
+[primitive]
+topology = "LineList"
+cull_mode = "Back"
+
+[[color_targets]]
+format = "Bgra8UnormSrgb"
+blend = "Alpha"
+"#;
+
+    fn description(vertex_layouts: &[&str]) -> PipelineDescription {
+        let mut description =
+            PipelineDescription::from_ron(r#"(vertex: (path: "v.wgsl"))"#).unwrap();
+        description.vertex_layouts = vertex_layouts.iter().map(|name| name.to_string()).collect();
+        description
+    }
+
+    #[test]
+    fn parses_ron() {
+        let description = PipelineDescription::from_ron(RON).unwrap();
+        assert_eq!(description.label.as_deref(), Some("Base material"));
+        assert_eq!(
+            description.vertex.path,
+            Path::new("base_material_vertex.wgsl")
+        );
+        assert_eq!(description.vertex.entry_point, None);
+        let fragment = description.fragment.unwrap();
+        assert_eq!(fragment.path, Path::new("base_material_fragment.wgsl"));
+        assert_eq!(fragment.entry_point.as_deref(), Some("fragment"));
+        assert_eq!(description.vertex_layouts, ["MeshVertex"]);
+        assert_eq!(description.primitive.cull_mode, Some(Face::Back));
+        assert_eq!(
+            description.primitive.topology,
+            PrimitiveTopology::TriangleList
+        );
+
+        let target = &description.color_targets[0];
+        assert_eq!(target.format, TextureFormat::Bgra8UnormSrgb);
+        assert!(matches!(target.blend, Blend::Alpha));
+        assert_eq!(target.write_mask, "rgb");
+
+        let depth = description.depth.unwrap();
+        assert_eq!(depth.format, TextureFormat::Depth32Float);
+        assert_eq!(depth.compare, CompareFunction::Less);
+        assert_eq!(description.sample_count, 4);
+    }
+
+    #[test]
+    fn parses_toml() {
+        let description = PipelineDescription::from_toml(TOML).unwrap();
+        assert_eq!(description.label.as_deref(), Some("Base material"));
+        assert_eq!(description.vertex.entry_point.as_deref(), Some("vertex"));
+        assert_eq!(
+            description.fragment.unwrap().path,
+            Path::new("base_material_fragment.wgsl")
+        );
+        assert_eq!(description.vertex_layouts, ["MeshVertex"]);
+        assert_eq!(description.primitive.topology, PrimitiveTopology::LineList);
+        assert_eq!(description.primitive.cull_mode, Some(Face::Back));
+        assert_eq!(description.color_targets.len(), 1);
+        assert!(description.depth.is_none());
+    }
+
+    #[test]
+    fn applies_defaults() {
+        let description = description(&[]);
+        assert!(description.label.is_none());
+        assert!(description.fragment.is_none());
+        assert!(description.vertex_layouts.is_empty());
+        assert_eq!(
+            description.primitive.topology,
+            PrimitiveTopology::TriangleList
+        );
+        assert_eq!(description.primitive.front_face, FrontFace::Ccw);
+        assert_eq!(description.primitive.cull_mode, None);
+        assert_eq!(description.primitive.polygon_mode, PolygonMode::Fill);
+        assert!(description.color_targets.is_empty());
+        assert!(description.depth.is_none());
+        assert_eq!(
+            description.sample_count,
+            RenderPipelineBuilder::DEFAULT_SAMPLE_COUNT
+        );
+    }
+
+    #[test]
+    fn applies_target_and_depth_defaults() {
+        let description = PipelineDescription::from_ron(
+            r#"(
+    vertex: (path: "v.wgsl"),
+    color_targets: [(format: Rgba8Unorm)],
+    depth: Some((format: Depth24Plus)),
+)"#,
+        )
+        .unwrap();
+        let target = &description.color_targets[0];
+        assert!(matches!(target.blend, Blend::Default));
+        assert_eq!(target.write_mask, "rgba");
+
+        type Builder<'a> = RenderPipelineBuilder<'a>;
+        let depth = description.depth.unwrap();
+        assert_eq!(depth.format, TextureFormat::Depth24Plus);
+        assert_eq!(depth.write_enabled, Builder::DEFAULT_DEPTH_WRITE_ENABLED);
+        assert_eq!(depth.compare, CompareFunction::LessEqual);
+        assert_eq!(depth.bias_constant, Builder::DEFAULT_DEPTH_BIAS_CONSTANT);
+        assert_eq!(
+            depth.bias_slope_scale,
+            Builder::DEFAULT_DEPTH_BIAS_SLOPE_SCALE
+        );
+        assert_eq!(depth.bias_clamp, Builder::DEFAULT_DEPTH_BIAS_CLAMP);
+    }
+
+    #[test]
+    fn maps_blend() {
+        assert_eq!(
+            Blend::Default.state(),
+            Some(RenderPipelineBuilder::DEFAULT_BLEND_STATE)
+        );
+        assert_eq!(Blend::Replace.state(), None);
+        assert_eq!(Blend::Alpha.state(), Some(wgpu::BlendState::ALPHA_BLENDING));
+        assert_eq!(
+            Blend::PremultipliedAlpha.state(),
+            Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING)
+        );
+
+        let additive = Blend::Additive.state().unwrap();
+        assert_eq!(additive.color, additive.alpha);
+        assert_eq!(additive.color.src_factor, wgpu::BlendFactor::One);
+        assert_eq!(additive.color.dst_factor, wgpu::BlendFactor::One);
+        assert_eq!(additive.color.operation, wgpu::BlendOperation::Add);
+
+        let custom = Blend::Custom {
+            color: BlendComponent {
+                src_factor: BlendFactor::SrcAlpha,
+                dst_factor: BlendFactor::OneMinusSrcAlpha,
+                operation: BlendOperation::Add,
+            },
+            alpha: BlendComponent {
+                src_factor: BlendFactor::One,
+                dst_factor: BlendFactor::Zero,
+                operation: BlendOperation::Max,
+            },
+        };
+        assert_eq!(
+            custom.state(),
+            Some(wgpu::BlendState {
+                color: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::SrcAlpha,
+                    dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                    operation: wgpu::BlendOperation::Add,
+                },
+                alpha: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::One,
+                    dst_factor: wgpu::BlendFactor::Zero,
+                    operation: wgpu::BlendOperation::Max,
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn parses_custom_blend() {
+        let description = PipelineDescription::from_ron(
+            r#"(
+    vertex: (path: "v.wgsl"),
+    color_targets: [(
+        format: Rgba8Unorm,
+        blend: Custom(
+            color: (src_factor: One, dst_factor: One),
+            alpha: (src_factor: Zero, dst_factor: One, operation: Max),
+        ),
+    )],
+)"#,
+        )
+        .unwrap();
+        let blend = description.color_targets[0].blend.state().unwrap();
+        assert_eq!(blend.color.operation, wgpu::BlendOperation::Add);
+        assert_eq!(blend.alpha.operation, wgpu::BlendOperation::Max);
+    }
+
+    #[test]
+    fn parses_write_masks() {
+        assert_eq!(write_mask("rgba").unwrap(), wgpu::ColorWrites::ALL);
+        assert_eq!(write_mask("").unwrap(), wgpu::ColorWrites::empty());
+        assert_eq!(
+            write_mask("br").unwrap(),
+            wgpu::ColorWrites::RED | wgpu::ColorWrites::BLUE
+        );
+        assert!(matches!(
+            write_mask("rgbx"),
+            Err(DescriptionError::WriteMask(mask)) if mask == "rgbx"
+        ));
+        assert!(matches!(
+            write_mask("RGBA"),
+            Err(DescriptionError::WriteMask(_))
+        ));
+    }
+
+    #[test]
+    fn looks_up_vertex_layouts() {
+        let custom = wgpu::VertexBufferLayout {
+            array_stride: 8,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &[],
+        };
+        let vertex_layouts = VertexLayouts::new().register_layout("Instance", custom.clone());
+        let layouts = description(&["MeshVertex", "Instance"])
+            .vertex_buffer_layouts(&vertex_layouts)
+            .unwrap();
+        assert_eq!(layouts, [MeshVertex::desc(), custom]);
+
+        let err = description(&["MeshVertex", "Sprite"])
+            .vertex_buffer_layouts(&vertex_layouts)
+            .unwrap_err();
+        assert!(matches!(err, DescriptionError::UnknownVertexLayout(name) if name == "Sprite"));
+    }
+
+    #[test]
+    fn rejects_invalid_descriptions() {
+        let err = PipelineDescription::from_ron(r#"(fragment: Some((path: "f.wgsl")))"#);
+        assert!(matches!(err, Err(DescriptionError::Parse(_))));
+        let err = PipelineDescription::from_ron(r#"(vertex: (path: "v.wgsl"), blend: Alpha)"#);
+        assert!(matches!(err, Err(DescriptionError::Parse(_))));
+        let err = PipelineDescription::from_toml("vertex = 1");
+        assert!(matches!(err, Err(DescriptionError::Parse(_))));
+    }
+}
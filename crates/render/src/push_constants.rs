@@ -0,0 +1,280 @@
+//!
+//! Small per-draw data, like an object index or material ID
+//!
+//! [`PushConstants`] sends a [`bytemuck::Pod`] struct to shaders with
+//! [`wgpu::RenderPass::set_push_constants`] when the device supports
+//! [`wgpu::Features::PUSH_CONSTANTS`]. Otherwise every draw gets its own slot of a uniform buffer,
+//! bound with a dynamic offset at bind group `group`, and the values are uploaded by
+//! [`PushConstants::flush`] before the commands are submitted.
+//!
+//! Shaders declare the data through the defines of [`PushConstants::shader_defines`]:
+//!
+//! ```text
+//! #ifdef PUSH_CONSTANTS
+//! var<push_constant> draw: DrawData;
+//! #else
+//! @group(PUSH_CONSTANTS_GROUP) @binding(0) var<uniform> draw: DrawData;
+//! #endif
+//! ```
+//!
+//! # Examples
+//!
+//! ```ignore
+//! #[repr(C)]
+//! #[derive(Copy, Clone, Pod, Zeroable)]
+//! struct DrawData {
+//!     object: u32,
+//!     material: u32,
+//! }
+//!
+//! let draw_data = PushConstants::<DrawData>::new(&device, wgpu::ShaderStages::VERTEX, 1, 1024, Some("Draw data"));
+//! let preprocessor = draw_data.shader_defines(Preprocessor::new());
+//! let layout = draw_data.pipeline_layout(&device, &[&material_layout], Some("Draw layout"));
+//!
+//! {
+//!     let mut pass = encoder.begin_render_pass(&desc);
+//!     pass.set_pipeline(&pipeline);
+//!     for (i, object) in objects.iter().enumerate() {
+//!         draw_data.set(&mut pass, &DrawData { object: i as u32, material: object.material });
+//!         pass.draw(0..object.vertices, 0..1);
+//!     }
+//! }
+//! draw_data.flush(&queue);
+//! queue.submit(Some(encoder.finish()));
+//! ```
+use std::marker::PhantomData;
+use std::sync::Mutex;
+
+use crate::shader_preprocessor::Preprocessor;
+
+/// Per-draw shader data of type `T`, as push constants or a uniform buffer fallback.
+#[derive(Debug)]
+pub struct PushConstants<T> {
+    stages: wgpu::ShaderStages,
+    group: u32,
+    fallback: Option<Fallback>,
+    phantom: PhantomData<T>,
+}
+
+#[derive(Debug)]
+struct Fallback {
+    buffer: wgpu::Buffer,
+    layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    stride: u64,
+    capacity: u64,
+    // Values set since the last flush, one slot per draw.
+    staging: Mutex<Vec<u8>>,
+}
+
+impl<T> PushConstants<T>
+where
+    T: bytemuck::Pod,
+{
+    /// Create push constants visible to `stages`.
+    ///
+    /// `group` is the bind group index used by the uniform buffer fallback, and `capacity` the
+    /// number of draws it can hold between two [`PushConstants::flush`] calls.
+    ///
+    /// # Panics
+    ///
+    /// If the size of `T` is not a multiple of 4, or if `capacity` is 0.
+    pub fn new(
+        device: &wgpu::Device,
+        stages: wgpu::ShaderStages,
+        group: u32,
+        capacity: u32,
+        label: Option<&str>,
+    ) -> Self {
+        let size = std::mem::size_of::<T>() as u32;
+        assert!(
+            size % wgpu::PUSH_CONSTANT_ALIGNMENT == 0,
+            "push constants size must be a multiple of {}, got {}",
+            wgpu::PUSH_CONSTANT_ALIGNMENT,
+            size
+        );
+        assert!(capacity > 0, "push constants capacity must not be 0");
+
+        let native = device.features().contains(wgpu::Features::PUSH_CONSTANTS)
+            && size <= device.limits().max_push_constant_size;
+        let fallback =
+            (!native).then(|| Fallback::new(device, stages, size as u64, capacity as u64, label));
+        Self {
+            stages,
+            group,
+            fallback,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Whether the device supports push constants, instead of using the uniform buffer fallback.
+    pub fn is_native(&self) -> bool {
+        self.fallback.is_none()
+    }
+
+    /// Push constant range to put in pipeline layouts, `None` for the fallback.
+    pub fn range(&self) -> Option<wgpu::PushConstantRange> {
+        self.is_native().then_some(wgpu::PushConstantRange {
+            stages: self.stages,
+            range: 0..std::mem::size_of::<T>() as u32,
+        })
+    }
+
+    /// Layout to put at index `group` of pipeline layouts, `None` when push constants are
+    /// supported.
+    pub fn bind_group_layout(&self) -> Option<&wgpu::BindGroupLayout> {
+        self.fallback.as_ref().map(|fallback| &fallback.layout)
+    }
+
+    /// Define `PUSH_CONSTANTS` if they are supported, `PUSH_CONSTANTS_GROUP` otherwise.
+    pub fn shader_defines(&self, preprocessor: Preprocessor) -> Preprocessor {
+        if self.is_native() {
+            preprocessor.define("PUSH_CONSTANTS", "")
+        } else {
+            preprocessor.define("PUSH_CONSTANTS_GROUP", &self.group.to_string())
+        }
+    }
+
+    /// Create a pipeline layout with `bind_group_layouts` and these push constants.
+    ///
+    /// # Panics
+    ///
+    /// With the fallback, if `bind_group_layouts` doesn't end right before `group`.
+    pub fn pipeline_layout(
+        &self,
+        device: &wgpu::Device,
+        bind_group_layouts: &[&wgpu::BindGroupLayout],
+        label: Option<&str>,
+    ) -> wgpu::PipelineLayout {
+        let mut layouts = bind_group_layouts.to_vec();
+        if let Some(layout) = self.bind_group_layout() {
+            assert_eq!(
+                layouts.len(),
+                self.group as usize,
+                "push constants fallback is bound to group {}",
+                self.group
+            );
+            layouts.push(layout);
+        }
+        device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label,
+            bind_group_layouts: &layouts,
+            push_constant_ranges: self.range().as_slice(),
+        })
+    }
+
+    /// Set `data` for the following draws of `pass`.
+    ///
+    /// # Panics
+    ///
+    /// With the fallback, if `capacity` values were already set since the last flush.
+    pub fn set<'a>(&'a self, pass: &mut wgpu::RenderPass<'a>, data: &T) {
+        let Some(fallback) = &self.fallback else {
+            pass.set_push_constants(self.stages, 0, bytemuck::bytes_of(data));
+            return;
+        };
+
+        let mut staging = fallback.staging.lock().unwrap();
+        let offset = staging.len() as u64;
+        assert!(
+            offset / fallback.stride < fallback.capacity,
+            "push constants fallback is full, {} draws per flush",
+            fallback.capacity
+        );
+        staging.extend_from_slice(bytemuck::bytes_of(data));
+        staging.resize((offset + fallback.stride) as usize, 0);
+        pass.set_bind_group(self.group, &fallback.bind_group, &[offset as u32]);
+    }
+
+    /// Upload values set with the fallback since the last flush.
+    ///
+    /// Must be called after recording the passes using them and before submitting them.
+    pub fn flush(&self, queue: &wgpu::Queue) {
+        if let Some(fallback) = &self.fallback {
+            let mut staging = fallback.staging.lock().unwrap();
+            if !staging.is_empty() {
+                queue.write_buffer(&fallback.buffer, 0, &staging);
+                staging.clear();
+            }
+        }
+    }
+}
+
+impl Fallback {
+    fn new(
+        device: &wgpu::Device,
+        stages: wgpu::ShaderStages,
+        size: u64,
+        capacity: u64,
+        label: Option<&str>,
+    ) -> Self {
+        // Uniform structs are 16 byte aligned in WGSL.
+        let binding_size = wgpu::util::align_to(size, 16);
+        let stride = wgpu::util::align_to(
+            binding_size,
+            device.limits().min_uniform_buffer_offset_alignment as u64,
+        );
+
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label,
+            size: stride * capacity,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label,
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: stages,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: true,
+                    min_binding_size: wgpu::BufferSize::new(binding_size),
+                },
+                count: None,
+            }],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label,
+            layout: &layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer: &buffer,
+                    offset: 0,
+                    size: wgpu::BufferSize::new(binding_size),
+                }),
+            }],
+        });
+
+        Self {
+            buffer,
+            layout,
+            bind_group,
+            stride,
+            capacity,
+            staging: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn device() -> wgpu::Device {
+        let instance = wgpu::Instance::new(wgpu::Backends::all());
+        let adapter = pollster::block_on(instance.request_adapter(&Default::default()))
+            .expect("no adapter to test with");
+        let (device, _) = pollster::block_on(adapter.request_device(&Default::default(), None))
+            .expect("failed to create device");
+        device
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter"]
+    #[should_panic(expected = "capacity must not be 0")]
+    fn rejects_zero_capacity() {
+        PushConstants::<[f32; 4]>::new(&device(), wgpu::ShaderStages::VERTEX, 1, 0, None);
+    }
+}
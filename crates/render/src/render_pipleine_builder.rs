@@ -1,12 +1,21 @@
 //!
 //! Builders to create render pipeline layout.
 //!
+use std::fmt;
+use std::hash::{Hash, Hasher};
 use std::num::NonZeroU32;
+use std::sync::Arc;
+
+use crate::shader::{catch_validation, ShaderRef};
+use crate::shader_reflection::{ReflectionError, ShaderReflection};
 
 #[derive(Debug)]
-enum Layout<'a> {
+pub(crate) enum Layout<'a> {
     Descriptor(wgpu::PipelineLayoutDescriptor<'a>),
     Created(&'a wgpu::PipelineLayout),
+    Shared(Arc<wgpu::PipelineLayout>),
+    SharedBindGroups(Vec<Arc<wgpu::BindGroupLayout>>),
+    Reflected,
 }
 
 /// Types that may be directly converted into a pipeline layout descriptor.
@@ -15,23 +24,57 @@ pub trait IntoPipelineLayoutDescriptor<'a> {
     fn into_pipeline_layout_descriptor(self) -> wgpu::PipelineLayoutDescriptor<'a>;
 }
 
+impl<'a> IntoPipelineLayoutDescriptor<'a> for wgpu::PipelineLayoutDescriptor<'a> {
+    fn into_pipeline_layout_descriptor(self) -> wgpu::PipelineLayoutDescriptor<'a> {
+        self
+    }
+}
+
+/// Bind group layouts in group order, without push constants.
+impl<'a> IntoPipelineLayoutDescriptor<'a> for &'a [&'a wgpu::BindGroupLayout] {
+    fn into_pipeline_layout_descriptor(self) -> wgpu::PipelineLayoutDescriptor<'a> {
+        wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: self,
+            push_constant_ranges: &[],
+        }
+    }
+}
+
 /// Render pipeline builder is here to ease struggles while creating pipeline.
 ///
+/// Shaders may be given either as a [`wgpu::ShaderModule`] or as a
+/// [`Shader`](crate::shader::Shader), in which case its stages are checked against the stage it
+/// is used for. A `Shader` with both vertex and fragment stages is also used as the fragment
+/// shader unless another one is given.
+///
 /// Examples
-/// ```
+/// ```no_run
+/// use render::prelude::*;
+///
+/// # let device: &wgpu::Device = unimplemented!();
+/// # let pipeline_layout: wgpu::PipelineLayout = unimplemented!();
+/// # let (v_shader, f_shader, shader): (Shader, Shader, Shader) = unimplemented!();
 /// let pipeline = RenderPipelineBuilder::from_layout(&pipeline_layout, &v_shader)
-///            .add_vertex_buffer_layout(Vertex::desc())
+///            .add_vertex_buffer_layout(MeshVertex::desc())
 ///            .fragment_shader(&f_shader)
 ///            .build(device, Some("Example pipeline"));
+///
+/// let pipeline = RenderPipelineBuilder::from_layout(&pipeline_layout, &shader)
+///            .vertex_entry_point("vs_main")
+///            .fragment_entry_point("fs_main")
+///            .build(device, Some("Single module pipeline"));
 /// ```
 #[derive(Debug)]
 pub struct RenderPipelineBuilder<'a> {
     layout: Layout<'a>,
-    vs_mod: &'a wgpu::ShaderModule,
-    fs_mod: Option<&'a wgpu::ShaderModule>,
+    vs_mod: ShaderRef<'a>,
+    fs_mod: Option<ShaderRef<'a>>,
+    vs_entry_point: &'a str,
+    fs_entry_point: &'a str,
     primitive: wgpu::PrimitiveState,
     color_state: Option<wgpu::ColorTargetState>,
-    color_states: &'a [Option<wgpu::ColorTargetState>],
+    color_states: Vec<Option<wgpu::ColorTargetState>>,
     depth_stencil: Option<wgpu::DepthStencilState>,
     vertex_buffers: Vec<wgpu::VertexBufferLayout<'static>>,
     multisample: wgpu::MultisampleState,
@@ -40,6 +83,9 @@ pub struct RenderPipelineBuilder<'a> {
 
 impl<'a> RenderPipelineBuilder<'a> {
     // Defaults
+    pub const DEFAULT_VERTEX_ENTRY_POINT: &'static str = "vertex";
+    pub const DEFAULT_FRAGMENT_ENTRY_POINT: &'static str = "fragment";
+
     pub const DEFAULT_PRIMITIVE_TOPOLOGY: wgpu::PrimitiveTopology =
         wgpu::PrimitiveTopology::TriangleList;
     pub const DEFAULT_FRONT_FACE: wgpu::FrontFace = wgpu::FrontFace::Ccw;
@@ -121,30 +167,66 @@ impl<'a> RenderPipelineBuilder<'a> {
 
     /// Begin building the render pipeline for the given pipeline layout and the vertex shader
     /// module.
-    pub fn from_layout(layout: &'a wgpu::PipelineLayout, vs_mod: &'a wgpu::ShaderModule) -> Self {
+    pub fn from_layout(layout: &'a wgpu::PipelineLayout, vs_mod: impl Into<ShaderRef<'a>>) -> Self {
         let layout = Layout::Created(layout);
-        Self::new_inner(layout, vs_mod)
+        Self::new_inner(layout, vs_mod.into())
+    }
+
+    /// Like [`RenderPipelineBuilder::from_layout`], but the builder keeps `layout` alive so that
+    /// [`PipelineCache`](crate::pipeline_cache::PipelineCache) can key the pipeline by it.
+    pub fn from_shared_layout(
+        layout: Arc<wgpu::PipelineLayout>,
+        vs_mod: impl Into<ShaderRef<'a>>,
+    ) -> Self {
+        Self::new_inner(Layout::Shared(layout), vs_mod.into())
+    }
+
+    /// Begin building the render pipeline for a pipeline with `bind_group_layouts` in group
+    /// order and without push constants, e.g. layouts from
+    /// [`BindGroupCache`](crate::bind_group_cache::BindGroupCache).
+    ///
+    /// The builder keeps the layouts alive so that
+    /// [`PipelineCache`](crate::pipeline_cache::PipelineCache) can key the pipeline by them.
+    pub fn from_shared_layouts(
+        bind_group_layouts: &[Arc<wgpu::BindGroupLayout>],
+        vs_mod: impl Into<ShaderRef<'a>>,
+    ) -> Self {
+        let layout = Layout::SharedBindGroups(bind_group_layouts.to_vec());
+        Self::new_inner(layout, vs_mod.into())
     }
 
     /// Begin building the render pipeline for a pipeline with the given layout descriptor and the
     /// vertex shader module.
-    pub fn from_layout_descriptor<T>(layout_desc: T, vs_mod: &'a wgpu::ShaderModule) -> Self
+    pub fn from_layout_descriptor<T>(layout_desc: T, vs_mod: impl Into<ShaderRef<'a>>) -> Self
     where
         T: IntoPipelineLayoutDescriptor<'a>,
     {
         let desc = layout_desc.into_pipeline_layout_descriptor();
         let layout = Layout::Descriptor(desc);
-        Self::new_inner(layout, vs_mod)
+        Self::new_inner(layout, vs_mod.into())
+    }
+
+    /// Begin building the render pipeline for the vertex shader module, with the layout derived
+    /// from reflection of the vertex and fragment shaders, see [`RenderPipelineBuilder::reflect`].
+    ///
+    /// Bind group layouts are created with every build, so bind groups should be created with
+    /// layouts made from the same reflection, e.g. by
+    /// [`LayoutBuilder::from_reflection`](crate::bind_group_builder::LayoutBuilder::from_reflection),
+    /// or with `get_bind_group_layout` of the built pipeline.
+    pub fn from_reflection(vs_mod: impl Into<ShaderRef<'a>>) -> Self {
+        Self::new_inner(Layout::Reflected, vs_mod.into())
     }
 
     // Shared between constructors.
-    fn new_inner(layout: Layout<'a>, vs_mod: &'a wgpu::ShaderModule) -> Self {
+    fn new_inner(layout: Layout<'a>, vs_mod: ShaderRef<'a>) -> Self {
         RenderPipelineBuilder {
             layout,
             vs_mod,
             fs_mod: None,
+            vs_entry_point: Self::DEFAULT_VERTEX_ENTRY_POINT,
+            fs_entry_point: Self::DEFAULT_FRAGMENT_ENTRY_POINT,
             color_state: None,
-            color_states: &[],
+            color_states: vec![],
             primitive: Self::DEFAULT_PRIMITIVE,
             depth_stencil: None,
             vertex_buffers: vec![],
@@ -156,8 +238,20 @@ impl<'a> RenderPipelineBuilder<'a> {
     // Builders
 
     /// Specify a compiled fragment shader for the render pipeline.
-    pub fn fragment_shader(mut self, fs_mod: &'a wgpu::ShaderModule) -> Self {
-        self.fs_mod = Some(fs_mod);
+    pub fn fragment_shader(mut self, fs_mod: impl Into<ShaderRef<'a>>) -> Self {
+        self.fs_mod = Some(fs_mod.into());
+        self
+    }
+
+    /// Name of the vertex shader function, `vertex` by default.
+    pub fn vertex_entry_point(mut self, entry_point: &'a str) -> Self {
+        self.vs_entry_point = entry_point;
+        self
+    }
+
+    /// Name of the fragment shader function, `fragment` by default.
+    pub fn fragment_entry_point(mut self, entry_point: &'a str) -> Self {
+        self.fs_entry_point = entry_point;
         self
     }
 
@@ -205,7 +299,7 @@ impl<'a> RenderPipelineBuilder<'a> {
 
     /// Specify the full color state for drawing to the output attachment.
     ///
-    /// If you have multiple output attachments, see the `color_states` method.
+    /// If you have multiple output attachments, see the `color_target` method.
     pub fn color_state(mut self, state: wgpu::ColorTargetState) -> Self {
         self.color_state = Some(state);
         self
@@ -245,6 +339,34 @@ impl<'a> RenderPipelineBuilder<'a> {
         self
     }
 
+    // Multiple render targets.
+
+    /// Add a color target with the given format, configured by `target_builder`.
+    ///
+    /// Call this multiple times in succession to add multiple color targets, in the same order as
+    /// the color attachments of the render pass. Can not be combined with single target methods
+    /// like `color_format` or `color_blend`.
+    pub fn color_target<F>(mut self, format: wgpu::TextureFormat, target_builder: F) -> Self
+    where
+        F: FnOnce(ColorTargetStateBuilder) -> ColorTargetStateBuilder,
+    {
+        let builder = ColorTargetStateBuilder::new(format);
+        self.color_states.push(Some(target_builder(builder).state));
+        self
+    }
+
+    /// Add a slot for a color attachment the pipeline does not write to.
+    pub fn empty_color_target(mut self) -> Self {
+        self.color_states.push(None);
+        self
+    }
+
+    /// Add fully specified color targets.
+    pub fn color_targets(mut self, states: &[Option<wgpu::ColorTargetState>]) -> Self {
+        self.color_states.extend_from_slice(states);
+        self
+    }
+
     // Depth / Stencil state
 
     /// Specify the full depth stencil state.
@@ -459,34 +581,485 @@ impl<'a> RenderPipelineBuilder<'a> {
     /// Build the render pipeline layout, its descriptor and ultimately the pipeline itself with
     /// the specified parameters.
     ///
-    /// **Panic!**s in the following occur:
+    /// Nothing is validated up front, so shaders aren't parsed again unless the layout is
+    /// reflected, and wgpu reports invalid pipelines itself. Use
+    /// [`RenderPipelineBuilder::try_build`] to get errors instead.
+    ///
+    /// **Panic!**s if:
     ///
-    /// - A rasterization state field was specified but no fragment shader was given.
     /// - A color state field was specified but no fragment shader was given.
+    /// - Both single target color state and `color_target`s were specified.
+    /// - A [`Shader`](crate::shader::Shader) is used for a stage it was not created for.
+    /// - The layout should be reflected from shaders that fail to or have no source.
     pub fn build(self, device: &wgpu::Device, label: Option<&str>) -> wgpu::RenderPipeline {
+        assert!(
+            self.vs_mod.supports(wgpu::ShaderStages::VERTEX),
+            "vertex shader was not created for vertex stage"
+        );
+        if let Some(fs_mod) = &self.fs_mod {
+            assert!(
+                fs_mod.supports(wgpu::ShaderStages::FRAGMENT),
+                "fragment shader was not created for fragment stage"
+            );
+        }
         match self.layout {
             Layout::Descriptor(ref desc) => {
                 let layout = device.create_pipeline_layout(desc);
                 build(self, &layout, device, label)
             }
             Layout::Created(layout) => build(self, layout, device, label),
+            Layout::Shared(ref layout) => {
+                let layout = Arc::clone(layout);
+                build(self, &layout, device, label)
+            }
+            Layout::SharedBindGroups(ref bind_group_layouts) => {
+                let layout = shared_layout(device, bind_group_layouts, label);
+                build(self, &layout, device, label)
+            }
+            Layout::Reflected => {
+                let reflection = self
+                    .reflect()
+                    .unwrap_or_else(|err| panic!("failed to reflect render pipeline: {}", err));
+                let layout = reflected_layout(device, &reflection, label);
+                build(self, &layout, device, label)
+            }
+        }
+    }
+
+    /// Like [`RenderPipelineBuilder::build`], but returns an error instead of panicking.
+    ///
+    /// Builder state is checked up front. Vertex inputs are checked against vertex buffer
+    /// attributes when the vertex shader is a [`Shader`](crate::shader::Shader), which keeps its
+    /// WGSL source. Anything else wgpu rejects is caught by an error scope and returned as
+    /// [`PipelineError::Validation`], except on the web where the scope can't be waited for.
+    pub fn try_build(
+        self,
+        device: &wgpu::Device,
+        label: Option<&str>,
+    ) -> Result<wgpu::RenderPipeline, PipelineError> {
+        self.validate(device.features())?;
+        let reflection = match self.layout {
+            Layout::Reflected => Some(self.reflect()?),
+            _ => None,
+        };
+
+        catch_validation(device, || match self.layout {
+            Layout::Descriptor(ref desc) => {
+                let layout = device.create_pipeline_layout(desc);
+                build(self, &layout, device, label)
+            }
+            Layout::Created(layout) => build(self, layout, device, label),
+            Layout::Shared(ref layout) => {
+                let layout = Arc::clone(layout);
+                build(self, &layout, device, label)
+            }
+            Layout::SharedBindGroups(ref bind_group_layouts) => {
+                let layout = shared_layout(device, bind_group_layouts, label);
+                build(self, &layout, device, label)
+            }
+            Layout::Reflected => {
+                let reflection = reflection.expect("reflected before building");
+                let layout = reflected_layout(device, &reflection, label);
+                build(self, &layout, device, label)
+            }
+        })
+        .map_err(PipelineError::Validation)
+    }
+
+    /// Check builder state without creating anything.
+    pub fn validate(&self, features: wgpu::Features) -> Result<(), PipelineError> {
+        if !self.vs_mod.supports(wgpu::ShaderStages::VERTEX) {
+            return Err(PipelineError::StageMismatch(wgpu::ShaderStages::VERTEX));
+        }
+        if let Some(fs_mod) = &self.fs_mod {
+            if !fs_mod.supports(wgpu::ShaderStages::FRAGMENT) {
+                return Err(PipelineError::StageMismatch(wgpu::ShaderStages::FRAGMENT));
+            }
+        }
+
+        let color_states = color_states(
+            self.fragment().is_some(),
+            self.color_state.clone(),
+            &self.color_states,
+        )?;
+
+        if let Some(depth_stencil) = &self.depth_stencil {
+            if !matches!(
+                depth_stencil.format.describe().sample_type,
+                wgpu::TextureSampleType::Depth
+            ) {
+                return Err(PipelineError::NotDepthFormat(depth_stencil.format));
+            }
+        }
+
+        let count = self.multisample.count;
+        if count != 1 && count != 4 {
+            return Err(PipelineError::SampleCount {
+                format: None,
+                count,
+            });
+        }
+        // Adapter may support more than guaranteed, which we can not know from the device alone.
+        let adapter_specific =
+            features.contains(wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES);
+        if count > 1 && !adapter_specific {
+            let formats = color_states
+                .iter()
+                .flatten()
+                .map(|state| state.format)
+                .chain(self.depth_stencil.iter().map(|state| state.format));
+            for format in formats {
+                let flags = format.describe().guaranteed_format_features.flags;
+                if !flags.contains(wgpu::TextureFormatFeatureFlags::MULTISAMPLE) {
+                    return Err(PipelineError::SampleCount {
+                        format: Some(format),
+                        count,
+                    });
+                }
+            }
+        }
+
+        if let Some(source) = self.vs_mod.source {
+            ShaderReflection::from_wgsl(source)?
+                .try_entry_point(wgpu::ShaderStages::VERTEX, self.vs_entry_point)?
+                .check_vertex_buffers(&self.vertex_buffers)?;
+        }
+
+        Ok(())
+    }
+
+    /// Reflection of the vertex shader, merged with the fragment shader if it's another module.
+    ///
+    /// Shaders must be [`Shader`](crate::shader::Shader)s, plain modules have no source to
+    /// reflect.
+    pub fn reflect(&self) -> Result<ShaderReflection, PipelineError> {
+        let source = self.vs_mod.source.ok_or(ReflectionError::MissingSource)?;
+        let mut reflection = ShaderReflection::from_wgsl(source)?;
+        if let Some(fs_mod) = self.fs_mod {
+            let fs_source = fs_mod.source.ok_or(ReflectionError::MissingSource)?;
+            if fs_source != source {
+                reflection.merge(&ShaderReflection::from_wgsl(fs_source)?)?;
+            }
+        }
+        Ok(reflection)
+    }
+
+    /// Key identifying the pipeline this builder would create, see
+    /// [`PipelineCache`](crate::pipeline_cache::PipelineCache).
+    ///
+    /// `None` if the builder borrows a layout or a plain [`wgpu::ShaderModule`], which the key
+    /// could only identify by address, and a freed address may be reused by another object.
+    /// Keyed builders use [`RenderPipelineBuilder::from_shared_layout`],
+    /// [`RenderPipelineBuilder::from_shared_layouts`] or [`RenderPipelineBuilder::from_reflection`]
+    /// with [`Shader`](crate::shader::Shader)s.
+    pub fn key(&self) -> Option<PipelineKey> {
+        let layout = match &self.layout {
+            Layout::Created(_) | Layout::Descriptor(_) => return None,
+            Layout::Shared(layout) => LayoutKey::Shared(Shared(Arc::clone(layout))),
+            Layout::SharedBindGroups(bind_group_layouts) => LayoutKey::SharedBindGroups(
+                bind_group_layouts
+                    .iter()
+                    .map(|layout| Shared(Arc::clone(layout)))
+                    .collect(),
+            ),
+            // Derived from the shaders, which are part of the key.
+            Layout::Reflected => LayoutKey::Reflected,
+        };
+        let fragment = match self.fs_mod {
+            Some(fs_mod) => Some(fs_mod.source?.to_string()),
+            None => None,
+        };
+        Some(PipelineKey {
+            layout,
+            vertex: self.vs_mod.source?.to_string(),
+            fragment,
+            vs_entry_point: self.vs_entry_point.to_string(),
+            fs_entry_point: self.fs_entry_point.to_string(),
+            primitive: self.primitive,
+            color_state: self.color_state.clone(),
+            color_states: self.color_states.clone(),
+            depth_stencil: self.depth_stencil.as_ref().map(DepthStencilKey::new),
+            vertex_buffers: self
+                .vertex_buffers
+                .iter()
+                .map(|buffer| VertexBufferKey {
+                    array_stride: buffer.array_stride,
+                    step_mode: buffer.step_mode,
+                    attributes: buffer.attributes.to_vec(),
+                })
+                .collect(),
+            multisample: self.multisample,
+            multiview: self.multiview,
+        })
+    }
+
+    // Fragment shader, either given explicitly or taken from a module with both stages.
+    fn fragment(&self) -> Option<ShaderRef<'a>> {
+        match self.fs_mod {
+            Some(fs_mod) => Some(fs_mod),
+            None => match self.vs_mod.stage {
+                Some(stage) if stage.contains(wgpu::ShaderStages::FRAGMENT) => Some(self.vs_mod),
+                _ => None,
+            },
         }
     }
 }
 
+/// Full state of a [`RenderPipelineBuilder`], comparable and hashable.
+///
+/// Shaders are identified by their source, so variants produced with different defines are told
+/// apart. Layouts are identified by the [`Arc`]s the key holds, which keeps them alive.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PipelineKey {
+    layout: LayoutKey,
+    vertex: String,
+    fragment: Option<String>,
+    vs_entry_point: String,
+    fs_entry_point: String,
+    primitive: wgpu::PrimitiveState,
+    color_state: Option<wgpu::ColorTargetState>,
+    color_states: Vec<Option<wgpu::ColorTargetState>>,
+    depth_stencil: Option<DepthStencilKey>,
+    vertex_buffers: Vec<VertexBufferKey>,
+    multisample: wgpu::MultisampleState,
+    multiview: Option<NonZeroU32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum LayoutKey {
+    Shared(Shared<wgpu::PipelineLayout>),
+    SharedBindGroups(Vec<Shared<wgpu::BindGroupLayout>>),
+    Reflected,
+}
+
+// Compared by identity, the `Arc` keeps the address from being reused.
+#[derive(Debug)]
+struct Shared<T>(Arc<T>);
+
+impl<T> Clone for Shared<T> {
+    fn clone(&self) -> Self {
+        Self(Arc::clone(&self.0))
+    }
+}
+
+impl<T> PartialEq for Shared<T> {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl<T> Eq for Shared<T> {}
+
+impl<T> Hash for Shared<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::ptr::hash(&*self.0, state);
+    }
+}
+
+// `wgpu::DepthStencilState` is not `Hash` because of float depth bias.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct DepthStencilKey {
+    format: wgpu::TextureFormat,
+    depth_write_enabled: bool,
+    depth_compare: wgpu::CompareFunction,
+    stencil: wgpu::StencilState,
+    bias_constant: i32,
+    bias_slope_scale: u32,
+    bias_clamp: u32,
+}
+
+impl DepthStencilKey {
+    fn new(state: &wgpu::DepthStencilState) -> Self {
+        Self {
+            format: state.format,
+            depth_write_enabled: state.depth_write_enabled,
+            depth_compare: state.depth_compare,
+            stencil: state.stencil.clone(),
+            bias_constant: state.bias.constant,
+            bias_slope_scale: state.bias.slope_scale.to_bits(),
+            bias_clamp: state.bias.clamp.to_bits(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct VertexBufferKey {
+    array_stride: wgpu::BufferAddress,
+    step_mode: wgpu::VertexStepMode,
+    attributes: Vec<wgpu::VertexAttribute>,
+}
+
+// Pipeline layout of `Layout::SharedBindGroups`.
+pub(crate) fn shared_layout(
+    device: &wgpu::Device,
+    bind_group_layouts: &[Arc<wgpu::BindGroupLayout>],
+    label: Option<&str>,
+) -> wgpu::PipelineLayout {
+    let bind_group_layouts: Vec<_> = bind_group_layouts.iter().map(|l| &**l).collect();
+    device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label,
+        bind_group_layouts: &bind_group_layouts,
+        push_constant_ranges: &[],
+    })
+}
+
+/// Reasons for [`RenderPipelineBuilder::try_build`] to fail.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PipelineError {
+    /// Shader was not created for the given stage.
+    StageMismatch(wgpu::ShaderStages),
+    /// Color targets or color state fields were specified, but no fragment shader was given.
+    MissingFragmentShader,
+    /// Both single target color state and `color_target`s were specified.
+    ConflictingColorStates,
+    /// Depth stencil state uses a format without depth.
+    NotDepthFormat(wgpu::TextureFormat),
+    /// Sample count is not supported, by the format if one is given.
+    SampleCount {
+        format: Option<wgpu::TextureFormat>,
+        count: u32,
+    },
+    /// Shader source failed to parse, contains formatted parser error.
+    Parse(String),
+    /// No entry point with given name and stage.
+    MissingEntryPoint {
+        stage: wgpu::ShaderStages,
+        name: String,
+    },
+    /// Vertex shader reads a location no vertex buffer provides.
+    MissingVertexInput(u32),
+    /// Vertex attribute format does not fit the type of vertex shader input.
+    VertexInputMismatch {
+        location: u32,
+        format: wgpu::VertexFormat,
+    },
+    /// Shader reflection failed or doesn't fit the pipeline.
+    Reflection(ReflectionError),
+    /// wgpu validation failed, contains the formatted wgpu error.
+    Validation(String),
+}
+
+impl From<ReflectionError> for PipelineError {
+    fn from(err: ReflectionError) -> Self {
+        match err {
+            ReflectionError::Parse(err) => Self::Parse(err),
+            ReflectionError::MissingEntryPoint { stage, name } => {
+                Self::MissingEntryPoint { stage, name }
+            }
+            ReflectionError::MissingVertexInput(location) => Self::MissingVertexInput(location),
+            ReflectionError::VertexInputMismatch { location, format } => {
+                Self::VertexInputMismatch { location, format }
+            }
+            err => Self::Reflection(err),
+        }
+    }
+}
+
+impl fmt::Display for PipelineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::StageMismatch(stage) => {
+                write!(f, "shader was not created for {:?} stage", stage)
+            }
+            Self::MissingFragmentShader => {
+                write!(f, "specified color states but no fragment shader")
+            }
+            Self::ConflictingColorStates => {
+                write!(f, "specified both color state fields and color targets")
+            }
+            Self::NotDepthFormat(format) => {
+                write!(f, "depth stencil state uses non depth format {:?}", format)
+            }
+            Self::SampleCount {
+                format: Some(format),
+                count,
+            } => write!(f, "format {:?} does not support {} samples", format, count),
+            Self::SampleCount {
+                format: None,
+                count,
+            } => write!(f, "sample count {} is not supported, use 1 or 4", count),
+            Self::Parse(err) => write!(f, "failed to parse shader: {}", err),
+            Self::MissingEntryPoint { stage, name } => {
+                write!(f, "no {:?} entry point named `{}`", stage, name)
+            }
+            Self::MissingVertexInput(location) => write!(
+                f,
+                "vertex shader input at location {} is not provided by any vertex buffer",
+                location
+            ),
+            Self::VertexInputMismatch { location, format } => write!(
+                f,
+                "vertex attribute {:?} at location {} does not fit shader input type",
+                format, location
+            ),
+            Self::Reflection(err) => write!(f, "{}", err),
+            Self::Validation(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for PipelineError {}
+
+// Resolve single target color state and color targets into the list of targets.
+fn color_states(
+    has_fragment: bool,
+    color_state: Option<wgpu::ColorTargetState>,
+    color_states: &[Option<wgpu::ColorTargetState>],
+) -> Result<Vec<Option<wgpu::ColorTargetState>>, PipelineError> {
+    match (has_fragment, color_states.is_empty(), color_state) {
+        (true, true, color_state) => Ok(vec![Some(
+            color_state.unwrap_or(RenderPipelineBuilder::DEFAULT_COLOR_STATE),
+        )]),
+        (true, false, None) => Ok(color_states.to_vec()),
+        (_, false, Some(_)) => Err(PipelineError::ConflictingColorStates),
+        (false, false, None) | (false, true, Some(_)) => Err(PipelineError::MissingFragmentShader),
+        (false, true, None) => Ok(vec![]),
+    }
+}
+
+// Create bind group layouts and the pipeline layout described by `reflection`.
+pub(crate) fn reflected_layout(
+    device: &wgpu::Device,
+    reflection: &ShaderReflection,
+    label: Option<&str>,
+) -> wgpu::PipelineLayout {
+    let bind_group_layouts: Vec<_> = (0..reflection.group_count())
+        .map(|group| {
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label,
+                entries: &reflection.bind_group_layout_entries(group),
+            })
+        })
+        .collect();
+    let bind_group_layouts: Vec<_> = bind_group_layouts.iter().collect();
+    let push_constant_ranges: Vec<_> = reflection
+        .push_constants
+        .iter()
+        .map(|push_constants| push_constants.range())
+        .collect();
+    device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label,
+        bind_group_layouts: &bind_group_layouts,
+        push_constant_ranges: &push_constant_ranges,
+    })
+}
+
 fn build(
     builder: RenderPipelineBuilder,
     layout: &wgpu::PipelineLayout,
     device: &wgpu::Device,
     label: Option<&str>,
 ) -> wgpu::RenderPipeline {
+    let fs_mod = builder.fragment();
     let RenderPipelineBuilder {
         layout: _layout,
         vs_mod,
-        fs_mod,
+        fs_mod: _fs_mod,
+        vs_entry_point,
+        fs_entry_point,
         primitive,
         color_state,
-        color_states,
+        color_states: targets,
         depth_stencil,
         multisample,
         vertex_buffers,
@@ -494,31 +1067,18 @@ fn build(
     } = builder;
 
     let vertex = wgpu::VertexState {
-        module: vs_mod,
-        entry_point: "vertex",
+        module: vs_mod.module,
+        entry_point: vs_entry_point,
         buffers: &vertex_buffers[..],
     };
 
-    let mut single_color_state = [Some(RenderPipelineBuilder::DEFAULT_COLOR_STATE)];
-    let color_states = match (fs_mod.is_some(), color_states.is_empty()) {
-        (true, true) => {
-            if let Some(cs) = color_state {
-                single_color_state[0] = Some(cs);
-            }
-            &single_color_state[..]
-        }
-        (true, false) => color_states,
-        (false, true) => panic!("specified color states but no fragment shader"),
-        (false, false) => match color_state.is_some() {
-            true => panic!("specified color state fields but no fragment shader"),
-            false => &[],
-        },
-    };
+    let color_states = color_states(fs_mod.is_some(), color_state, &targets)
+        .unwrap_or_else(|err| panic!("failed to build render pipeline: {}", err));
     let fragment = match (fs_mod, color_states.is_empty()) {
         (Some(fs_mod), false) => Some(wgpu::FragmentState {
-            module: fs_mod,
-            entry_point: "fragment",
-            targets: color_states,
+            module: fs_mod.module,
+            entry_point: fs_entry_point,
+            targets: &color_states,
         }),
         _ => None,
     };
@@ -536,3 +1096,55 @@ fn build(
 
     device.create_render_pipeline(&pipeline_desc)
 }
+
+/// A builder type to simplify the process of creating a color target state.
+#[derive(Debug)]
+pub struct ColorTargetStateBuilder {
+    state: wgpu::ColorTargetState,
+}
+
+impl ColorTargetStateBuilder {
+    /// Begin building a color target of `format` with default blending and write mask.
+    pub fn new(format: wgpu::TextureFormat) -> Self {
+        let mut state = RenderPipelineBuilder::DEFAULT_COLOR_STATE;
+        state.format = format;
+        ColorTargetStateBuilder { state }
+    }
+
+    /// Specify the full blend state, `None` disables blending.
+    pub fn blend(mut self, blend: Option<wgpu::BlendState>) -> Self {
+        self.state.blend = blend;
+        self
+    }
+
+    /// The color blending used for this target.
+    pub fn color_blend(mut self, color_blend: wgpu::BlendComponent) -> Self {
+        let blend = self
+            .state
+            .blend
+            .get_or_insert(RenderPipelineBuilder::DEFAULT_BLEND_STATE);
+        blend.color = color_blend;
+        self
+    }
+
+    /// The alpha blending used for this target.
+    pub fn alpha_blend(mut self, alpha_blend: wgpu::BlendComponent) -> Self {
+        let blend = self
+            .state
+            .blend
+            .get_or_insert(RenderPipelineBuilder::DEFAULT_BLEND_STATE);
+        blend.alpha = alpha_blend;
+        self
+    }
+
+    /// Mask which enables/disables writes to different color/alpha channel.
+    pub fn write_mask(mut self, mask: wgpu::ColorWrites) -> Self {
+        self.state.write_mask = mask;
+        self
+    }
+
+    /// Return the built color target state.
+    pub fn into_inner(self) -> wgpu::ColorTargetState {
+        self.state
+    }
+}
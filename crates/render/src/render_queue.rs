@@ -0,0 +1,565 @@
+//!
+//! Sorting and batching of draws
+//!
+//! Renderables don't record commands into the render pass themselves, they push [`Draw`]s into a
+//! [`RenderQueue`]. Every draw carries a packed [`SortKey`], and the queue records draws in key
+//! order, so draws sharing a pipeline and material end up next to each other. Pipelines, bind
+//! groups and buffers already set by the previous draw are not set again, and instanced draws of
+//! the same mesh with adjacent instance ranges are merged into one draw call. [`RenderStats`] tells
+//! how many state changes a frame needed.
+//!
+//! # Examples
+//!
+//! ```ignore
+//! impl Renderable for Sprite {
+//!     fn render<'a>(&'a self, queue: &mut RenderQueue<'a>) {
+//!         let key = SortKey::new(
+//!             RenderPhase::Transparent,
+//!             render_queue::resource_id(&self.pipeline),
+//!             render_queue::resource_id(&self.texture),
+//!             queue.depth(self.position),
+//!         );
+//!         queue.push(
+//!             Draw::new(key, &self.pipeline, &self.quad, 0..6)
+//!                 .bind_group(MATERIAL_GROUP, &self.texture)
+//!                 .instances(self.index..self.index + 1),
+//!         );
+//!     }
+//! }
+//! ```
+use std::hash::Hasher;
+use std::ops::Range;
+
+use crate::bindings::ViewUniform;
+use crate::renderer::RenderPhase;
+
+/// Number of bind groups a draw can set, the default `max_bind_groups` limit of wgpu.
+pub const MAX_BIND_GROUPS: usize = 4;
+
+const PHASE_SHIFT: u32 = 62;
+const ID_BITS: u32 = 16;
+const DEPTH_BITS: u32 = 30;
+const DEPTH_MASK: u64 = (1 << DEPTH_BITS) - 1;
+
+/// Order of a draw in the frame, packed into 64 bits.
+///
+/// From the most significant bits: 2 bits of [`RenderPhase`], then 16 bits of pipeline id,
+/// 16 bits of material id and 30 bits of depth for opaque draws. Transparent draws put reversed
+/// depth before the ids instead, as blending requires drawing them back to front.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SortKey(u64);
+
+impl SortKey {
+    /// Key of a draw in `phase` using `pipeline` and `material` ids, `depth` units in front of
+    /// the camera.
+    ///
+    /// Opaque draws are grouped by pipeline and material, then drawn front to back so that depth
+    /// testing discards hidden fragments early.
+    pub fn new(phase: RenderPhase, pipeline: u16, material: u16, depth: f32) -> Self {
+        let phase_bits = (phase as u64) << PHASE_SHIFT;
+        let ids = (pipeline as u64) << ID_BITS | material as u64;
+        let depth = quantize_depth(depth);
+        match phase {
+            RenderPhase::Opaque => Self(phase_bits | ids << DEPTH_BITS | depth),
+            RenderPhase::Transparent => {
+                Self(phase_bits | (DEPTH_MASK - depth) << (2 * ID_BITS) | ids)
+            }
+        }
+    }
+
+    /// Packed key.
+    pub fn bits(self) -> u64 {
+        self.0
+    }
+
+    /// Phase the draw belongs to.
+    pub fn phase(self) -> RenderPhase {
+        match self.0 >> PHASE_SHIFT {
+            0 => RenderPhase::Opaque,
+            _ => RenderPhase::Transparent,
+        }
+    }
+}
+
+/// Small id of a GPU resource for [`SortKey`]s, derived from its address.
+///
+/// Different resources may get the same id, which only makes sorting less effective.
+pub fn resource_id<T>(resource: &T) -> u16 {
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    std::ptr::hash(resource, &mut hasher);
+    hasher.finish() as u16
+}
+
+/// Single draw call with the state it needs.
+#[derive(Debug, Clone)]
+pub struct Draw<'a> {
+    pub key: SortKey,
+    pub pipeline: &'a wgpu::RenderPipeline,
+    /// Bind groups by index, `None` binds the default group of the pass, e.g. the frame group.
+    pub bind_groups: [Option<&'a wgpu::BindGroup>; MAX_BIND_GROUPS],
+    /// Dynamic offsets of the bind groups by index, empty for default groups.
+    pub dynamic_offsets: [&'a [u32]; MAX_BIND_GROUPS],
+    pub vertex_buffer: &'a wgpu::Buffer,
+    pub index_buffer: Option<(&'a wgpu::Buffer, wgpu::IndexFormat)>,
+    /// Range of vertices, or of indices if the draw is indexed.
+    pub elements: Range<u32>,
+    pub instances: Range<u32>,
+}
+
+impl<'a> Draw<'a> {
+    /// Draw `vertices` of `vertex_buffer` with `pipeline`.
+    pub fn new(
+        key: SortKey,
+        pipeline: &'a wgpu::RenderPipeline,
+        vertex_buffer: &'a wgpu::Buffer,
+        vertices: Range<u32>,
+    ) -> Self {
+        Self {
+            key,
+            pipeline,
+            bind_groups: [None; MAX_BIND_GROUPS],
+            dynamic_offsets: [&[]; MAX_BIND_GROUPS],
+            vertex_buffer,
+            index_buffer: None,
+            elements: vertices,
+            instances: 0..1,
+        }
+    }
+
+    /// Bind `bind_group` at `index`.
+    pub fn bind_group(self, index: u32, bind_group: &'a wgpu::BindGroup) -> Self {
+        self.bind_group_with_offsets(index, bind_group, &[])
+    }
+
+    /// Bind `bind_group` at `index` with `offsets` for its dynamic buffers, in binding order.
+    pub fn bind_group_with_offsets(
+        mut self,
+        index: u32,
+        bind_group: &'a wgpu::BindGroup,
+        offsets: &'a [u32],
+    ) -> Self {
+        self.bind_groups[index as usize] = Some(bind_group);
+        self.dynamic_offsets[index as usize] = offsets;
+        self
+    }
+
+    /// Draw `indices` of `index_buffer` instead of vertices.
+    pub fn indexed(
+        mut self,
+        index_buffer: &'a wgpu::Buffer,
+        format: wgpu::IndexFormat,
+        indices: Range<u32>,
+    ) -> Self {
+        self.index_buffer = Some((index_buffer, format));
+        self.elements = indices;
+        self
+    }
+
+    /// Draw `instances` instead of a single one.
+    pub fn instances(mut self, instances: Range<u32>) -> Self {
+        self.instances = instances;
+        self
+    }
+
+    // Whether `next` can be recorded as part of the same draw call.
+    fn merges_with(&self, next: &Draw) -> bool {
+        self.state().merges_with(&next.state())
+    }
+
+    // Bind group at `index` with its dynamic offsets.
+    fn binding(&self, index: usize) -> Option<(&'a wgpu::BindGroup, &'a [u32])> {
+        self.bind_groups[index].map(|bind_group| (bind_group, self.dynamic_offsets[index]))
+    }
+
+    fn state(&self) -> DrawState<'a> {
+        DrawState {
+            pipeline: address(self.pipeline),
+            bindings: std::array::from_fn(|index| binding_id(self.binding(index))),
+            vertex_buffer: address(self.vertex_buffer),
+            index_buffer: self
+                .index_buffer
+                .map(|(buffer, format)| (address(buffer), format)),
+            elements: self.elements.clone(),
+            instances: self.instances.clone(),
+        }
+    }
+}
+
+// State a draw call is recorded with, resources identified by their addresses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct DrawState<'a> {
+    pipeline: usize,
+    bindings: [Option<(usize, &'a [u32])>; MAX_BIND_GROUPS],
+    vertex_buffer: usize,
+    index_buffer: Option<(usize, wgpu::IndexFormat)>,
+    elements: Range<u32>,
+    instances: Range<u32>,
+}
+
+impl DrawState<'_> {
+    // Whether `next` draws the instances right after these with the same state.
+    fn merges_with(&self, next: &Self) -> bool {
+        self.instances.end == next.instances.start
+            && self.pipeline == next.pipeline
+            && self.bindings == next.bindings
+            && self.vertex_buffer == next.vertex_buffer
+            && self.index_buffer == next.index_buffer
+            && self.elements == next.elements
+    }
+}
+
+/// State changes and draw calls needed to record a frame.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RenderStats {
+    /// Draws pushed into the queue.
+    pub draws: u32,
+    /// Draw calls recorded after merging.
+    pub draw_calls: u32,
+    pub pipeline_switches: u32,
+    pub bind_group_switches: u32,
+}
+
+/// Draws of a frame, recorded in [`SortKey`] order.
+#[derive(Debug)]
+pub struct RenderQueue<'a> {
+    view: [[f32; 4]; 4],
+    draws: Vec<Draw<'a>>,
+}
+
+impl<'a> RenderQueue<'a> {
+    /// Create an empty queue for draws seen from `view`.
+    pub fn new(view: &ViewUniform) -> Self {
+        Self {
+            view: view.view,
+            draws: Vec::new(),
+        }
+    }
+
+    /// Distance of world space `position` in front of the camera, for [`SortKey::new`].
+    pub fn depth(&self, position: [f32; 3]) -> f32 {
+        // The camera looks down -Z in view space.
+        -(0..3)
+            .map(|i| self.view[i][2] * position[i])
+            .fold(self.view[3][2], |sum, value| sum + value)
+    }
+
+    /// Add `draw` to the frame.
+    pub fn push(&mut self, draw: Draw<'a>) {
+        self.draws.push(draw);
+    }
+
+    /// Number of draws pushed so far.
+    pub fn len(&self) -> usize {
+        self.draws.len()
+    }
+
+    /// Returns `true` if no draw was pushed.
+    pub fn is_empty(&self) -> bool {
+        self.draws.is_empty()
+    }
+
+    /// Sort draws and record them into `pass`.
+    ///
+    /// `defaults` are the bind groups of draws that don't set their own at an index.
+    pub fn record(
+        mut self,
+        pass: &mut wgpu::RenderPass<'a>,
+        defaults: [Option<&'a wgpu::BindGroup>; MAX_BIND_GROUPS],
+    ) -> RenderStats {
+        // Stable, so draws with equal keys keep the order they were pushed in.
+        self.draws.sort_by_key(|draw| draw.key);
+
+        let mut stats = RenderStats {
+            draws: self.draws.len() as u32,
+            ..RenderStats::default()
+        };
+        let mut pipeline: Option<&wgpu::RenderPipeline> = None;
+        let mut bind_groups: [Option<(usize, &[u32])>; MAX_BIND_GROUPS] = [None; MAX_BIND_GROUPS];
+        let mut vertex_buffer: Option<&wgpu::Buffer> = None;
+        let mut index_buffer: Option<(&wgpu::Buffer, wgpu::IndexFormat)> = None;
+
+        let mut draws = self.draws.into_iter().peekable();
+        while let Some(mut draw) = draws.next() {
+            while let Some(next) = draws.next_if(|next| draw.merges_with(next)) {
+                draw.instances.end = next.instances.end;
+            }
+
+            if !pipeline.is_some_and(|pipeline| std::ptr::eq(pipeline, draw.pipeline)) {
+                pass.set_pipeline(draw.pipeline);
+                pipeline = Some(draw.pipeline);
+                stats.pipeline_switches += 1;
+            }
+            for (index, default) in defaults.iter().enumerate() {
+                let binding = draw
+                    .binding(index)
+                    .or(default.map(|bind_group| (bind_group, &[][..])));
+                if let Some((bind_group, offsets)) = binding {
+                    if bind_groups[index] != binding_id(binding) {
+                        pass.set_bind_group(index as u32, bind_group, offsets);
+                        bind_groups[index] = binding_id(binding);
+                        stats.bind_group_switches += 1;
+                    }
+                }
+            }
+            if !same(vertex_buffer, Some(draw.vertex_buffer)) {
+                pass.set_vertex_buffer(0, draw.vertex_buffer.slice(..));
+                vertex_buffer = Some(draw.vertex_buffer);
+            }
+
+            match draw.index_buffer {
+                Some((buffer, format)) => {
+                    let changed = !index_buffer.is_some_and(|(current, current_format)| {
+                        std::ptr::eq(current, buffer) && current_format == format
+                    });
+                    if changed {
+                        pass.set_index_buffer(buffer.slice(..), format);
+                        index_buffer = Some((buffer, format));
+                    }
+                    pass.draw_indexed(draw.elements, 0, draw.instances);
+                }
+                None => pass.draw(draw.elements, draw.instances),
+            }
+            stats.draw_calls += 1;
+        }
+        stats
+    }
+}
+
+// Whether both are the same resource, or both are missing.
+fn same<T>(a: Option<&T>, b: Option<&T>) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => std::ptr::eq(a, b),
+        (a, b) => a.is_none() && b.is_none(),
+    }
+}
+
+// Address of a resource, which identifies it while it is borrowed.
+fn address<T>(resource: &T) -> usize {
+    resource as *const T as usize
+}
+
+// Bind group identified by its address, along with its dynamic offsets.
+fn binding_id<'a>(binding: Option<(&wgpu::BindGroup, &'a [u32])>) -> Option<(usize, &'a [u32])> {
+    binding.map(|(bind_group, offsets)| (address(bind_group), offsets))
+}
+
+// Non-negative floats keep their order when compared as bits, the sign bit is dropped.
+fn quantize_depth(depth: f32) -> u64 {
+    (depth.max(0.0).to_bits() >> 1) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(phase: RenderPhase, depth: f32) -> SortKey {
+        SortKey::new(phase, 1, 2, depth)
+    }
+
+    fn state(instances: Range<u32>) -> DrawState<'static> {
+        DrawState {
+            pipeline: 1,
+            bindings: [None, None, Some((2, &[])), None],
+            vertex_buffer: 3,
+            index_buffer: None,
+            elements: 0..3,
+            instances,
+        }
+    }
+
+    fn device() -> wgpu::Device {
+        let instance = wgpu::Instance::new(wgpu::Backends::all());
+        let adapter = pollster::block_on(instance.request_adapter(&Default::default()))
+            .expect("no adapter to test with");
+        let (device, _) = pollster::block_on(adapter.request_device(&Default::default(), None))
+            .expect("failed to create device");
+        device
+    }
+
+    fn pipeline(device: &wgpu::Device) -> wgpu::RenderPipeline {
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: None,
+            source: wgpu::ShaderSource::Wgsl(
+                "@vertex fn vertex() -> @builtin(position) vec4<f32> { return vec4<f32>(0.0); }"
+                    .into(),
+            ),
+        });
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: None,
+            layout: None,
+            vertex: wgpu::VertexState {
+                module: &module,
+                entry_point: "vertex",
+                buffers: &[],
+            },
+            primitive: Default::default(),
+            depth_stencil: None,
+            multisample: Default::default(),
+            fragment: None,
+            multiview: None,
+        })
+    }
+
+    fn buffer(device: &wgpu::Device) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: 64,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::UNIFORM,
+            mapped_at_creation: false,
+        })
+    }
+
+    #[test]
+    fn opaque_draws_sort_front_to_back() {
+        let near = key(RenderPhase::Opaque, 1.0);
+        let far = key(RenderPhase::Opaque, 10.0);
+        assert!(near < far);
+        assert!(
+            SortKey::new(RenderPhase::Opaque, 1, 2, 100.0)
+                < SortKey::new(RenderPhase::Opaque, 1, 3, 0.0)
+        );
+    }
+
+    #[test]
+    fn transparent_draws_sort_back_to_front() {
+        let near = key(RenderPhase::Transparent, 1.0);
+        let far = key(RenderPhase::Transparent, 10.0);
+        assert!(far < near);
+        assert!(
+            SortKey::new(RenderPhase::Transparent, 9, 9, 10.0)
+                < SortKey::new(RenderPhase::Transparent, 0, 0, 1.0)
+        );
+    }
+
+    #[test]
+    fn phase_dominates_order() {
+        let opaque = SortKey::new(RenderPhase::Opaque, u16::MAX, u16::MAX, f32::MAX);
+        let transparent = SortKey::new(RenderPhase::Transparent, 0, 0, f32::MAX);
+        assert!(opaque < transparent);
+        assert_eq!(opaque.phase(), RenderPhase::Opaque);
+        assert_eq!(transparent.phase(), RenderPhase::Transparent);
+    }
+
+    #[test]
+    fn quantized_depth_keeps_order() {
+        let depths = [0.0, 1e-6, 0.5, 1.0, 2.0, 1e3, f32::MAX];
+        for pair in depths.windows(2) {
+            assert!(quantize_depth(pair[0]) < quantize_depth(pair[1]));
+        }
+        assert_eq!(quantize_depth(-1.0), quantize_depth(0.0));
+        assert!(quantize_depth(f32::MAX) <= DEPTH_MASK);
+    }
+
+    #[test]
+    fn only_adjacent_instances_merge() {
+        assert!(state(0..2).merges_with(&state(2..3)));
+        assert!(!state(0..2).merges_with(&state(3..4)));
+        assert!(!state(2..3).merges_with(&state(0..2)));
+        assert!(!state(0..2).merges_with(&state(1..3)));
+    }
+
+    #[test]
+    fn state_changes_prevent_merging() {
+        let changes = [
+            DrawState {
+                pipeline: 4,
+                ..state(2..3)
+            },
+            DrawState {
+                bindings: [None, None, Some((4, &[])), None],
+                ..state(2..3)
+            },
+            DrawState {
+                bindings: [None; MAX_BIND_GROUPS],
+                ..state(2..3)
+            },
+            DrawState {
+                vertex_buffer: 4,
+                ..state(2..3)
+            },
+            DrawState {
+                index_buffer: Some((3, wgpu::IndexFormat::Uint32)),
+                ..state(2..3)
+            },
+            DrawState {
+                elements: 3..6,
+                ..state(2..3)
+            },
+        ];
+        for next in &changes {
+            assert!(!state(0..2).merges_with(next), "{:?}", next);
+        }
+
+        let indexed = |instances, format| DrawState {
+            index_buffer: Some((3, format)),
+            ..state(instances)
+        };
+        assert!(indexed(0..2, wgpu::IndexFormat::Uint32)
+            .merges_with(&indexed(2..3, wgpu::IndexFormat::Uint32)));
+        assert!(!indexed(0..2, wgpu::IndexFormat::Uint32)
+            .merges_with(&indexed(2..3, wgpu::IndexFormat::Uint16)));
+    }
+
+    #[test]
+    fn dynamic_offsets_prevent_merging() {
+        let offsets = |instances, offsets: &'static [u32]| DrawState {
+            bindings: [None, None, Some((2, offsets)), None],
+            ..state(instances)
+        };
+        assert!(offsets(0..1, &[256]).merges_with(&offsets(1..2, &[256])));
+        assert!(!offsets(0..1, &[0]).merges_with(&offsets(1..2, &[256])));
+        assert!(!offsets(0..1, &[]).merges_with(&offsets(1..2, &[0])));
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter"]
+    fn draws_of_same_resources_merge() {
+        let device = device();
+        let pipeline = pipeline(&device);
+        let vertices = buffer(&device);
+        let other_vertices = buffer(&device);
+        let draw = |instances: Range<u32>| {
+            Draw::new(key(RenderPhase::Opaque, 1.0), &pipeline, &vertices, 0..3)
+                .instances(instances)
+        };
+
+        assert!(draw(0..2).merges_with(&draw(2..3)));
+        assert!(!draw(0..2).merges_with(&draw(3..4)));
+        assert!(!draw(2..3).merges_with(&draw(0..2)));
+        let indexed = draw(2..3).indexed(&vertices, wgpu::IndexFormat::Uint32, 0..3);
+        assert!(!draw(0..2).merges_with(&indexed));
+        let other = Draw::new(
+            key(RenderPhase::Opaque, 1.0),
+            &pipeline,
+            &other_vertices,
+            0..3,
+        )
+        .instances(2..3);
+        assert!(!draw(0..2).merges_with(&other));
+    }
+
+    #[test]
+    #[ignore = "needs a GPU adapter"]
+    fn draws_with_same_offsets_merge() {
+        let device = device();
+        let pipeline = pipeline(&device);
+        let vertices = buffer(&device);
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &layout,
+            entries: &[],
+        });
+        let draw = |instances: Range<u32>, offsets: &'static [u32]| {
+            Draw::new(key(RenderPhase::Opaque, 1.0), &pipeline, &vertices, 0..3)
+                .bind_group_with_offsets(2, &bind_group, offsets)
+                .instances(instances)
+        };
+
+        assert!(draw(0..1, &[256]).merges_with(&draw(1..2, &[256])));
+        assert!(!draw(0..1, &[0]).merges_with(&draw(1..2, &[256])));
+    }
+}
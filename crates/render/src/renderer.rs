@@ -1,6 +1,25 @@
 //!
 //! Traits and structs for rendering process
 //!
+use std::time::Instant;
+
+use crate::bind_group_cache::BindGroupCache;
+use crate::bindings::{self, FrameUniform, ViewUniform};
+use crate::buffers::uniform::UniformBuffer;
+use crate::render_pass;
+use crate::render_pipleine_builder::RenderPipelineBuilder;
+use crate::render_queue::{RenderQueue, RenderStats};
+
+/// Part of the frame a draw belongs to, the most significant bits of its
+/// [`crate::render_queue::SortKey`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum RenderPhase {
+    /// Drawn first.
+    #[default]
+    Opaque,
+    /// Drawn after all opaque objects, so they show through.
+    Transparent,
+}
 
 /// Trait for objects that can be drawn.
 pub trait Renderable {
@@ -9,13 +28,20 @@ pub trait Renderable {
     fn should_render(&self, _context: &RenderingContext) -> bool {
         true
     }
+    /// Phase the draws of object belong to.
+    fn phase(&self) -> RenderPhase {
+        RenderPhase::Opaque
+    }
     /// Update object with given context.
     ///
     /// On this step object need to rebind buffers and update it's content.
     /// Usually there's no need to rebind, because binding is stored along object.
     fn update(&mut self, context: &mut RenderingContext);
-    /// Render object using given context
-    fn render(&mut self, context: &mut RenderingContext);
+    /// Push draws of object into `queue`, shared with other objects.
+    ///
+    /// Draws that don't set their own bind groups get the frame and view groups, see
+    /// [`RenderingContext::draw`].
+    fn render<'a>(&'a self, queue: &mut RenderQueue<'a>);
 }
 
 /// Represents context for render pass.
@@ -26,6 +52,14 @@ pub struct RenderingContext<'a> {
     pub queue: &'a mut wgpu::Queue,
     /// Target to which result of rendering will be written to
     pub output: &'a wgpu::TextureView,
+    /// Depth buffer of `output`, in [`Renderer::DEPTH_FORMAT`]
+    pub depth: &'a wgpu::TextureView,
+    /// Globals of the frame, bound at [`crate::bindings::FRAME_GROUP`]
+    pub frame: &'a UniformBuffer<FrameUniform>,
+    /// Camera of the view being rendered, bound at [`crate::bindings::VIEW_GROUP`]
+    pub view: &'a UniformBuffer<ViewUniform>,
+    /// Contents of `view`, used to sort draws by depth
+    pub camera: &'a ViewUniform,
 }
 
 impl<'a> RenderingContext<'a> {
@@ -41,4 +75,254 @@ impl<'a> RenderingContext<'a> {
     pub fn submit(&self, encoder: wgpu::CommandEncoder) {
         self.queue.submit(Some(encoder.finish()));
     }
+
+    /// Draw `renderables` into [`RenderingContext::output`] cleared with `clear_color`, testing
+    /// against [`RenderingContext::depth`] cleared to the far plane.
+    ///
+    /// Their draws are sorted through a [`RenderQueue`], recorded into a single render pass
+    /// with the frame and view bind groups as defaults, and submitted as a single command buffer.
+    pub fn draw(
+        &mut self,
+        renderables: &[&dyn Renderable],
+        clear_color: wgpu::Color,
+    ) -> RenderStats {
+        let mut queue = RenderQueue::new(self.camera);
+        for renderable in renderables {
+            renderable.render(&mut queue);
+        }
+
+        let mut encoder = self.create_encoder("Frame encoder");
+        let stats = {
+            let mut pass = render_pass::Builder::new()
+                .color_attachment(self.output, |attachment| {
+                    attachment.load_op(wgpu::LoadOp::Clear(clear_color))
+                })
+                .depth_stencil_attachment(self.depth, |attachment| attachment)
+                .begin(&mut encoder);
+            let defaults = [
+                Some(&self.frame.bind_group),
+                Some(&self.view.bind_group),
+                None,
+                None,
+            ];
+            queue.record(&mut pass, defaults)
+        };
+        self.submit(encoder);
+        stats
+    }
+}
+
+/// Handle of a renderable added to a [`Renderer`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RenderableId(usize);
+
+/// Draws registered [`Renderable`]s to a surface every frame.
+///
+/// Like the caches, a renderer belongs to a single [`wgpu::Device`] and must always be used with
+/// it. It owns a depth buffer the size of the surface, so pipelines of the renderables must use
+/// [`Renderer::DEPTH_FORMAT`].
+///
+/// Nothing is drawn while the surface has no area, e.g. when the window is minimized.
+///
+/// # Examples
+///
+/// ```ignore
+/// let mut renderer = Renderer::new(&device, &mut bind_groups, surface, config);
+/// renderer.add(Box::new(object));
+///
+/// // On resize:
+/// renderer.resize(&device, size.width, size.height);
+/// // Every frame:
+/// renderer.render(&device, &mut queue)?;
+/// ```
+pub struct Renderer {
+    surface: wgpu::Surface,
+    config: wgpu::SurfaceConfiguration,
+    depth: wgpu::TextureView,
+    frame: UniformBuffer<FrameUniform>,
+    view: UniformBuffer<ViewUniform>,
+    camera: ViewUniform,
+    renderables: Vec<(RenderableId, Box<dyn Renderable>)>,
+    next_id: usize,
+    clear_color: wgpu::Color,
+    stats: RenderStats,
+    start: Instant,
+    last_frame: Instant,
+    frame_index: u32,
+}
+
+impl Renderer {
+    /// Format of the depth buffer.
+    pub const DEPTH_FORMAT: wgpu::TextureFormat = RenderPipelineBuilder::DEFAULT_DEPTH_FORMAT;
+
+    /// Create a renderer drawing to `surface`, which is configured with `config`.
+    pub fn new(
+        device: &wgpu::Device,
+        cache: &mut BindGroupCache,
+        surface: wgpu::Surface,
+        config: wgpu::SurfaceConfiguration,
+    ) -> Self {
+        if has_area(&config) {
+            surface.configure(device, &config);
+        }
+        let depth = create_depth_view(device, &config);
+        let now = Instant::now();
+        Self {
+            surface,
+            config,
+            depth,
+            frame: bindings::uniform(device, cache, FrameUniform::default(), "Frame"),
+            view: bindings::uniform(device, cache, ViewUniform::default(), "View"),
+            camera: ViewUniform::default(),
+            renderables: Vec::new(),
+            next_id: 0,
+            clear_color: wgpu::Color::TRANSPARENT,
+            stats: RenderStats::default(),
+            start: now,
+            last_frame: now,
+            frame_index: 0,
+        }
+    }
+
+    /// Register `renderable` to be updated and drawn every frame.
+    pub fn add(&mut self, renderable: Box<dyn Renderable>) -> RenderableId {
+        let id = RenderableId(self.next_id);
+        self.next_id += 1;
+        self.renderables.push((id, renderable));
+        id
+    }
+
+    /// Unregister renderable `id`, returning it.
+    pub fn remove(&mut self, id: RenderableId) -> Option<Box<dyn Renderable>> {
+        let index = self.renderables.iter().position(|(i, _)| *i == id)?;
+        Some(self.renderables.remove(index).1)
+    }
+
+    /// Renderable `id`, if it's still registered.
+    pub fn get_mut(&mut self, id: RenderableId) -> Option<&mut (dyn Renderable + 'static)> {
+        self.renderables
+            .iter_mut()
+            .find(|(i, _)| *i == id)
+            .map(|(_, renderable)| &mut **renderable)
+    }
+
+    /// Color the surface is cleared with before drawing.
+    pub fn set_clear_color(&mut self, color: wgpu::Color) {
+        self.clear_color = color;
+    }
+
+    /// Camera the following frames are drawn with.
+    pub fn set_view(&mut self, queue: &wgpu::Queue, view: &ViewUniform) {
+        self.view.copy_to_gpu(queue, view);
+        self.camera = *view;
+    }
+
+    /// State changes and draw calls of the last frame.
+    pub fn stats(&self) -> RenderStats {
+        self.stats
+    }
+
+    /// Configuration of the surface.
+    pub fn config(&self) -> &wgpu::SurfaceConfiguration {
+        &self.config
+    }
+
+    /// Reconfigure the surface and recreate the depth buffer for new window size.
+    ///
+    /// Surface is left as is while either size is 0, and frames are skipped until the next
+    /// resize.
+    pub fn resize(&mut self, device: &wgpu::Device, width: u32, height: u32) {
+        self.config.width = width;
+        self.config.height = height;
+        if !has_area(&self.config) {
+            return;
+        }
+        self.surface.configure(device, &self.config);
+        self.depth = create_depth_view(device, &self.config);
+    }
+
+    /// Update all renderables and draw the ones that should render.
+    ///
+    /// A lost or outdated surface is reconfigured and the frame is skipped, other surface errors
+    /// are returned. Frame is skipped as well while the surface has no area.
+    pub fn render(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &mut wgpu::Queue,
+    ) -> Result<(), wgpu::SurfaceError> {
+        if !has_area(&self.config) {
+            return Ok(());
+        }
+        let output = match self.surface.get_current_texture() {
+            Ok(output) => output,
+            Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+                self.surface.configure(device, &self.config);
+                return Ok(());
+            }
+            Err(err) => return Err(err),
+        };
+        let target = output
+            .texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
+
+        let now = Instant::now();
+        let frame = FrameUniform::new(
+            (now - self.start).as_secs_f32(),
+            (now - self.last_frame).as_secs_f32(),
+            self.frame_index,
+        );
+        self.frame.copy_to_gpu(queue, &frame);
+        self.last_frame = now;
+        self.frame_index = self.frame_index.wrapping_add(1);
+
+        let mut context = RenderingContext {
+            device,
+            queue,
+            output: &target,
+            depth: &self.depth,
+            frame: &self.frame,
+            view: &self.view,
+            camera: &self.camera,
+        };
+        for (_, renderable) in &mut self.renderables {
+            renderable.update(&mut context);
+        }
+
+        let renderables: Vec<_> = self
+            .renderables
+            .iter()
+            .map(|(_, renderable)| &**renderable)
+            .filter(|renderable| renderable.should_render(&context))
+            .collect();
+        self.stats = context.draw(&renderables, self.clear_color);
+
+        output.present();
+        Ok(())
+    }
+}
+
+// Surfaces and textures can't be created with a zero size.
+fn has_area(config: &wgpu::SurfaceConfiguration) -> bool {
+    config.width > 0 && config.height > 0
+}
+
+// Depth buffer matching the size of the surface, at least one pixel.
+fn create_depth_view(
+    device: &wgpu::Device,
+    config: &wgpu::SurfaceConfiguration,
+) -> wgpu::TextureView {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Depth buffer"),
+        size: wgpu::Extent3d {
+            width: config.width.max(1),
+            height: config.height.max(1),
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: Renderer::DEPTH_FORMAT,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+    });
+    texture.create_view(&wgpu::TextureViewDescriptor::default())
 }
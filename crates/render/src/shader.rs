@@ -1,34 +1,171 @@
 use std::borrow::Cow;
+use std::fmt;
 use std::ops::Deref;
+use std::path::Path;
 
 use wgpu::Device;
 use wgpu::ShaderModule;
 use wgpu::ShaderStages;
 
+use crate::shader_preprocessor::{PreprocessError, Preprocessor, ProcessedShader, WgslError};
+use crate::shader_reflection::{ReflectionError, ShaderReflection};
+
+/// Shader module together with the stages its entry points are meant for.
+///
+/// A single module may hold several stages, e.g. `ShaderStages::VERTEX | ShaderStages::FRAGMENT`.
+#[derive(Debug)]
 pub struct Shader {
     stage: ShaderStages,
     shader: ShaderModule,
+    source: Cow<'static, str>,
 }
 
 impl Shader {
+    /// Compile WGSL `contents` into a shader module for `stage`.
     pub fn from_string(
         device: &Device,
         contents: impl Into<Cow<'static, str>>,
         stage: ShaderStages,
         label: Option<&str>,
     ) -> Self {
+        let source = contents.into();
         Self {
             shader: device.create_shader_module(wgpu::ShaderModuleDescriptor {
                 label,
-                source: wgpu::ShaderSource::Wgsl(contents.into()),
+                source: wgpu::ShaderSource::Wgsl(source.clone()),
             }),
             stage,
+            source,
         }
     }
 
+    /// Compile a shader expanded by [`Preprocessor`].
+    ///
+    /// The source is validated with naga first so errors point to the original files, and anything
+    /// else wgpu rejects is caught by an error scope instead of panicking, except on the web.
+    pub fn from_processed(
+        device: &Device,
+        processed: &ProcessedShader,
+        stage: ShaderStages,
+        label: Option<&str>,
+    ) -> Result<Self, ShaderError> {
+        processed.validate()?;
+        Self::from_checked(device, processed.source.clone(), stage, label)
+    }
+
+    /// Load and compile WGSL file at `path`, expanding its directives with `preprocessor`.
+    ///
+    /// `#include`s of files are resolved relative to `path`.
+    pub fn from_file(
+        device: &Device,
+        preprocessor: &Preprocessor,
+        path: impl AsRef<Path>,
+        stage: ShaderStages,
+        label: Option<&str>,
+    ) -> Result<Self, ShaderError> {
+        let processed = preprocessor.process_file(path)?;
+        Self::from_processed(device, &processed, stage, label)
+    }
+
+    /// Compile GLSL `source` for the single `stage`, as if `defines` were `#define`d at its top.
+    ///
+    /// The source is translated to WGSL by naga, which is what [`Shader::source`] returns. GLSL
+    /// entry points are named `main`, so pipeline builders need their entry point set to it.
+    pub fn from_glsl(
+        device: &Device,
+        source: &str,
+        stage: ShaderStages,
+        defines: &[(&str, &str)],
+        label: Option<&str>,
+    ) -> Result<Self, ShaderError> {
+        let module = parse_glsl(source, stage, defines)?;
+        Self::from_module(device, &module, label)
+    }
+
+    /// Compile SPIR-V binary `data`, with stages taken from its entry points.
+    ///
+    /// The module is translated to WGSL by naga, which is what [`Shader::source`] returns.
+    pub fn from_spirv(
+        device: &Device,
+        data: &[u8],
+        label: Option<&str>,
+    ) -> Result<Self, ShaderError> {
+        let module = parse_spirv(data)?;
+        Self::from_module(device, &module, label)
+    }
+
+    /// Compile a naga `module`, with stages taken from its entry points.
+    ///
+    /// The module is translated to WGSL, which is what [`Shader::source`] returns.
+    pub fn from_module(
+        device: &Device,
+        module: &naga::Module,
+        label: Option<&str>,
+    ) -> Result<Self, ShaderError> {
+        let (source, stage) = translate(module)?;
+        Self::from_checked(device, source, stage, label)
+    }
+
+    // Compile valid WGSL `source`, catching what wgpu still rejects.
+    fn from_checked(
+        device: &Device,
+        source: String,
+        stage: ShaderStages,
+        label: Option<&str>,
+    ) -> Result<Self, ShaderError> {
+        catch_validation(device, || Self::from_string(device, source, stage, label))
+            .map_err(ShaderError::Validation)
+    }
+
+    /// Stages this shader has entry points for.
     pub fn stage(&self) -> ShaderStages {
         self.stage
     }
+
+    /// WGSL source the shader was compiled from.
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Parse the source with naga and reflect bindings and entry points, see [`ShaderReflection`].
+    pub fn reflect(&self) -> Result<ShaderReflection, ReflectionError> {
+        ShaderReflection::from_wgsl(&self.source)
+    }
+}
+
+/// Shader module used by a pipeline builder, with stages known if it came from a [`Shader`].
+#[derive(Debug, Clone, Copy)]
+pub struct ShaderRef<'a> {
+    pub module: &'a ShaderModule,
+    pub stage: Option<ShaderStages>,
+    pub source: Option<&'a str>,
+}
+
+impl<'a> ShaderRef<'a> {
+    /// Whether the module may be used for `stage`, always true when stages are unknown.
+    pub fn supports(&self, stage: ShaderStages) -> bool {
+        self.stage.is_none_or(|stages| stages.contains(stage))
+    }
+}
+
+impl<'a> From<&'a Shader> for ShaderRef<'a> {
+    fn from(shader: &'a Shader) -> Self {
+        Self {
+            module: &shader.shader,
+            stage: Some(shader.stage),
+            source: Some(&shader.source),
+        }
+    }
+}
+
+impl<'a> From<&'a ShaderModule> for ShaderRef<'a> {
+    fn from(module: &'a ShaderModule) -> Self {
+        Self {
+            module,
+            stage: None,
+            source: None,
+        }
+    }
 }
 
 impl Deref for Shader {
@@ -38,3 +175,232 @@ impl Deref for Shader {
         &self.shader
     }
 }
+
+/// Error of loading a [`Shader`] from a file or a [`ProcessedShader`].
+#[derive(Debug, Clone)]
+pub enum ShaderError {
+    /// File could not be read or its directives expanded.
+    Preprocess(PreprocessError),
+    /// Expanded source is not valid WGSL.
+    Wgsl(WgslError),
+    /// GLSL or SPIR-V source failed to parse, contains the formatted errors.
+    Parse(String),
+    /// naga failed to validate a module or translate it to WGSL, contains the formatted error.
+    Translation(String),
+    /// GLSL sources are compiled for exactly one stage.
+    Stage(ShaderStages),
+    /// wgpu validation failed, contains the formatted wgpu error.
+    Validation(String),
+}
+
+impl From<PreprocessError> for ShaderError {
+    fn from(err: PreprocessError) -> Self {
+        Self::Preprocess(err)
+    }
+}
+
+impl From<WgslError> for ShaderError {
+    fn from(err: WgslError) -> Self {
+        Self::Wgsl(err)
+    }
+}
+
+impl fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Preprocess(err) => write!(f, "{}", err),
+            Self::Wgsl(err) => write!(f, "{}", err),
+            Self::Parse(err) => write!(f, "failed to parse shader: {}", err),
+            Self::Translation(err) => write!(f, "failed to translate shader: {}", err),
+            Self::Stage(stage) => write!(f, "GLSL shader can't be compiled for {:?}", stage),
+            Self::Validation(err) => write!(f, "shader validation failed: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for ShaderError {}
+
+// Parse GLSL `source` for the single `stage` with `defines`.
+fn parse_glsl(
+    source: &str,
+    stage: ShaderStages,
+    defines: &[(&str, &str)],
+) -> Result<naga::Module, ShaderError> {
+    let naga_stage = match stage {
+        ShaderStages::VERTEX => naga::ShaderStage::Vertex,
+        ShaderStages::FRAGMENT => naga::ShaderStage::Fragment,
+        ShaderStages::COMPUTE => naga::ShaderStage::Compute,
+        _ => return Err(ShaderError::Stage(stage)),
+    };
+    let mut options = naga::front::glsl::Options::from(naga_stage);
+    options.defines.extend(
+        defines
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string())),
+    );
+
+    naga::front::glsl::Parser::default()
+        .parse(&options, source)
+        .map_err(|errors| {
+            let errors: Vec<_> = errors
+                .iter()
+                .map(|err| {
+                    let location = err.meta.location(source);
+                    format!(
+                        "{}:{}: {}",
+                        location.line_number, location.line_position, err
+                    )
+                })
+                .collect();
+            ShaderError::Parse(errors.join("\n"))
+        })
+}
+
+fn parse_spirv(data: &[u8]) -> Result<naga::Module, ShaderError> {
+    // Same options wgpu uses for SPIR-V sources.
+    let options = naga::front::spv::Options {
+        adjust_coordinate_space: false,
+        strict_capabilities: true,
+        block_ctx_dump_prefix: None,
+    };
+    naga::front::spv::parse_u8_slice(data, &options)
+        .map_err(|err| ShaderError::Parse(err.to_string()))
+}
+
+// Validate `module` and write it as WGSL, along with the stages of its entry points.
+fn translate(module: &naga::Module) -> Result<(String, ShaderStages), ShaderError> {
+    let info = naga::valid::Validator::new(
+        naga::valid::ValidationFlags::all(),
+        naga::valid::Capabilities::all(),
+    )
+    .validate(module)
+    .map_err(|err| ShaderError::Translation(err.to_string()))?;
+    let source =
+        naga::back::wgsl::write_string(module, &info, naga::back::wgsl::WriterFlags::empty())
+            .map_err(|err| ShaderError::Translation(err.to_string()))?;
+
+    let stage = module
+        .entry_points
+        .iter()
+        .fold(ShaderStages::NONE, |stages, ep| {
+            stages
+                | match ep.stage {
+                    naga::ShaderStage::Vertex => ShaderStages::VERTEX,
+                    naga::ShaderStage::Fragment => ShaderStages::FRAGMENT,
+                    naga::ShaderStage::Compute => ShaderStages::COMPUTE,
+                }
+        });
+    Ok((source, stage))
+}
+
+// Run `create` in a validation error scope, returning the error wgpu caught.
+//
+// Popping the scope means blocking on its future, which the web doesn't allow, so there errors
+// are left to the uncaptured error handler of the device.
+pub(crate) fn catch_validation<T>(
+    device: &Device,
+    create: impl FnOnce() -> T,
+) -> Result<T, String> {
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let created = create();
+        match pollster::block_on(device.pop_error_scope()) {
+            Some(err) => Err(err.to_string()),
+            None => Ok(created),
+        }
+    }
+    #[cfg(target_arch = "wasm32")]
+    {
+        let _ = device;
+        Ok(create())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRAGMENT: &str = "#version 450
+        layout(location = 0) out vec4 color;
+
+        void main() {
+        #ifdef RED
+            color = vec4(RED, 0.0, 0.0, 1.0);
+        #else
+            color = vec4(0.0, 0.0, 1.0, 1.0);
+        #endif
+        }
+    ";
+
+    #[test]
+    fn applies_glsl_defines() {
+        let blue = parse_glsl(FRAGMENT, ShaderStages::FRAGMENT, &[]).unwrap();
+        let red = parse_glsl(FRAGMENT, ShaderStages::FRAGMENT, &[("RED", "0.5")]).unwrap();
+
+        let (blue, _) = translate(&blue).unwrap();
+        let (red, _) = translate(&red).unwrap();
+        assert!(blue.contains("vec4<f32>(0.0, 0.0, 1.0, 1.0)"), "{}", blue);
+        assert!(red.contains("vec4<f32>(0.5, 0.0, 0.0, 1.0)"), "{}", red);
+    }
+
+    #[test]
+    fn compiles_glsl_for_one_stage() {
+        let module = parse_glsl(FRAGMENT, ShaderStages::FRAGMENT, &[]).unwrap();
+        let (_, stage) = translate(&module).unwrap();
+
+        assert_eq!(stage, ShaderStages::FRAGMENT);
+        assert_eq!(module.entry_points[0].name, "main");
+        assert!(matches!(
+            parse_glsl(FRAGMENT, ShaderStages::VERTEX_FRAGMENT, &[]),
+            Err(ShaderError::Stage(ShaderStages::VERTEX_FRAGMENT))
+        ));
+        assert!(matches!(
+            parse_glsl(FRAGMENT, ShaderStages::NONE, &[]),
+            Err(ShaderError::Stage(ShaderStages::NONE))
+        ));
+    }
+
+    #[test]
+    fn reports_glsl_errors_with_location() {
+        let err = parse_glsl(
+            "#version 450\nvoid main() {\n    x = 1;\n}\n",
+            ShaderStages::VERTEX,
+            &[],
+        )
+        .unwrap_err();
+
+        match err {
+            ShaderError::Parse(err) => assert!(err.starts_with("3:"), "{}", err),
+            err => panic!("unexpected error: {}", err),
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_spirv() {
+        assert!(matches!(parse_spirv(&[0; 16]), Err(ShaderError::Parse(_))));
+    }
+
+    #[test]
+    fn derives_stages_from_entry_points() {
+        let module = naga::front::wgsl::parse_str(
+            "@vertex
+            fn vertex() -> @builtin(position) vec4<f32> {
+                return vec4<f32>(0.0);
+            }
+
+            @fragment
+            fn fragment() -> @location(0) vec4<f32> {
+                return vec4<f32>(1.0);
+            }",
+        )
+        .unwrap();
+        let (source, stage) = translate(&module).unwrap();
+
+        assert_eq!(stage, ShaderStages::VERTEX_FRAGMENT);
+        assert!(ShaderReflection::from_wgsl(&source).is_ok());
+
+        let (_, stage) = translate(&naga::Module::default()).unwrap();
+        assert_eq!(stage, ShaderStages::NONE);
+    }
+}
@@ -0,0 +1,746 @@
+//!
+//! WGSL preprocessor
+//!
+//! WGSL has no way to share code between files, so [`Preprocessor`] adds a few C-like
+//! directives on top of it. Directives take a whole line:
+//!
+//! - `#import name` pastes module `name` unless it was already imported into this shader.
+//! - `#include "name"` pastes module `name` every time, falling back to a file relative to the
+//!   including one.
+//! - `#define NAME [value]` and `#undef NAME` manage defines. Later occurrences of `NAME` as a
+//!   whole word are replaced by `value`, if there is one.
+//! - `#ifdef NAME`, `#ifndef NAME`, `#else` and `#endif` select code for shader permutations.
+//!
+//! [`ProcessedShader`] remembers where every output line came from, so both preprocessor and
+//! WGSL errors point to the original file and line.
+//!
+//! # Examples
+//!
+//! ```
+//! use render::shader_preprocessor::Preprocessor;
+//!
+//! let preprocessor = Preprocessor::new()
+//!     .module("common", "struct Light {\n    color: vec3<f32>,\n};\n")
+//!     .define("SHADOWS", "");
+//!
+//! let source = "#import common\n#ifdef SHADOWS\nlet shadows = true;\n#else\nlet shadows = false;\n#endif\n";
+//! let shader = preprocessor.process("lit.wgsl", source).unwrap();
+//!
+//! assert!(shader.source.contains("struct Light"));
+//! assert!(shader.source.contains("shadows = true"));
+//! assert_eq!(shader.origin(4).unwrap().to_string(), "lit.wgsl:3");
+//! ```
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Line of an original file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLocation {
+    /// Module name or file path.
+    pub file: Arc<str>,
+    /// 1-based line number.
+    pub line: usize,
+}
+
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.file, self.line)
+    }
+}
+
+/// Expands directives of WGSL sources, see [module level documentation](self).
+#[derive(Debug, Clone, Default)]
+pub struct Preprocessor {
+    modules: HashMap<String, String>,
+    defines: HashMap<String, String>,
+}
+
+/// WGSL source produced by [`Preprocessor`], with origins of its lines.
+#[derive(Debug, Clone)]
+pub struct ProcessedShader {
+    /// Expanded WGSL source.
+    pub source: String,
+    origins: Vec<SourceLocation>,
+}
+
+impl Preprocessor {
+    /// Create a preprocessor without modules and defines.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register module `source` under `name` for `#import` and `#include`.
+    pub fn module(mut self, name: &str, source: &str) -> Self {
+        self.modules.insert(name.to_string(), source.to_string());
+        self
+    }
+
+    /// Define `name` for every processed shader, an empty `value` only makes it defined.
+    pub fn define(mut self, name: &str, value: &str) -> Self {
+        self.defines.insert(name.to_string(), value.to_string());
+        self
+    }
+
+    /// Source of module `name`, if registered.
+    pub fn module_source(&self, name: &str) -> Option<&str> {
+        self.modules.get(name).map(String::as_str)
+    }
+
+    /// Names checked by `#ifdef` and `#ifndef` in `source` and everything it imports or includes,
+    /// i.e. the defines producing its permutations.
+    ///
+    /// Directives are scanned regardless of conditions and modules that can't be found are skipped,
+    /// [`Preprocessor::process`] reports them.
+    pub fn conditions(&self, file: &str, source: &str) -> Vec<String> {
+        let mut conditions = Vec::new();
+        let mut visited = HashSet::new();
+        self.scan_conditions(file, source, &mut visited, &mut conditions);
+        conditions
+    }
+
+    /// Defines passed to every processed shader.
+    pub fn defines(&self) -> &HashMap<String, String> {
+        &self.defines
+    }
+
+    /// Expand directives of `source`, with `file` used in error messages and to resolve
+    /// `#include`s of files.
+    pub fn process(&self, file: &str, source: &str) -> Result<ProcessedShader, PreprocessError> {
+        let mut state = State {
+            defines: self.defines.clone(),
+            imported: HashSet::new(),
+            stack: Vec::new(),
+            output: ProcessedShader {
+                source: String::new(),
+                origins: Vec::new(),
+            },
+        };
+        self.expand(&mut state, Arc::from(file), source)?;
+        Ok(state.output)
+    }
+
+    /// Read file at `path` and expand its directives.
+    pub fn process_file(&self, path: impl AsRef<Path>) -> Result<ProcessedShader, PreprocessError> {
+        let path = path.as_ref();
+        let file = path.to_string_lossy();
+        let source = std::fs::read_to_string(path).map_err(|err| PreprocessError {
+            location: None,
+            kind: PreprocessErrorKind::Io(file.to_string(), err.to_string()),
+        })?;
+        self.process(&file, &source)
+    }
+
+    fn expand(
+        &self,
+        state: &mut State,
+        file: Arc<str>,
+        source: &str,
+    ) -> Result<(), PreprocessError> {
+        state.stack.push(file.clone());
+        // Whether enclosing `#ifdef`s are active, and whether an `#else` was seen.
+        let mut conditions: Vec<(bool, bool)> = Vec::new();
+
+        for (i, line) in source.lines().enumerate() {
+            let location = SourceLocation {
+                file: file.clone(),
+                line: i + 1,
+            };
+            let error = |kind| PreprocessError {
+                location: Some(location.clone()),
+                kind,
+            };
+            let active = conditions.iter().all(|(active, _)| *active);
+
+            let trimmed = line.trim_start();
+            let directive = match trimmed.strip_prefix('#') {
+                Some(directive) => directive,
+                None => {
+                    if active {
+                        state.push_line(&substitute(line, &state.defines), location);
+                    }
+                    continue;
+                }
+            };
+            let mut words = directive.split_whitespace();
+            let name = words.next().unwrap_or_default();
+            let mut argument = |directive: &'static str| {
+                words
+                    .next()
+                    .ok_or_else(|| error(PreprocessErrorKind::MissingArgument(directive)))
+            };
+
+            match name {
+                "ifdef" => {
+                    let defined = state.defines.contains_key(argument("ifdef")?);
+                    conditions.push((defined, false));
+                }
+                "ifndef" => {
+                    let defined = state.defines.contains_key(argument("ifndef")?);
+                    conditions.push((!defined, false));
+                }
+                "else" => match conditions.last_mut() {
+                    Some((active, seen_else @ false)) => {
+                        *active = !*active;
+                        *seen_else = true;
+                    }
+                    _ => return Err(error(PreprocessErrorKind::UnexpectedDirective("else"))),
+                },
+                "endif" => {
+                    if conditions.pop().is_none() {
+                        return Err(error(PreprocessErrorKind::UnexpectedDirective("endif")));
+                    }
+                }
+                _ if !active => {}
+                "define" => {
+                    let define = argument("define")?.to_string();
+                    // Expand defines in the value now, so later redefinitions don't affect it.
+                    let value = substitute(&words.collect::<Vec<_>>().join(" "), &state.defines);
+                    state.defines.insert(define, value);
+                }
+                "undef" => {
+                    let define = argument("undef")?;
+                    state.defines.remove(define);
+                }
+                "import" => {
+                    let module = argument("import")?.to_string();
+                    if state.imported.insert(module.clone()) {
+                        self.paste(state, &file, &module, false).map_err(error)?;
+                    }
+                }
+                "include" => {
+                    let module = argument("include")?.trim_matches('"').to_string();
+                    self.paste(state, &file, &module, true).map_err(error)?;
+                }
+                _ => {
+                    return Err(error(PreprocessErrorKind::UnknownDirective(
+                        name.to_string(),
+                    )))
+                }
+            }
+        }
+
+        if !conditions.is_empty() {
+            return Err(PreprocessError {
+                location: Some(SourceLocation {
+                    file,
+                    line: source.lines().count(),
+                }),
+                kind: PreprocessErrorKind::MissingEndif,
+            });
+        }
+        state.stack.pop();
+        Ok(())
+    }
+
+    fn scan_conditions(
+        &self,
+        file: &str,
+        source: &str,
+        visited: &mut HashSet<String>,
+        conditions: &mut Vec<String>,
+    ) {
+        if !visited.insert(file.to_string()) {
+            return;
+        }
+        for line in source.lines() {
+            let mut words = match line.trim_start().strip_prefix('#') {
+                Some(directive) => directive.split_whitespace(),
+                None => continue,
+            };
+            match (words.next(), words.next()) {
+                (Some("ifdef" | "ifndef"), Some(name))
+                    if !conditions.iter().any(|condition| condition == name) =>
+                {
+                    conditions.push(name.to_string());
+                }
+                (Some(directive @ ("import" | "include")), Some(name)) => {
+                    let name = name.trim_matches('"');
+                    if let Some(source) = self.modules.get(name) {
+                        self.scan_conditions(name, source, visited, conditions);
+                    } else if directive == "include" {
+                        let path = Path::new(file)
+                            .parent()
+                            .map_or_else(|| PathBuf::from(name), |dir| dir.join(name));
+                        if let Ok(source) = std::fs::read_to_string(&path) {
+                            self.scan_conditions(
+                                &path.to_string_lossy(),
+                                &source,
+                                visited,
+                                conditions,
+                            );
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // Expand module `name` into output, reading it from disk if allowed and not registered.
+    fn paste(
+        &self,
+        state: &mut State,
+        including: &str,
+        name: &str,
+        from_disk: bool,
+    ) -> Result<(), PreprocessErrorKind> {
+        let (file, source): (Arc<str>, _) = match self.modules.get(name) {
+            Some(source) => (Arc::from(name), source.clone()),
+            None if from_disk => {
+                let path = Path::new(including)
+                    .parent()
+                    .map_or_else(|| PathBuf::from(name), |dir| dir.join(name));
+                let file = path.to_string_lossy().to_string();
+                let source = std::fs::read_to_string(&path)
+                    .map_err(|err| PreprocessErrorKind::Io(file.clone(), err.to_string()))?;
+                (Arc::from(file), source)
+            }
+            None => return Err(PreprocessErrorKind::UnknownModule(name.to_string())),
+        };
+
+        if state.stack.contains(&file) {
+            return Err(PreprocessErrorKind::RecursiveInclude(file.to_string()));
+        }
+        // Errors inside the module carry their own location.
+        self.expand(state, file, &source)
+            .map_err(|err| PreprocessErrorKind::Nested(Box::new(err)))
+    }
+}
+
+struct State {
+    defines: HashMap<String, String>,
+    imported: HashSet<String>,
+    stack: Vec<Arc<str>>,
+    output: ProcessedShader,
+}
+
+impl State {
+    fn push_line(&mut self, line: &str, location: SourceLocation) {
+        self.output.source.push_str(line);
+        self.output.source.push('\n');
+        self.output.origins.push(location);
+    }
+}
+
+// Replace whole word defines that have a value.
+fn substitute(line: &str, defines: &HashMap<String, String>) -> String {
+    if defines.values().all(String::is_empty) {
+        return line.to_string();
+    }
+
+    let mut result = String::with_capacity(line.len());
+    let mut word = String::new();
+    let flush = |word: &mut String, result: &mut String| {
+        match defines.get(word.as_str()) {
+            Some(value) if !value.is_empty() => result.push_str(value),
+            _ => result.push_str(word),
+        }
+        word.clear();
+    };
+    for c in line.chars() {
+        if c.is_alphanumeric() || c == '_' {
+            word.push(c);
+        } else {
+            flush(&mut word, &mut result);
+            result.push(c);
+        }
+    }
+    flush(&mut word, &mut result);
+    result
+}
+
+impl ProcessedShader {
+    /// Origin of 1-based `line` of the expanded source.
+    pub fn origin(&self, line: usize) -> Option<&SourceLocation> {
+        line.checked_sub(1).and_then(|i| self.origins.get(i))
+    }
+
+    /// Files and modules the source was expanded from, in order of first appearance.
+    pub fn files(&self) -> Vec<&str> {
+        let mut files: Vec<&str> = Vec::new();
+        for origin in &self.origins {
+            if !files.contains(&&*origin.file) {
+                files.push(&origin.file);
+            }
+        }
+        files
+    }
+
+    /// Parse expanded source, reporting errors at their original location.
+    pub fn parse(&self) -> Result<naga::Module, WgslError> {
+        naga::front::wgsl::parse_str(&self.source).map_err(|err| {
+            let location = err.location(&self.source);
+            let labels = err
+                .labels()
+                .map(|(_, label)| label.to_string())
+                .filter(|label| !label.is_empty())
+                .collect();
+            WgslError {
+                location: location
+                    .and_then(|location| self.origin(location.line_number as usize))
+                    .cloned(),
+                column: location.map(|location| location.line_position),
+                message: err.message().to_string(),
+                labels,
+            }
+        })
+    }
+
+    /// Parse and validate expanded source with naga, reporting errors at their original location.
+    pub fn validate(&self) -> Result<(naga::Module, naga::valid::ModuleInfo), WgslError> {
+        let module = self.parse()?;
+        let info = naga::valid::Validator::new(
+            naga::valid::ValidationFlags::all(),
+            naga::valid::Capabilities::all(),
+        )
+        .validate(&module)
+        .map_err(|err| {
+            let location = err.location(&self.source);
+            // Validation errors wrap their cause, e.g. "Function is invalid" around the reason.
+            let mut message = err.to_string();
+            let mut cause = std::error::Error::source(&err);
+            while let Some(err) = cause {
+                message = format!("{}: {}", message, err);
+                cause = err.source();
+            }
+            WgslError {
+                location: location
+                    .and_then(|location| self.origin(location.line_number as usize))
+                    .cloned(),
+                column: location.map(|location| location.line_position),
+                message,
+                labels: err
+                    .spans()
+                    .map(|(_, label)| label.clone())
+                    .filter(|label| !label.is_empty())
+                    .collect(),
+            }
+        })?;
+        Ok((module, info))
+    }
+}
+
+/// WGSL error found in a [`ProcessedShader`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WgslError {
+    /// Original line of the error, if known.
+    pub location: Option<SourceLocation>,
+    /// 1-based column within the expanded line.
+    pub column: Option<u32>,
+    pub message: String,
+    /// Additional notes attached to the error.
+    pub labels: Vec<String>,
+}
+
+impl fmt::Display for WgslError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.location, self.column) {
+            (Some(location), Some(column)) => write!(f, "{}:{}: ", location, column)?,
+            (Some(location), None) => write!(f, "{}: ", location)?,
+            _ => {}
+        }
+        write!(f, "{}", self.message)?;
+        for label in &self.labels {
+            write!(f, "\n  {}", label)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for WgslError {}
+
+/// Error of [`Preprocessor::process`], with location of the offending directive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PreprocessError {
+    /// Location of the directive, `None` if the top level file could not be read.
+    pub location: Option<SourceLocation>,
+    pub kind: PreprocessErrorKind,
+}
+
+/// Reasons for preprocessing to fail.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PreprocessErrorKind {
+    /// File could not be read, contains path and IO error.
+    Io(String, String),
+    /// No module registered under given name.
+    UnknownModule(String),
+    /// Module includes itself, directly or not.
+    RecursiveInclude(String),
+    /// Directive other than the supported ones.
+    UnknownDirective(String),
+    /// Directive requires an argument.
+    MissingArgument(&'static str),
+    /// `#else` or `#endif` without matching `#ifdef`.
+    UnexpectedDirective(&'static str),
+    /// `#ifdef` without matching `#endif`.
+    MissingEndif,
+    /// Error inside an imported or included module.
+    Nested(Box<PreprocessError>),
+}
+
+impl PreprocessError {
+    /// Locations of the directives that led to the error, innermost first.
+    pub fn includes(&self) -> Vec<&SourceLocation> {
+        let mut includes = Vec::new();
+        let mut err = self;
+        while let PreprocessErrorKind::Nested(inner) = &err.kind {
+            includes.extend(&err.location);
+            err = inner;
+        }
+        includes.reverse();
+        includes
+    }
+
+    /// Innermost error, located in the file that caused it.
+    pub fn root(&self) -> &PreprocessError {
+        match &self.kind {
+            PreprocessErrorKind::Nested(err) => err.root(),
+            _ => self,
+        }
+    }
+}
+
+impl fmt::Display for PreprocessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let root = self.root();
+        if let Some(location) = &root.location {
+            write!(f, "{}: ", location)?;
+        }
+        write!(f, "{}", root.kind)?;
+        for location in self.includes() {
+            write!(f, "\n  included from {}", location)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for PreprocessErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(file, err) => write!(f, "failed to read {}: {}", file, err),
+            Self::UnknownModule(name) => write!(f, "unknown module `{}`", name),
+            Self::RecursiveInclude(name) => write!(f, "module `{}` includes itself", name),
+            Self::UnknownDirective(name) => write!(f, "unknown directive `#{}`", name),
+            Self::MissingArgument(name) => write!(f, "`#{}` requires an argument", name),
+            Self::UnexpectedDirective(name) => {
+                write!(f, "`#{}` without matching `#ifdef`", name)
+            }
+            Self::MissingEndif => write!(f, "`#ifdef` without matching `#endif`"),
+            Self::Nested(err) => write!(f, "{}", err.root().kind),
+        }
+    }
+}
+
+impl std::error::Error for PreprocessError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn location(file: &str, line: usize) -> SourceLocation {
+        SourceLocation {
+            file: Arc::from(file),
+            line,
+        }
+    }
+
+    fn error_kind(preprocessor: &Preprocessor, source: &str) -> PreprocessErrorKind {
+        preprocessor.process("main.wgsl", source).unwrap_err().kind
+    }
+
+    #[test]
+    fn imports_once() {
+        let preprocessor = Preprocessor::new()
+            .module("common", "let common = 1;")
+            .module("lights", "#import common\nlet lights = 2;");
+        let shader = preprocessor
+            .process(
+                "main.wgsl",
+                "#import common\n#import lights\n#import common\n",
+            )
+            .unwrap();
+        assert_eq!(shader.source, "let common = 1;\nlet lights = 2;\n");
+    }
+
+    #[test]
+    fn includes_every_time() {
+        let preprocessor = Preprocessor::new().module("snippet", "x += 1;");
+        let shader = preprocessor
+            .process("main.wgsl", "#include \"snippet\"\n#include \"snippet\"\n")
+            .unwrap();
+        assert_eq!(shader.source, "x += 1;\nx += 1;\n");
+    }
+
+    #[test]
+    fn substitutes_whole_words() {
+        let preprocessor = Preprocessor::new().define("COUNT", "4");
+        let shader = preprocessor
+            .process(
+                "main.wgsl",
+                "let a = COUNT;\nlet b = COUNTER + MY_COUNT + COUNT_2;\n#define SIZE COUNT\nlet c = SIZE*2;\n",
+            )
+            .unwrap();
+        assert_eq!(
+            shader.source,
+            "let a = 4;\nlet b = COUNTER + MY_COUNT + COUNT_2;\nlet c = 4*2;\n"
+        );
+    }
+
+    #[test]
+    fn define_value_is_expanded_when_defined() {
+        let shader = Preprocessor::new()
+            .process(
+                "main.wgsl",
+                "#define A 1\n#define B A\n#undef A\n#define A 2\nlet b = B;\n",
+            )
+            .unwrap();
+        assert_eq!(shader.source, "let b = 1;\n");
+    }
+
+    #[test]
+    fn nested_conditions() {
+        let source = "\
+#ifdef OUTER
+#ifdef INNER
+let v = 1;
+#else
+let v = 2;
+#endif
+#else
+#ifndef INNER
+let v = 3;
+#else
+let v = 4;
+#endif
+#endif
+";
+        let cases = [
+            (&["OUTER", "INNER"][..], "let v = 1;\n"),
+            (&["OUTER"][..], "let v = 2;\n"),
+            (&[][..], "let v = 3;\n"),
+            (&["INNER"][..], "let v = 4;\n"),
+        ];
+        for (defines, expected) in cases {
+            let preprocessor = defines
+                .iter()
+                .fold(Preprocessor::new(), |preprocessor, define| {
+                    preprocessor.define(define, "")
+                });
+            let shader = preprocessor.process("main.wgsl", source).unwrap();
+            assert_eq!(shader.source, expected, "with {:?}", defines);
+        }
+    }
+
+    #[test]
+    fn inactive_directives_are_skipped() {
+        let shader = Preprocessor::new()
+            .process(
+                "main.wgsl",
+                "#ifdef MISSING\n#import missing\n#define X 1\n#endif\nlet x = X;\n",
+            )
+            .unwrap();
+        assert_eq!(shader.source, "let x = X;\n");
+    }
+
+    #[test]
+    fn maps_lines_to_origins() {
+        let preprocessor = Preprocessor::new().module("common", "let a = 1;\n\nlet b = 2;");
+        let shader = preprocessor
+            .process(
+                "main.wgsl",
+                "#ifdef NOPE\nskipped\n#endif\n#import common\nlet c = 3;\n",
+            )
+            .unwrap();
+        assert_eq!(shader.origin(1), Some(&location("common", 1)));
+        assert_eq!(shader.origin(3), Some(&location("common", 3)));
+        assert_eq!(shader.origin(4), Some(&location("main.wgsl", 5)));
+        assert_eq!(shader.origin(0), None);
+        assert_eq!(shader.origin(5), None);
+        assert_eq!(shader.files(), ["common", "main.wgsl"]);
+    }
+
+    #[test]
+    fn wgsl_errors_point_to_origin() {
+        let preprocessor = Preprocessor::new().module("broken", "let ok = 1;\nlet = 2;");
+        let shader = preprocessor
+            .process("main.wgsl", "let main = 0;\n#import broken\n")
+            .unwrap();
+        let err = shader.parse().unwrap_err();
+        assert_eq!(err.location, Some(location("broken", 2)));
+    }
+
+    #[test]
+    fn nests_errors_of_modules() {
+        let preprocessor = Preprocessor::new()
+            .module("outer", "\n#import inner")
+            .module("inner", "let a = 1;\n#import missing");
+        let err = preprocessor
+            .process("main.wgsl", "#import outer\n")
+            .unwrap_err();
+
+        let root = err.root();
+        assert_eq!(root.location, Some(location("inner", 2)));
+        assert_eq!(
+            root.kind,
+            PreprocessErrorKind::UnknownModule("missing".to_string())
+        );
+        assert_eq!(
+            err.includes(),
+            [&location("outer", 2), &location("main.wgsl", 1)]
+        );
+        assert_eq!(
+            err.to_string(),
+            "inner:2: unknown module `missing`\n  included from outer:2\n  included from main.wgsl:1"
+        );
+    }
+
+    #[test]
+    fn rejects_recursive_includes() {
+        let preprocessor = Preprocessor::new().module("loop", "#include \"loop\"");
+        let err = preprocessor
+            .process("main.wgsl", "#include \"loop\"\n")
+            .unwrap_err();
+        assert_eq!(
+            err.root().kind,
+            PreprocessErrorKind::RecursiveInclude("loop".to_string())
+        );
+    }
+
+    #[test]
+    fn rejects_unmatched_conditions() {
+        let preprocessor = Preprocessor::new();
+        assert_eq!(
+            error_kind(&preprocessor, "let a = 1;\n#endif\n"),
+            PreprocessErrorKind::UnexpectedDirective("endif")
+        );
+        assert_eq!(
+            error_kind(&preprocessor, "#else\n"),
+            PreprocessErrorKind::UnexpectedDirective("else")
+        );
+        assert_eq!(
+            error_kind(&preprocessor, "#ifdef A\n#else\n#else\n#endif\n"),
+            PreprocessErrorKind::UnexpectedDirective("else")
+        );
+
+        let err = preprocessor
+            .process("main.wgsl", "#ifdef A\nlet a = 1;\n")
+            .unwrap_err();
+        assert_eq!(err.kind, PreprocessErrorKind::MissingEndif);
+        assert_eq!(err.location, Some(location("main.wgsl", 2)));
+    }
+
+    #[test]
+    fn rejects_malformed_directives() {
+        let preprocessor = Preprocessor::new();
+        assert_eq!(
+            error_kind(&preprocessor, "#ifdef\n"),
+            PreprocessErrorKind::MissingArgument("ifdef")
+        );
+        assert_eq!(
+            error_kind(&preprocessor, "#pragma once\n"),
+            PreprocessErrorKind::UnknownDirective("pragma".to_string())
+        );
+    }
+}
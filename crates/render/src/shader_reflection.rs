@@ -0,0 +1,1094 @@
+//!
+//! Reflection of shader interfaces
+//!
+//! [`ShaderReflection`] describes what a shader expects from the pipeline: resource bindings with
+//! their types and the stages using them, push constants, and inputs and outputs of every entry
+//! point. It's gathered by naga from the WGSL source, so layouts can be derived from shaders
+//! instead of being written by hand next to them, see
+//! [`RenderPipelineBuilder::from_reflection`](crate::render_pipleine_builder::RenderPipelineBuilder::from_reflection)
+//! and [`LayoutBuilder::from_reflection`](crate::bind_group_builder::LayoutBuilder::from_reflection).
+//!
+//! Resource types that can't be told from WGSL are reflected with the most common choice:
+//! float textures are filterable and samplers are [`wgpu::SamplerBindingType::Filtering`].
+//!
+//! # Examples
+//!
+//! ```
+//! use render::shader_reflection::ShaderReflection;
+//! use render::wgpu;
+//!
+//! let reflection = ShaderReflection::from_wgsl(
+//!     "@group(0) @binding(0) var<uniform> color: vec4<f32>;
+//!
+//!     @vertex
+//!     fn vertex(@location(0) position: vec3<f32>) -> @builtin(position) vec4<f32> {
+//!         return vec4<f32>(position, 1.0);
+//!     }
+//!
+//!     @fragment
+//!     fn fragment() -> @location(0) vec4<f32> {
+//!         return color;
+//!     }",
+//! )
+//! .unwrap();
+//!
+//! let entries = reflection.bind_group_layout_entries(0);
+//! assert_eq!(entries[0].visibility, wgpu::ShaderStages::FRAGMENT);
+//!
+//! let vertex = reflection
+//!     .entry_point(wgpu::ShaderStages::VERTEX, "vertex")
+//!     .unwrap();
+//! assert_eq!(vertex.inputs[0].format(), wgpu::VertexFormat::Float32x3);
+//! ```
+use std::fmt;
+use std::num::NonZeroU32;
+
+/// Resource binding declared by a shader.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BindingReflection {
+    pub group: u32,
+    pub binding: u32,
+    /// Name of the global variable.
+    pub name: Option<String>,
+    pub ty: wgpu::BindingType,
+    /// Number of elements of a binding array.
+    pub count: Option<NonZeroU32>,
+    /// Stages of the entry points using the binding.
+    pub visibility: wgpu::ShaderStages,
+    /// Size of the buffer type in bytes, the minimum for runtime sized arrays.
+    pub size: Option<u64>,
+}
+
+impl BindingReflection {
+    /// Layout entry for this binding.
+    pub fn layout_entry(&self) -> wgpu::BindGroupLayoutEntry {
+        wgpu::BindGroupLayoutEntry {
+            binding: self.binding,
+            visibility: self.visibility,
+            ty: self.ty,
+            count: self.count,
+        }
+    }
+}
+
+/// Input or output of an entry point with a location.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StageIo {
+    pub location: u32,
+    /// Name of the argument or struct member.
+    pub name: Option<String>,
+    pub kind: naga::ScalarKind,
+    /// Number of vector components, 1 for scalars.
+    pub components: u32,
+}
+
+impl StageIo {
+    /// Vertex format matching the type exactly.
+    pub fn format(&self) -> wgpu::VertexFormat {
+        use naga::ScalarKind as K;
+        use wgpu::VertexFormat as F;
+
+        match (self.kind, self.components) {
+            (K::Sint, 1) => F::Sint32,
+            (K::Sint, 2) => F::Sint32x2,
+            (K::Sint, 3) => F::Sint32x3,
+            (K::Sint, _) => F::Sint32x4,
+            (K::Uint | K::Bool, 1) => F::Uint32,
+            (K::Uint | K::Bool, 2) => F::Uint32x2,
+            (K::Uint | K::Bool, 3) => F::Uint32x3,
+            (K::Uint | K::Bool, _) => F::Uint32x4,
+            (K::Float, 1) => F::Float32,
+            (K::Float, 2) => F::Float32x2,
+            (K::Float, 3) => F::Float32x3,
+            (K::Float, _) => F::Float32x4,
+        }
+    }
+}
+
+/// Interface of a single entry point.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntryPointReflection {
+    pub name: String,
+    pub stage: wgpu::ShaderStages,
+    /// Inputs with a location, vertex attributes for vertex shaders.
+    pub inputs: Vec<StageIo>,
+    /// Outputs with a location, color targets for fragment shaders.
+    pub outputs: Vec<StageIo>,
+    /// Workgroup size of compute shaders, zeros otherwise.
+    pub workgroup_size: [u32; 3],
+}
+
+impl EntryPointReflection {
+    /// Check that every input is fed by an attribute of `vertex_buffers` with matching scalar kind.
+    pub fn check_vertex_buffers(
+        &self,
+        vertex_buffers: &[wgpu::VertexBufferLayout],
+    ) -> Result<(), ReflectionError> {
+        let attributes: Vec<_> = vertex_buffers
+            .iter()
+            .flat_map(|buffer| buffer.attributes)
+            .collect();
+        for input in &self.inputs {
+            let attribute = attributes
+                .iter()
+                .find(|attribute| attribute.shader_location == input.location)
+                .ok_or(ReflectionError::MissingVertexInput(input.location))?;
+            if input.kind != vertex_format_kind(attribute.format) {
+                return Err(ReflectionError::VertexInputMismatch {
+                    location: input.location,
+                    format: attribute.format,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Push constant block declared by a shader.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PushConstantReflection {
+    /// Size of the block in bytes.
+    pub size: u32,
+    /// Stages of the entry points using the block.
+    pub visibility: wgpu::ShaderStages,
+}
+
+impl PushConstantReflection {
+    /// Range covering the whole block.
+    pub fn range(&self) -> wgpu::PushConstantRange {
+        wgpu::PushConstantRange {
+            stages: self.visibility,
+            range: 0..self.size,
+        }
+    }
+}
+
+/// Interface of a shader module, see [module level documentation](self).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ShaderReflection {
+    /// Bindings sorted by group and binding.
+    pub bindings: Vec<BindingReflection>,
+    pub push_constants: Option<PushConstantReflection>,
+    pub entry_points: Vec<EntryPointReflection>,
+}
+
+impl ShaderReflection {
+    /// Parse and reflect WGSL `source`.
+    pub fn from_wgsl(source: &str) -> Result<Self, ReflectionError> {
+        let module = naga::front::wgsl::parse_str(source)
+            .map_err(|err| ReflectionError::Parse(err.emit_to_string(source)))?;
+        Self::from_module(&module)
+    }
+
+    /// Reflect a parsed naga `module`.
+    ///
+    /// The module is validated, which tells the stages using each binding.
+    pub fn from_module(module: &naga::Module) -> Result<Self, ReflectionError> {
+        let info = naga::valid::Validator::new(
+            naga::valid::ValidationFlags::all(),
+            naga::valid::Capabilities::all(),
+        )
+        .validate(module)
+        .map_err(|err| ReflectionError::Validation(err.to_string()))?;
+
+        let mut reflection = Self::default();
+        for (handle, global) in module.global_variables.iter() {
+            let visibility = module
+                .entry_points
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| !info.get_entry_point(*i)[handle].is_empty())
+                .fold(wgpu::ShaderStages::NONE, |stages, (_, ep)| {
+                    stages | stage(ep.stage)
+                });
+
+            if global.space == naga::AddressSpace::PushConstant {
+                reflection.push_constants = Some(PushConstantReflection {
+                    size: module.types[global.ty].inner.size(&module.constants),
+                    visibility,
+                });
+                continue;
+            }
+            let resource = match &global.binding {
+                Some(resource) => resource,
+                None => continue,
+            };
+
+            let (ty, count) = match &module.types[global.ty].inner {
+                naga::TypeInner::BindingArray { base, size } => {
+                    let count = match size {
+                        naga::ArraySize::Constant(constant) => {
+                            array_length(&module.constants[*constant])
+                        }
+                        naga::ArraySize::Dynamic => None,
+                    };
+                    (*base, count.and_then(NonZeroU32::new))
+                }
+                _ => (global.ty, None),
+            };
+            let inner = &module.types[ty].inner;
+            let ty =
+                binding_type(global.space, inner).ok_or(ReflectionError::UnsupportedBinding {
+                    group: resource.group,
+                    binding: resource.binding,
+                })?;
+            let size = matches!(ty, wgpu::BindingType::Buffer { .. })
+                .then(|| inner.size(&module.constants) as u64);
+
+            reflection.bindings.push(BindingReflection {
+                group: resource.group,
+                binding: resource.binding,
+                name: global.name.clone(),
+                ty,
+                count,
+                visibility,
+                size,
+            });
+        }
+        reflection
+            .bindings
+            .sort_by_key(|binding| (binding.group, binding.binding));
+
+        for ep in &module.entry_points {
+            let mut inputs = Vec::new();
+            for argument in &ep.function.arguments {
+                stage_io(
+                    module,
+                    argument.binding.as_ref(),
+                    argument.name.as_deref(),
+                    argument.ty,
+                    &mut inputs,
+                );
+            }
+            let mut outputs = Vec::new();
+            if let Some(result) = &ep.function.result {
+                stage_io(
+                    module,
+                    result.binding.as_ref(),
+                    None,
+                    result.ty,
+                    &mut outputs,
+                );
+            }
+            reflection.entry_points.push(EntryPointReflection {
+                name: ep.name.clone(),
+                stage: stage(ep.stage),
+                inputs,
+                outputs,
+                workgroup_size: ep.workgroup_size,
+            });
+        }
+        Ok(reflection)
+    }
+
+    /// Entry point with given `stage` and `name`.
+    pub fn entry_point(
+        &self,
+        stage: wgpu::ShaderStages,
+        name: &str,
+    ) -> Option<&EntryPointReflection> {
+        self.entry_points
+            .iter()
+            .find(|ep| ep.stage == stage && ep.name == name)
+    }
+
+    /// Like [`ShaderReflection::entry_point`], but returns an error if there's none.
+    pub fn try_entry_point(
+        &self,
+        stage: wgpu::ShaderStages,
+        name: &str,
+    ) -> Result<&EntryPointReflection, ReflectionError> {
+        self.entry_point(stage, name)
+            .ok_or_else(|| ReflectionError::MissingEntryPoint {
+                stage,
+                name: name.to_string(),
+            })
+    }
+
+    /// Add bindings and entry points of `other`, e.g. a separate fragment shader module.
+    ///
+    /// Bindings declared by both must have the same type, their visibility is combined.
+    pub fn merge(&mut self, other: &ShaderReflection) -> Result<(), ReflectionError> {
+        for binding in &other.bindings {
+            let existing = self
+                .bindings
+                .iter_mut()
+                .find(|b| b.group == binding.group && b.binding == binding.binding);
+            match existing {
+                Some(existing) if existing.ty != binding.ty || existing.count != binding.count => {
+                    return Err(ReflectionError::ConflictingBinding {
+                        group: binding.group,
+                        binding: binding.binding,
+                    })
+                }
+                Some(existing) => existing.visibility |= binding.visibility,
+                None => self.bindings.push(binding.clone()),
+            }
+        }
+        self.bindings
+            .sort_by_key(|binding| (binding.group, binding.binding));
+
+        self.push_constants = match (self.push_constants, other.push_constants) {
+            (Some(a), Some(b)) => Some(PushConstantReflection {
+                size: a.size.max(b.size),
+                visibility: a.visibility | b.visibility,
+            }),
+            (a, b) => a.or(b),
+        };
+        self.entry_points.extend(other.entry_points.iter().cloned());
+        Ok(())
+    }
+
+    /// Number of bind groups the pipeline layout needs, including empty groups in between.
+    pub fn group_count(&self) -> u32 {
+        self.bindings
+            .iter()
+            .map(|binding| binding.group + 1)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Layout entries of bind group `group`, empty if the shader declares none.
+    pub fn bind_group_layout_entries(&self, group: u32) -> Vec<wgpu::BindGroupLayoutEntry> {
+        self.bindings
+            .iter()
+            .filter(|binding| binding.group == group)
+            .map(BindingReflection::layout_entry)
+            .collect()
+    }
+
+    /// Check that layout `entries` of bind group `group` provide every binding of the shader.
+    ///
+    /// Entries may declare more bindings or stages than the shader uses. Properties the shader
+    /// does not determine, like filtering and dynamic offsets, are not compared. Read-write storage
+    /// buffers may be bound to shaders that only read them.
+    pub fn check_bind_group(
+        &self,
+        group: u32,
+        entries: &[wgpu::BindGroupLayoutEntry],
+    ) -> Result<(), ReflectionError> {
+        for binding in self
+            .bindings
+            .iter()
+            .filter(|binding| binding.group == group)
+        {
+            let entry = entries
+                .iter()
+                .find(|entry| entry.binding == binding.binding)
+                .ok_or(ReflectionError::MissingBinding {
+                    group,
+                    binding: binding.binding,
+                })?;
+            if !compatible(&binding.ty, &entry.ty) || binding.count != entry.count {
+                return Err(ReflectionError::BindingMismatch {
+                    group,
+                    binding: binding.binding,
+                    expected: binding.ty,
+                    found: entry.ty,
+                });
+            }
+            if !entry.visibility.contains(binding.visibility) {
+                return Err(ReflectionError::Visibility {
+                    group,
+                    binding: binding.binding,
+                    expected: binding.visibility,
+                    found: entry.visibility,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Reasons for reflection, or checks against it, to fail.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReflectionError {
+    /// Shader source failed to parse, contains formatted parser error.
+    Parse(String),
+    /// Shader failed naga validation, contains the formatted error.
+    Validation(String),
+    /// Shader module was not created from a [`Shader`](crate::shader::Shader), so it has no source.
+    MissingSource,
+    /// No entry point with given name and stage.
+    MissingEntryPoint {
+        stage: wgpu::ShaderStages,
+        name: String,
+    },
+    /// Vertex shader reads a location no vertex buffer provides.
+    MissingVertexInput(u32),
+    /// Vertex attribute format does not fit the type of vertex shader input.
+    VertexInputMismatch {
+        location: u32,
+        format: wgpu::VertexFormat,
+    },
+    /// Global has a type that can't be bound.
+    UnsupportedBinding { group: u32, binding: u32 },
+    /// Modules declare the same binding with different types.
+    ConflictingBinding { group: u32, binding: u32 },
+    /// Layout has no entry for a binding of the shader.
+    MissingBinding { group: u32, binding: u32 },
+    /// Layout entry type doesn't fit the binding of the shader.
+    BindingMismatch {
+        group: u32,
+        binding: u32,
+        expected: wgpu::BindingType,
+        found: wgpu::BindingType,
+    },
+    /// Layout entry is not visible to all stages using the binding.
+    Visibility {
+        group: u32,
+        binding: u32,
+        expected: wgpu::ShaderStages,
+        found: wgpu::ShaderStages,
+    },
+}
+
+impl fmt::Display for ReflectionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Parse(err) => write!(f, "failed to parse shader: {}", err),
+            Self::Validation(err) => write!(f, "shader is invalid: {}", err),
+            Self::MissingSource => write!(f, "shader module has no source to reflect"),
+            Self::MissingEntryPoint { stage, name } => {
+                write!(f, "no {:?} entry point named `{}`", stage, name)
+            }
+            Self::MissingVertexInput(location) => write!(
+                f,
+                "vertex shader input at location {} is not provided by any vertex buffer",
+                location
+            ),
+            Self::VertexInputMismatch { location, format } => write!(
+                f,
+                "vertex attribute {:?} at location {} does not fit shader input type",
+                format, location
+            ),
+            Self::UnsupportedBinding { group, binding } => write!(
+                f,
+                "binding {} of group {} has a type that can't be bound",
+                binding, group
+            ),
+            Self::ConflictingBinding { group, binding } => write!(
+                f,
+                "binding {} of group {} is declared with different types",
+                binding, group
+            ),
+            Self::MissingBinding { group, binding } => write!(
+                f,
+                "layout of group {} has no entry for shader binding {}",
+                group, binding
+            ),
+            Self::BindingMismatch {
+                group,
+                binding,
+                expected,
+                found,
+            } => write!(
+                f,
+                "binding {} of group {} is {:?} in the shader, but {:?} in the layout",
+                binding, group, expected, found
+            ),
+            Self::Visibility {
+                group,
+                binding,
+                expected,
+                found,
+            } => write!(
+                f,
+                "binding {} of group {} is used by {:?}, but only visible to {:?}",
+                binding, group, expected, found
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ReflectionError {}
+
+pub(crate) fn vertex_format_kind(format: wgpu::VertexFormat) -> naga::ScalarKind {
+    use wgpu::VertexFormat as F;
+
+    match format {
+        F::Uint8x2
+        | F::Uint8x4
+        | F::Uint16x2
+        | F::Uint16x4
+        | F::Uint32
+        | F::Uint32x2
+        | F::Uint32x3
+        | F::Uint32x4 => naga::ScalarKind::Uint,
+        F::Sint8x2
+        | F::Sint8x4
+        | F::Sint16x2
+        | F::Sint16x4
+        | F::Sint32
+        | F::Sint32x2
+        | F::Sint32x3
+        | F::Sint32x4 => naga::ScalarKind::Sint,
+        _ => naga::ScalarKind::Float,
+    }
+}
+
+fn stage(stage: naga::ShaderStage) -> wgpu::ShaderStages {
+    match stage {
+        naga::ShaderStage::Vertex => wgpu::ShaderStages::VERTEX,
+        naga::ShaderStage::Fragment => wgpu::ShaderStages::FRAGMENT,
+        naga::ShaderStage::Compute => wgpu::ShaderStages::COMPUTE,
+    }
+}
+
+// Collect located inputs or outputs, looking into structs without binding.
+fn stage_io(
+    module: &naga::Module,
+    binding: Option<&naga::Binding>,
+    name: Option<&str>,
+    ty: naga::Handle<naga::Type>,
+    io: &mut Vec<StageIo>,
+) {
+    let inner = &module.types[ty].inner;
+    match (binding, inner) {
+        (Some(naga::Binding::Location { location, .. }), inner) => {
+            let (kind, components) = match *inner {
+                naga::TypeInner::Scalar { kind, .. } => (kind, 1),
+                naga::TypeInner::Vector { size, kind, .. } => (kind, size as u32),
+                // Validation only allows scalars and vectors.
+                _ => return,
+            };
+            io.push(StageIo {
+                location: *location,
+                name: name.map(str::to_string),
+                kind,
+                components,
+            });
+        }
+        (None, naga::TypeInner::Struct { members, .. }) => {
+            for member in members {
+                stage_io(
+                    module,
+                    member.binding.as_ref(),
+                    member.name.as_deref(),
+                    member.ty,
+                    io,
+                );
+            }
+        }
+        _ => {}
+    }
+}
+
+fn array_length(constant: &naga::Constant) -> Option<u32> {
+    match constant.inner {
+        naga::ConstantInner::Scalar {
+            value: naga::ScalarValue::Uint(value),
+            ..
+        } => u32::try_from(value).ok(),
+        naga::ConstantInner::Scalar {
+            value: naga::ScalarValue::Sint(value),
+            ..
+        } => u32::try_from(value).ok(),
+        _ => None,
+    }
+}
+
+fn binding_type(space: naga::AddressSpace, inner: &naga::TypeInner) -> Option<wgpu::BindingType> {
+    let ty = match (space, inner) {
+        (naga::AddressSpace::Uniform, _) => wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        (naga::AddressSpace::Storage { access }, _) => wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage {
+                read_only: !access.contains(naga::StorageAccess::STORE),
+            },
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        (naga::AddressSpace::Handle, naga::TypeInner::Sampler { comparison }) => {
+            wgpu::BindingType::Sampler(if *comparison {
+                wgpu::SamplerBindingType::Comparison
+            } else {
+                wgpu::SamplerBindingType::Filtering
+            })
+        }
+        (
+            naga::AddressSpace::Handle,
+            naga::TypeInner::Image {
+                dim,
+                arrayed,
+                class,
+            },
+        ) => {
+            let view_dimension = view_dimension(*dim, *arrayed);
+            match *class {
+                naga::ImageClass::Sampled { kind, multi } => wgpu::BindingType::Texture {
+                    sample_type: match kind {
+                        naga::ScalarKind::Sint => wgpu::TextureSampleType::Sint,
+                        naga::ScalarKind::Uint => wgpu::TextureSampleType::Uint,
+                        _ => wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    view_dimension,
+                    multisampled: multi,
+                },
+                naga::ImageClass::Depth { multi } => wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Depth,
+                    view_dimension,
+                    multisampled: multi,
+                },
+                naga::ImageClass::Storage { format, access } => wgpu::BindingType::StorageTexture {
+                    access: match (
+                        access.contains(naga::StorageAccess::LOAD),
+                        access.contains(naga::StorageAccess::STORE),
+                    ) {
+                        (true, true) => wgpu::StorageTextureAccess::ReadWrite,
+                        (true, false) => wgpu::StorageTextureAccess::ReadOnly,
+                        (false, _) => wgpu::StorageTextureAccess::WriteOnly,
+                    },
+                    format: storage_format(format),
+                    view_dimension,
+                },
+            }
+        }
+        _ => return None,
+    };
+    Some(ty)
+}
+
+fn view_dimension(dim: naga::ImageDimension, arrayed: bool) -> wgpu::TextureViewDimension {
+    match (dim, arrayed) {
+        (naga::ImageDimension::D1, _) => wgpu::TextureViewDimension::D1,
+        (naga::ImageDimension::D2, false) => wgpu::TextureViewDimension::D2,
+        (naga::ImageDimension::D2, true) => wgpu::TextureViewDimension::D2Array,
+        (naga::ImageDimension::D3, _) => wgpu::TextureViewDimension::D3,
+        (naga::ImageDimension::Cube, false) => wgpu::TextureViewDimension::Cube,
+        (naga::ImageDimension::Cube, true) => wgpu::TextureViewDimension::CubeArray,
+    }
+}
+
+fn storage_format(format: naga::StorageFormat) -> wgpu::TextureFormat {
+    use naga::StorageFormat as S;
+    use wgpu::TextureFormat as T;
+
+    match format {
+        S::R8Unorm => T::R8Unorm,
+        S::R8Snorm => T::R8Snorm,
+        S::R8Uint => T::R8Uint,
+        S::R8Sint => T::R8Sint,
+        S::R16Uint => T::R16Uint,
+        S::R16Sint => T::R16Sint,
+        S::R16Float => T::R16Float,
+        S::Rg8Unorm => T::Rg8Unorm,
+        S::Rg8Snorm => T::Rg8Snorm,
+        S::Rg8Uint => T::Rg8Uint,
+        S::Rg8Sint => T::Rg8Sint,
+        S::R32Uint => T::R32Uint,
+        S::R32Sint => T::R32Sint,
+        S::R32Float => T::R32Float,
+        S::Rg16Uint => T::Rg16Uint,
+        S::Rg16Sint => T::Rg16Sint,
+        S::Rg16Float => T::Rg16Float,
+        S::Rgba8Unorm => T::Rgba8Unorm,
+        S::Rgba8Snorm => T::Rgba8Snorm,
+        S::Rgba8Uint => T::Rgba8Uint,
+        S::Rgba8Sint => T::Rgba8Sint,
+        S::Rgb10a2Unorm => T::Rgb10a2Unorm,
+        S::Rg11b10Float => T::Rg11b10Float,
+        S::Rg32Uint => T::Rg32Uint,
+        S::Rg32Sint => T::Rg32Sint,
+        S::Rg32Float => T::Rg32Float,
+        S::Rgba16Uint => T::Rgba16Uint,
+        S::Rgba16Sint => T::Rgba16Sint,
+        S::Rgba16Float => T::Rgba16Float,
+        S::Rgba32Uint => T::Rgba32Uint,
+        S::Rgba32Sint => T::Rgba32Sint,
+        S::Rgba32Float => T::Rgba32Float,
+    }
+}
+
+// Whether a layout entry of type `found` can be used for a shader binding of type `expected`.
+fn compatible(expected: &wgpu::BindingType, found: &wgpu::BindingType) -> bool {
+    use wgpu::BindingType as B;
+
+    match (expected, found) {
+        (B::Buffer { ty: expected, .. }, B::Buffer { ty: found, .. }) => match (expected, found) {
+            // Read-write storage can be read by shaders that only read it.
+            (
+                wgpu::BufferBindingType::Storage { read_only: true },
+                wgpu::BufferBindingType::Storage { .. },
+            ) => true,
+            (expected, found) => expected == found,
+        },
+        (B::Sampler(expected), B::Sampler(found)) => {
+            (*expected == wgpu::SamplerBindingType::Comparison)
+                == (*found == wgpu::SamplerBindingType::Comparison)
+        }
+        (
+            B::Texture {
+                sample_type: expected_type,
+                view_dimension: expected_dimension,
+                multisampled: expected_multisampled,
+            },
+            B::Texture {
+                sample_type: found_type,
+                view_dimension: found_dimension,
+                multisampled: found_multisampled,
+            },
+        ) => {
+            let same_type = matches!(
+                (expected_type, found_type),
+                (
+                    wgpu::TextureSampleType::Float { .. },
+                    wgpu::TextureSampleType::Float { .. }
+                )
+            ) || expected_type == found_type;
+            same_type
+                && expected_dimension == found_dimension
+                && expected_multisampled == found_multisampled
+        }
+        (expected, found) => expected == found,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHADER: &str = "
+        struct Light {
+            color: vec4<f32>,
+        }
+
+        @group(0) @binding(0) var<uniform> view: mat4x4<f32>;
+        @group(0) @binding(1) var<storage, read> lights: array<Light>;
+        @group(1) @binding(0) var color_texture: texture_2d<f32>;
+        @group(1) @binding(1) var color_sampler: sampler;
+
+        struct Vertex {
+            @location(0) position: vec3<f32>,
+            @location(2) joints: vec4<u32>,
+        }
+
+        @vertex
+        fn vertex(vertex: Vertex) -> @builtin(position) vec4<f32> {
+            return view * vec4<f32>(vertex.position, f32(vertex.joints.x));
+        }
+
+        @fragment
+        fn fragment(@location(0) uv: vec2<f32>) -> @location(0) vec4<f32> {
+            return textureSample(color_texture, color_sampler, uv) * lights[0].color;
+        }
+    ";
+
+    fn storage(read_only: bool) -> wgpu::BindingType {
+        wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage { read_only },
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        }
+    }
+
+    fn entry(binding: u32, ty: wgpu::BindingType) -> wgpu::BindGroupLayoutEntry {
+        wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+            ty,
+            count: None,
+        }
+    }
+
+    #[test]
+    fn reflects_bindings() {
+        let reflection = ShaderReflection::from_wgsl(SHADER).unwrap();
+
+        let bindings: Vec<_> = reflection
+            .bindings
+            .iter()
+            .map(|binding| (binding.group, binding.binding, binding.name.as_deref()))
+            .collect();
+        assert_eq!(
+            bindings,
+            [
+                (0, 0, Some("view")),
+                (0, 1, Some("lights")),
+                (1, 0, Some("color_texture")),
+                (1, 1, Some("color_sampler")),
+            ]
+        );
+        assert_eq!(reflection.group_count(), 2);
+
+        let view = &reflection.bindings[0];
+        assert_eq!(
+            view.ty,
+            wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            }
+        );
+        assert_eq!(view.size, Some(64));
+        assert_eq!(reflection.bindings[1].ty, storage(true));
+        assert_eq!(reflection.bindings[1].size, Some(16));
+        assert_eq!(
+            reflection.bindings[2].ty,
+            wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            }
+        );
+        assert_eq!(reflection.bindings[2].size, None);
+        assert_eq!(
+            reflection.bindings[3].ty,
+            wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering)
+        );
+    }
+
+    #[test]
+    fn reflects_visibility() {
+        let reflection = ShaderReflection::from_wgsl(SHADER).unwrap();
+
+        let visibility: Vec<_> = reflection
+            .bindings
+            .iter()
+            .map(|binding| binding.visibility)
+            .collect();
+        assert_eq!(
+            visibility,
+            [
+                wgpu::ShaderStages::VERTEX,
+                wgpu::ShaderStages::FRAGMENT,
+                wgpu::ShaderStages::FRAGMENT,
+                wgpu::ShaderStages::FRAGMENT,
+            ]
+        );
+    }
+
+    #[test]
+    fn reflects_entry_points() {
+        let reflection = ShaderReflection::from_wgsl(SHADER).unwrap();
+
+        let vertex = reflection
+            .try_entry_point(wgpu::ShaderStages::VERTEX, "vertex")
+            .unwrap();
+        let inputs: Vec<_> = vertex
+            .inputs
+            .iter()
+            .map(|input| (input.location, input.name.as_deref(), input.format()))
+            .collect();
+        assert_eq!(
+            inputs,
+            [
+                (0, Some("position"), wgpu::VertexFormat::Float32x3),
+                (2, Some("joints"), wgpu::VertexFormat::Uint32x4),
+            ]
+        );
+        assert!(vertex.outputs.is_empty());
+
+        let fragment = reflection
+            .entry_point(wgpu::ShaderStages::FRAGMENT, "fragment")
+            .unwrap();
+        assert_eq!(fragment.outputs[0].format(), wgpu::VertexFormat::Float32x4);
+        assert_eq!(
+            reflection.try_entry_point(wgpu::ShaderStages::FRAGMENT, "vertex"),
+            Err(ReflectionError::MissingEntryPoint {
+                stage: wgpu::ShaderStages::FRAGMENT,
+                name: "vertex".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn reflects_workgroup_size() {
+        let reflection = ShaderReflection::from_wgsl(
+            "@group(0) @binding(0) var<storage, read_write> data: array<u32>;
+
+            @compute @workgroup_size(8, 4)
+            fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+                data[id.x] = id.y;
+            }",
+        )
+        .unwrap();
+
+        let main = reflection
+            .entry_point(wgpu::ShaderStages::COMPUTE, "main")
+            .unwrap();
+        assert_eq!(main.workgroup_size, [8, 4, 1]);
+        assert!(main.inputs.is_empty());
+        assert_eq!(reflection.bindings[0].ty, storage(false));
+        assert_eq!(
+            reflection.bindings[0].visibility,
+            wgpu::ShaderStages::COMPUTE
+        );
+    }
+
+    #[test]
+    fn reports_invalid_source() {
+        assert!(matches!(
+            ShaderReflection::from_wgsl("fn main( {"),
+            Err(ReflectionError::Parse(_))
+        ));
+        assert!(matches!(
+            ShaderReflection::from_wgsl("fn main() -> f32 { return 1u; }"),
+            Err(ReflectionError::Validation(_))
+        ));
+    }
+
+    #[test]
+    fn merges_modules() {
+        let mut vertex = ShaderReflection::from_wgsl(
+            "@group(0) @binding(0) var<uniform> view: mat4x4<f32>;
+
+            @vertex
+            fn main(@location(0) position: vec4<f32>) -> @builtin(position) vec4<f32> {
+                return view * position;
+            }",
+        )
+        .unwrap();
+        let fragment = ShaderReflection::from_wgsl(
+            "@group(0) @binding(0) var<uniform> view: mat4x4<f32>;
+            @group(0) @binding(1) var<uniform> color: vec4<f32>;
+
+            @fragment
+            fn main() -> @location(0) vec4<f32> {
+                return view[0] * color;
+            }",
+        )
+        .unwrap();
+
+        vertex.merge(&fragment).unwrap();
+        assert_eq!(vertex.bindings.len(), 2);
+        assert_eq!(
+            vertex.bindings[0].visibility,
+            wgpu::ShaderStages::VERTEX_FRAGMENT
+        );
+        assert_eq!(vertex.bindings[1].visibility, wgpu::ShaderStages::FRAGMENT);
+        assert_eq!(vertex.entry_points.len(), 2);
+    }
+
+    #[test]
+    fn rejects_conflicting_bindings() {
+        let mut uniform = ShaderReflection::from_wgsl(
+            "@group(0) @binding(0) var<uniform> data: vec4<f32>;
+
+            @fragment
+            fn main() -> @location(0) vec4<f32> {
+                return data;
+            }",
+        )
+        .unwrap();
+        let storage = ShaderReflection::from_wgsl(
+            "@group(0) @binding(0) var<storage, read> data: vec4<f32>;
+
+            @fragment
+            fn main() -> @location(0) vec4<f32> {
+                return data;
+            }",
+        )
+        .unwrap();
+
+        assert_eq!(
+            uniform.merge(&storage),
+            Err(ReflectionError::ConflictingBinding {
+                group: 0,
+                binding: 0
+            })
+        );
+    }
+
+    #[test]
+    fn checks_vertex_buffers() {
+        let reflection = ShaderReflection::from_wgsl(SHADER).unwrap();
+        let vertex = reflection
+            .entry_point(wgpu::ShaderStages::VERTEX, "vertex")
+            .unwrap();
+
+        let positions = wgpu::vertex_attr_array![0 => Float32x3];
+        let joints = wgpu::vertex_attr_array![2 => Uint16x4];
+        let float_joints = wgpu::vertex_attr_array![2 => Float32x4];
+        let layout = |attributes| wgpu::VertexBufferLayout {
+            array_stride: 0,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes,
+        };
+
+        assert_eq!(
+            vertex.check_vertex_buffers(&[layout(&positions), layout(&joints)]),
+            Ok(())
+        );
+        assert_eq!(
+            vertex.check_vertex_buffers(&[layout(&positions)]),
+            Err(ReflectionError::MissingVertexInput(2))
+        );
+        assert_eq!(
+            vertex.check_vertex_buffers(&[layout(&positions), layout(&float_joints)]),
+            Err(ReflectionError::VertexInputMismatch {
+                location: 2,
+                format: wgpu::VertexFormat::Float32x4,
+            })
+        );
+    }
+
+    #[test]
+    fn accepts_read_write_storage_for_read_only_bindings() {
+        let reflection = ShaderReflection::from_wgsl(SHADER).unwrap();
+        let view = reflection.bindings[0].ty;
+
+        assert_eq!(
+            reflection.check_bind_group(0, &[entry(0, view), entry(1, storage(false))]),
+            Ok(())
+        );
+        assert_eq!(
+            reflection.check_bind_group(0, &[entry(0, view), entry(1, storage(true))]),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn rejects_read_only_storage_for_read_write_bindings() {
+        let expected = storage(false);
+        let found = storage(true);
+
+        assert!(!compatible(&expected, &found));
+        assert!(compatible(&expected, &expected));
+    }
+
+    #[test]
+    fn checks_bind_groups() {
+        let reflection = ShaderReflection::from_wgsl(SHADER).unwrap();
+        let texture = reflection.bindings[2].ty;
+        let sampler = wgpu::BindingType::Sampler(wgpu::SamplerBindingType::NonFiltering);
+
+        assert_eq!(
+            reflection.check_bind_group(1, &[entry(0, texture), entry(1, sampler)]),
+            Ok(())
+        );
+        assert_eq!(
+            reflection.check_bind_group(1, &[entry(0, texture)]),
+            Err(ReflectionError::MissingBinding {
+                group: 1,
+                binding: 1
+            })
+        );
+        assert_eq!(
+            reflection.check_bind_group(1, &[entry(0, sampler), entry(1, sampler)]),
+            Err(ReflectionError::BindingMismatch {
+                group: 1,
+                binding: 0,
+                expected: texture,
+                found: sampler,
+            })
+        );
+
+        let vertex_only = wgpu::BindGroupLayoutEntry {
+            visibility: wgpu::ShaderStages::VERTEX,
+            ..entry(0, texture)
+        };
+        assert_eq!(
+            reflection.check_bind_group(1, &[vertex_only, entry(1, sampler)]),
+            Err(ReflectionError::Visibility {
+                group: 1,
+                binding: 0,
+                expected: wgpu::ShaderStages::FRAGMENT,
+                found: wgpu::ShaderStages::VERTEX,
+            })
+        );
+    }
+}
@@ -0,0 +1,326 @@
+//!
+//! Shaders loaded from disk and reloaded on change
+//!
+//! A [`ShaderWatcher`] loads WGSL files through a [`Preprocessor`] and watches them, together
+//! with every file they `#include`. [`ShaderWatcher::update`] should be called once per frame: it
+//! recompiles changed shaders and rebuilds the pipelines using them. Pipelines are handed out as
+//! [`HotPipeline`]s, which always point to the latest successfully built pipeline, so a shader
+//! with errors keeps the previous version on screen while the errors are returned to the game.
+//!
+//! Like the caches, a watcher belongs to a single [`wgpu::Device`] and must always be used with it.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # use std::sync::Arc;
+//! # use render::render_pipleine_builder::RenderPipelineBuilder;
+//! # use render::shader_preprocessor::Preprocessor;
+//! # use render::shader_watcher::ShaderWatcher;
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! # let device: wgpu::Device = unimplemented!();
+//! # let layout: wgpu::PipelineLayout = unimplemented!();
+//! # let mut encoder: wgpu::CommandEncoder = unimplemented!();
+//! # let target: wgpu::TextureView = unimplemented!();
+//! let mut watcher = ShaderWatcher::new(Preprocessor::new())?;
+//!
+//! let shader = watcher.load(&device, "assets/sky.wgsl", wgpu::ShaderStages::VERTEX_FRAGMENT, Some("Sky"))?;
+//! let layout = Arc::new(layout);
+//! let sky = watcher.pipeline(&device, &[shader], move |device, shaders| {
+//!     RenderPipelineBuilder::from_layout(&layout, shaders[0])
+//!         .color_format(wgpu::TextureFormat::Bgra8UnormSrgb)
+//!         .try_build(device, Some("Sky pipeline"))
+//! })?;
+//!
+//! // Every frame:
+//! for err in watcher.update(&device) {
+//!     eprintln!("{}", err);
+//! }
+//! // The render pass borrows the pipeline, so it has to outlive the pass.
+//! let pipeline = sky.current();
+//! # let mut render_pass = render::render_pass::Builder::new()
+//! #     .color_attachment(&target, |attachment| attachment)
+//! #     .begin(&mut encoder);
+//! render_pass.set_pipeline(&pipeline);
+//! # Ok(())
+//! # }
+//! ```
+use std::collections::HashSet;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc, RwLock};
+
+use notify::Watcher;
+
+use crate::render_pipleine_builder::PipelineError;
+use crate::shader::{Shader, ShaderError};
+use crate::shader_preprocessor::Preprocessor;
+
+/// Handle of a shader loaded by [`ShaderWatcher::load`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ShaderId(usize);
+
+/// Render pipeline that is replaced when its shaders change.
+#[derive(Debug, Clone)]
+pub struct HotPipeline {
+    current: Arc<RwLock<Arc<wgpu::RenderPipeline>>>,
+}
+
+impl HotPipeline {
+    /// Latest successfully built pipeline.
+    pub fn current(&self) -> Arc<wgpu::RenderPipeline> {
+        Arc::clone(&self.current.read().unwrap())
+    }
+
+    fn replace(&self, pipeline: wgpu::RenderPipeline) {
+        *self.current.write().unwrap() = Arc::new(pipeline);
+    }
+}
+
+type BuildPipeline =
+    Box<dyn Fn(&wgpu::Device, &[&Shader]) -> Result<wgpu::RenderPipeline, PipelineError>>;
+
+struct WatchedShader {
+    path: PathBuf,
+    stage: wgpu::ShaderStages,
+    label: Option<String>,
+    shader: Arc<Shader>,
+    // Canonical paths of the shader file and the files it includes.
+    files: Vec<PathBuf>,
+}
+
+struct WatchedPipeline {
+    shaders: Vec<ShaderId>,
+    build: BuildPipeline,
+    pipeline: HotPipeline,
+}
+
+/// Loader of WGSL files that recompiles them on change.
+pub struct ShaderWatcher {
+    preprocessor: Preprocessor,
+    watcher: notify::RecommendedWatcher,
+    events: mpsc::Receiver<notify::Result<notify::Event>>,
+    directories: HashSet<PathBuf>,
+    shaders: Vec<WatchedShader>,
+    pipelines: Vec<WatchedPipeline>,
+}
+
+impl ShaderWatcher {
+    /// Create a watcher loading shaders with `preprocessor`.
+    pub fn new(preprocessor: Preprocessor) -> Result<Self, ReloadError> {
+        let (sender, events) = mpsc::channel();
+        let watcher = notify::recommended_watcher(sender)
+            .map_err(|err| ReloadError::Watch(err.to_string()))?;
+        Ok(Self {
+            preprocessor,
+            watcher,
+            events,
+            directories: HashSet::new(),
+            shaders: Vec::new(),
+            pipelines: Vec::new(),
+        })
+    }
+
+    /// Load the WGSL file at `path` for `stage` and start watching it.
+    pub fn load(
+        &mut self,
+        device: &wgpu::Device,
+        path: impl AsRef<Path>,
+        stage: wgpu::ShaderStages,
+        label: Option<&str>,
+    ) -> Result<ShaderId, ReloadError> {
+        let path = path.as_ref();
+        let (shader, files) =
+            self.compile(device, path, stage, label)
+                .map_err(|error| ReloadError::Shader {
+                    path: path.to_path_buf(),
+                    error,
+                })?;
+        self.watch(&files)?;
+
+        self.shaders.push(WatchedShader {
+            path: path.to_path_buf(),
+            stage,
+            label: label.map(str::to_string),
+            shader: Arc::new(shader),
+            files,
+        });
+        Ok(ShaderId(self.shaders.len() - 1))
+    }
+
+    /// Current version of shader `id`.
+    pub fn shader(&self, id: ShaderId) -> Arc<Shader> {
+        Arc::clone(&self.shaders[id.0].shader)
+    }
+
+    /// Build a pipeline from `shaders` and rebuild it whenever one of them is reloaded.
+    ///
+    /// `build` receives the current versions of `shaders`, in the same order.
+    pub fn pipeline<F>(
+        &mut self,
+        device: &wgpu::Device,
+        shaders: &[ShaderId],
+        build: F,
+    ) -> Result<HotPipeline, PipelineError>
+    where
+        F: Fn(&wgpu::Device, &[&Shader]) -> Result<wgpu::RenderPipeline, PipelineError> + 'static,
+    {
+        let pipeline = build(device, &self.current_shaders(shaders))?;
+        let pipeline = HotPipeline {
+            current: Arc::new(RwLock::new(Arc::new(pipeline))),
+        };
+        self.pipelines.push(WatchedPipeline {
+            shaders: shaders.to_vec(),
+            build: Box::new(build),
+            pipeline: pipeline.clone(),
+        });
+        Ok(pipeline)
+    }
+
+    /// Reload shaders changed since the last call and rebuild their pipelines.
+    ///
+    /// Shaders and pipelines that fail keep their previous version. Returns the errors, which
+    /// the game is expected to report.
+    pub fn update(&mut self, device: &wgpu::Device) -> Vec<ReloadError> {
+        let mut errors = Vec::new();
+
+        // Editors often emit several events per save, so each file is reloaded once.
+        let mut changed = HashSet::new();
+        for event in self.events.try_iter() {
+            match event {
+                Ok(event) if event.kind.is_modify() || event.kind.is_create() => {
+                    changed.extend(event.paths);
+                }
+                Ok(_) => {}
+                Err(err) => errors.push(ReloadError::Watch(err.to_string())),
+            }
+        }
+        if changed.is_empty() {
+            return errors;
+        }
+
+        let mut reloaded = Vec::new();
+        for i in 0..self.shaders.len() {
+            let watched = &self.shaders[i];
+            if !watched.files.iter().any(|file| changed.contains(file)) {
+                continue;
+            }
+            let path = watched.path.clone();
+            match self.compile(device, &path, watched.stage, watched.label.as_deref()) {
+                Ok((shader, files)) => {
+                    if let Err(err) = self.watch(&files) {
+                        errors.push(err);
+                    }
+                    let watched = &mut self.shaders[i];
+                    watched.shader = Arc::new(shader);
+                    watched.files = files;
+                    reloaded.push(ShaderId(i));
+                }
+                Err(error) => errors.push(ReloadError::Shader { path, error }),
+            }
+        }
+
+        for watched in &self.pipelines {
+            if !watched.shaders.iter().any(|id| reloaded.contains(id)) {
+                continue;
+            }
+            let shaders = self.current_shaders(&watched.shaders);
+            match (watched.build)(device, &shaders) {
+                Ok(pipeline) => watched.pipeline.replace(pipeline),
+                Err(error) => errors.push(ReloadError::Pipeline {
+                    shaders: watched
+                        .shaders
+                        .iter()
+                        .map(|id| self.shaders[id.0].path.clone())
+                        .collect(),
+                    error,
+                }),
+            }
+        }
+        errors
+    }
+
+    fn compile(
+        &self,
+        device: &wgpu::Device,
+        path: &Path,
+        stage: wgpu::ShaderStages,
+        label: Option<&str>,
+    ) -> Result<(Shader, Vec<PathBuf>), ShaderError> {
+        let processed = self.preprocessor.process_file(path)?;
+        let shader = Shader::from_processed(device, &processed, stage, label)?;
+        // Registered modules are not files and can't change.
+        let files = processed
+            .files()
+            .into_iter()
+            .filter_map(|file| Path::new(file).canonicalize().ok())
+            .collect();
+        Ok((shader, files))
+    }
+
+    // Watch directories instead of files, editors often save by replacing the file.
+    fn watch(&mut self, files: &[PathBuf]) -> Result<(), ReloadError> {
+        for directory in files.iter().filter_map(|file| file.parent()) {
+            if self.directories.contains(directory) {
+                continue;
+            }
+            self.watcher
+                .watch(directory, notify::RecursiveMode::NonRecursive)
+                .map_err(|err| ReloadError::Watch(err.to_string()))?;
+            self.directories.insert(directory.to_path_buf());
+        }
+        Ok(())
+    }
+
+    fn current_shaders(&self, ids: &[ShaderId]) -> Vec<&Shader> {
+        ids.iter().map(|id| &*self.shaders[id.0].shader).collect()
+    }
+}
+
+impl fmt::Debug for ShaderWatcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ShaderWatcher")
+            .field("directories", &self.directories)
+            .field("shaders", &self.shaders.len())
+            .field("pipelines", &self.pipelines.len())
+            .finish()
+    }
+}
+
+/// Error of loading or reloading shaders.
+#[derive(Debug, Clone)]
+pub enum ReloadError {
+    /// File watcher failed, contains the formatted error.
+    Watch(String),
+    /// Shader at `path` failed to compile.
+    Shader { path: PathBuf, error: ShaderError },
+    /// Pipeline using shaders at `shaders` failed to build.
+    Pipeline {
+        shaders: Vec<PathBuf>,
+        error: PipelineError,
+    },
+}
+
+impl fmt::Display for ReloadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Watch(err) => write!(f, "failed to watch shaders: {}", err),
+            Self::Shader { path, error } => {
+                write!(f, "failed to load shader {}: {}", path.display(), error)
+            }
+            Self::Pipeline { shaders, error } => {
+                let paths: Vec<_> = shaders
+                    .iter()
+                    .map(|path| path.display().to_string())
+                    .collect();
+                write!(
+                    f,
+                    "failed to rebuild pipeline of {}: {}",
+                    paths.join(", "),
+                    error
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for ReloadError {}
@@ -0,0 +1,446 @@
+//!
+//! `#[derive(AsBindGroup)]` implementation
+//!
+use proc_macro2::{Span, TokenStream};
+use quote::{format_ident, quote, quote_spanned};
+use syn::{
+    parse::ParseStream, punctuated::Punctuated, spanned::Spanned, Data, DeriveInput, Fields,
+    LitBool, LitInt, LitStr, Meta, Token,
+};
+
+enum Kind {
+    Uniform,
+    Texture {
+        multisampled: bool,
+        dimension: TokenStream,
+        sample_type: TokenStream,
+    },
+    Sampler {
+        sampler_type: TokenStream,
+    },
+    Storage {
+        read_only: bool,
+    },
+}
+
+struct Binding {
+    index: u32,
+    span: Span,
+    visibility: TokenStream,
+    writable_in_vertex: bool,
+    kind: Kind,
+    member: TokenStream,
+    ty: syn::Type,
+}
+
+const ATTRIBUTES: [&str; 4] = ["uniform", "texture", "sampler", "storage"];
+
+fn lit_str(meta: &Meta) -> syn::Result<LitStr> {
+    match meta {
+        Meta::NameValue(syn::MetaNameValue {
+            value:
+                syn::Expr::Lit(syn::ExprLit {
+                    lit: syn::Lit::Str(lit),
+                    ..
+                }),
+            ..
+        }) => Ok(lit.clone()),
+        _ => Err(syn::Error::new(meta.span(), "expected `name = \"value\"`")),
+    }
+}
+
+fn lit_bool(meta: &Meta) -> syn::Result<bool> {
+    match meta {
+        Meta::Path(_) => Ok(true),
+        Meta::NameValue(syn::MetaNameValue {
+            value:
+                syn::Expr::Lit(syn::ExprLit {
+                    lit: syn::Lit::Bool(LitBool { value, .. }),
+                    ..
+                }),
+            ..
+        }) => Ok(*value),
+        _ => Err(syn::Error::new(
+            meta.span(),
+            "expected `name` or `name = bool`",
+        )),
+    }
+}
+
+fn visibility(meta: &Meta) -> syn::Result<(TokenStream, bool)> {
+    let list = meta.require_list()?;
+    let mut stages = Vec::new();
+    let mut vertex = false;
+    list.parse_nested_meta(|stage| {
+        let flag = if stage.path.is_ident("vertex") {
+            vertex = true;
+            quote!(VERTEX)
+        } else if stage.path.is_ident("fragment") {
+            quote!(FRAGMENT)
+        } else if stage.path.is_ident("compute") {
+            quote!(COMPUTE)
+        } else {
+            return Err(stage.error("expected `vertex`, `fragment` or `compute`"));
+        };
+        stages.push(quote!(::render::wgpu::ShaderStages::#flag));
+        Ok(())
+    })?;
+    if stages.is_empty() {
+        return Err(syn::Error::new(
+            list.span(),
+            "binding must be visible to at least one stage",
+        ));
+    }
+    Ok((quote!(#(#stages)|*), vertex))
+}
+
+fn parse_binding(
+    attr: &syn::Attribute,
+    member: TokenStream,
+    ty: &syn::Type,
+) -> syn::Result<Binding> {
+    let name = attr
+        .path()
+        .get_ident()
+        .expect("filtered by name")
+        .to_string();
+    let (index, options) = attr.parse_args_with(|input: ParseStream| {
+        let index: LitInt = input.parse()?;
+        let options = if input.is_empty() {
+            Punctuated::new()
+        } else {
+            input.parse::<Token![,]>()?;
+            Punctuated::<Meta, Token![,]>::parse_terminated(input)?
+        };
+        Ok((index, options))
+    })?;
+
+    let wgpu = quote!(::render::wgpu);
+    let mut visibility_flags = quote!(#wgpu::ShaderStages::VERTEX | #wgpu::ShaderStages::FRAGMENT);
+    let mut in_vertex = true;
+    let mut read_only = false;
+    let mut multisampled = false;
+    let mut dimension = quote!(#wgpu::TextureViewDimension::D2);
+    let mut sample_type = "float".to_string();
+    let mut filterable = true;
+    let mut sampler_type = quote!(#wgpu::SamplerBindingType::Filtering);
+
+    for option in &options {
+        let path = option.path();
+        let unexpected = || {
+            syn::Error::new(
+                path.span(),
+                format!("unexpected option for `#[{}(..)]`", name),
+            )
+        };
+        if path.is_ident("visibility") {
+            (visibility_flags, in_vertex) = visibility(option)?;
+        } else if path.is_ident("read_only") {
+            if name != "storage" {
+                return Err(unexpected());
+            }
+            read_only = lit_bool(option)?;
+        } else if path.is_ident("multisampled") {
+            if name != "texture" {
+                return Err(unexpected());
+            }
+            multisampled = lit_bool(option)?;
+        } else if path.is_ident("filterable") {
+            if name != "texture" {
+                return Err(unexpected());
+            }
+            filterable = lit_bool(option)?;
+        } else if path.is_ident("dimension") {
+            if name != "texture" {
+                return Err(unexpected());
+            }
+            let lit = lit_str(option)?;
+            let variant = match lit.value().as_str() {
+                "1d" => quote!(D1),
+                "2d" => quote!(D2),
+                "2d_array" => quote!(D2Array),
+                "cube" => quote!(Cube),
+                "cube_array" => quote!(CubeArray),
+                "3d" => quote!(D3),
+                _ => {
+                    return Err(syn::Error::new(
+                        lit.span(),
+                        "expected one of \"1d\", \"2d\", \"2d_array\", \"cube\", \"cube_array\", \"3d\"",
+                    ))
+                }
+            };
+            dimension = quote!(#wgpu::TextureViewDimension::#variant);
+        } else if path.is_ident("sample_type") {
+            if name != "texture" {
+                return Err(unexpected());
+            }
+            let lit = lit_str(option)?;
+            sample_type = lit.value();
+            if !["float", "depth", "sint", "uint"].contains(&sample_type.as_str()) {
+                return Err(syn::Error::new(
+                    lit.span(),
+                    "expected one of \"float\", \"depth\", \"sint\", \"uint\"",
+                ));
+            }
+        } else if path.is_ident("sampler_type") {
+            if name != "sampler" {
+                return Err(unexpected());
+            }
+            let lit = lit_str(option)?;
+            sampler_type = match lit.value().as_str() {
+                "filtering" => quote!(#wgpu::SamplerBindingType::Filtering),
+                "non_filtering" => quote!(#wgpu::SamplerBindingType::NonFiltering),
+                "comparison" => quote!(#wgpu::SamplerBindingType::Comparison),
+                _ => {
+                    return Err(syn::Error::new(
+                        lit.span(),
+                        "expected one of \"filtering\", \"non_filtering\", \"comparison\"",
+                    ))
+                }
+            };
+        } else {
+            return Err(unexpected());
+        }
+    }
+
+    if multisampled && sample_type == "float" && filterable {
+        // Multisampled textures can not be sampled with filtering.
+        filterable = false;
+    }
+    let sample_type = match sample_type.as_str() {
+        "float" => quote!(#wgpu::TextureSampleType::Float { filterable: #filterable }),
+        "depth" => quote!(#wgpu::TextureSampleType::Depth),
+        "sint" => quote!(#wgpu::TextureSampleType::Sint),
+        _ => quote!(#wgpu::TextureSampleType::Uint),
+    };
+
+    let kind = match name.as_str() {
+        "uniform" => Kind::Uniform,
+        "texture" => Kind::Texture {
+            multisampled,
+            dimension,
+            sample_type,
+        },
+        "sampler" => Kind::Sampler { sampler_type },
+        _ => Kind::Storage { read_only },
+    };
+
+    Ok(Binding {
+        index: index.base10_parse()?,
+        span: attr.span(),
+        visibility: visibility_flags,
+        writable_in_vertex: in_vertex && matches!(kind, Kind::Storage { read_only: false }),
+        kind,
+        member,
+        ty: ty.clone(),
+    })
+}
+
+fn bindings(input: &DeriveInput) -> syn::Result<Vec<Binding>> {
+    let fields: Vec<_> = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => fields.named.iter().collect(),
+            Fields::Unnamed(fields) => fields.unnamed.iter().collect(),
+            Fields::Unit => Vec::new(),
+        },
+        _ => {
+            return Err(syn::Error::new(
+                input.ident.span(),
+                "AsBindGroup can only be derived for structs",
+            ))
+        }
+    };
+
+    let mut bindings = Vec::new();
+    for (i, field) in fields.iter().enumerate() {
+        let member = match &field.ident {
+            Some(ident) => quote!(#ident),
+            None => {
+                let index = syn::Index::from(i);
+                quote!(#index)
+            }
+        };
+
+        let attrs: Vec<_> = field
+            .attrs
+            .iter()
+            .filter(|attr| ATTRIBUTES.iter().any(|name| attr.path().is_ident(name)))
+            .collect();
+        let field_bindings = attrs
+            .iter()
+            .map(|attr| parse_binding(attr, member.clone(), &field.ty))
+            .collect::<syn::Result<Vec<_>>>()?;
+
+        // Only a texture may share its field with a sampler.
+        let combined = field_bindings.len() == 2
+            && field_bindings
+                .iter()
+                .any(|b| matches!(b.kind, Kind::Texture { .. }))
+            && field_bindings
+                .iter()
+                .any(|b| matches!(b.kind, Kind::Sampler { .. }));
+        if field_bindings.len() > 1 && !combined {
+            return Err(syn::Error::new(
+                field_bindings[1].span,
+                "field can be bound more than once only as `#[texture(..)]` with `#[sampler(..)]`",
+            ));
+        }
+        bindings.extend(field_bindings);
+    }
+
+    for binding in &bindings {
+        if binding.writable_in_vertex {
+            return Err(syn::Error::new(
+                binding.span,
+                "writable storage buffers are not allowed in vertex stage, \
+                 add `read_only` or change `visibility`",
+            ));
+        }
+    }
+
+    bindings.sort_by_key(|binding| binding.index);
+    for pair in bindings.windows(2) {
+        if pair[0].index == pair[1].index {
+            return Err(syn::Error::new(
+                pair[1].span,
+                format!("binding {} is used more than once", pair[1].index),
+            ));
+        }
+    }
+    Ok(bindings)
+}
+
+pub fn expand(input: DeriveInput) -> syn::Result<TokenStream> {
+    let ident = &input.ident;
+    let wgpu = quote!(::render::wgpu);
+    let uniform = quote!(::render::buffers::uniform);
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let bindings = bindings(&input)?;
+
+    let mut buffers = Vec::new();
+    let mut uniforms = Vec::new();
+    let mut writes = Vec::new();
+    let mut resources = Vec::new();
+    let mut entries = Vec::new();
+    for binding in &bindings {
+        let index = binding.index;
+        let member = &binding.member;
+        let visibility = &binding.visibility;
+        let ty = &binding.ty;
+        let span = ty.span();
+        match &binding.kind {
+            Kind::Uniform => {
+                let buffer = format_ident!("uniform_{}", binding.index);
+                let label = format!("{} uniform {}", ident, binding.index);
+                buffers.push(quote_spanned! {span=>
+                    let #buffer = #wgpu::util::DeviceExt::create_buffer_init(
+                        device,
+                        &#wgpu::util::BufferInitDescriptor {
+                            label: Some(#label),
+                            contents: &::render::layout::to_bytes::<#ty>(&self.#member),
+                            usage: #wgpu::BufferUsages::UNIFORM | #wgpu::BufferUsages::COPY_DST,
+                        },
+                    );
+                });
+                resources.push(quote! {
+                    .binding_at(#index, #buffer.as_entire_binding())
+                });
+                uniforms.push(quote! {
+                    #uniform::PreparedUniform {
+                        binding: #index,
+                        buffer: #buffer,
+                        size: <#ty as ::render::layout::WgslType>::SIZE,
+                    }
+                });
+                writes.push(quote_spanned! {span=>
+                    prepared.write::<#ty>(queue, #index, &self.#member);
+                });
+                entries.push(quote! {
+                    .binding_at(#index, #visibility, #wgpu::BindingType::Buffer {
+                        ty: #wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    })
+                });
+            }
+            Kind::Texture {
+                multisampled,
+                dimension,
+                sample_type,
+            } => {
+                resources.push(quote_spanned! {span=>
+                    .binding_at(#index, #wgpu::BindingResource::TextureView(
+                        #uniform::AsTextureView::texture_view(&self.#member),
+                    ))
+                });
+                entries.push(quote! {
+                    .binding_at(#index, #visibility, #wgpu::BindingType::Texture {
+                        multisampled: #multisampled,
+                        view_dimension: #dimension,
+                        sample_type: #sample_type,
+                    })
+                });
+            }
+            Kind::Sampler { sampler_type } => {
+                resources.push(quote_spanned! {span=>
+                    .binding_at(#index, #wgpu::BindingResource::Sampler(
+                        #uniform::AsSampler::sampler(&self.#member),
+                    ))
+                });
+                entries.push(quote! {
+                    .binding_at(#index, #visibility, #wgpu::BindingType::Sampler(#sampler_type))
+                });
+            }
+            Kind::Storage { read_only } => {
+                resources.push(quote_spanned! {span=>
+                    .binding_at(#index, #uniform::AsStorageBinding::storage_binding(&self.#member))
+                });
+                entries.push(quote! {
+                    .binding_at(#index, #visibility, #wgpu::BindingType::Buffer {
+                        ty: #wgpu::BufferBindingType::Storage { read_only: #read_only },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    })
+                });
+            }
+        }
+    }
+
+    let bind_group_label = format!("{} bind group", ident);
+
+    Ok(quote! {
+        impl #impl_generics #uniform::AsBindGroup for #ident #ty_generics #where_clause {
+            fn prepare_bind_group(
+                &self,
+                device: &#wgpu::Device,
+                layout: &#wgpu::BindGroupLayout,
+            ) -> #uniform::PreparedBindGroup {
+                #(#buffers)*
+                let bind_group = ::render::bind_group_builder::Builder::new()
+                    #(#resources)*
+                    .build(device, layout, Some(#bind_group_label));
+                #uniform::PreparedBindGroup {
+                    bind_group,
+                    uniforms: ::std::vec![#(#uniforms),*],
+                }
+            }
+
+            #[allow(unused_variables)]
+            fn write_uniforms(
+                &self,
+                queue: &#wgpu::Queue,
+                prepared: &#uniform::PreparedBindGroup,
+            ) {
+                #(#writes)*
+            }
+
+            fn layout_entries() -> ::std::vec::Vec<#wgpu::BindGroupLayoutEntry> {
+                ::render::bind_group_builder::LayoutBuilder::new()
+                    #(#entries)*
+                    .entries()
+                    .to_vec()
+            }
+        }
+    })
+}
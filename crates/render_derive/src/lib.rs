@@ -0,0 +1,75 @@
+//!
+//! Derive macros for Revengine render crate
+//!
+//! Generated code refers to items through `::render`, so it works both in dependent crates and
+//! inside `render` itself.
+//!
+mod as_bind_group;
+mod vertex;
+mod wgsl_type;
+
+use proc_macro::TokenStream;
+use syn::{parse_macro_input, DeriveInput};
+
+/// Derive `render::layout::WgslType` for a struct with named fields.
+///
+/// Struct attributes:
+/// - `#[wgsl(name = "Light")]` overrides name of the struct in WGSL.
+/// - `#[wgsl(uniform)]` fails compilation if struct breaks uniform address space rules.
+///
+/// Field attributes:
+/// - `#[wgsl(array)]` treats `[T; N]` field as `array<T, N>` instead of a vector or matrix.
+/// - `#[wgsl(align = 16)]` and `#[wgsl(size = 32)]` map to WGSL `@align` and `@size`.
+#[proc_macro_derive(WgslType, attributes(wgsl))]
+pub fn derive_wgsl_type(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    wgsl_type::expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Derive `render::buffers::vertices::Vertex` from field types and offsets.
+///
+/// Every field takes the next shader location, starting from 0, and is mapped to a
+/// `wgpu::VertexFormat` through `render::buffers::vertices::VertexField`.
+///
+/// Struct attributes:
+/// - `#[vertex(step_mode = instance)]` advances the buffer per instance instead of per vertex.
+/// - `#[vertex(location = 5)]` starts numbering shader locations from 5.
+///
+/// Field attributes:
+/// - `#[vertex(location = 3)]` places the field at given location, following fields continue
+///   from it.
+/// - `#[vertex(format = Unorm8x4)]` overrides the format inferred from the field type.
+/// - `#[vertex(skip)]` leaves the field out of the layout, e.g. for padding.
+#[proc_macro_derive(Vertex, attributes(vertex))]
+pub fn derive_vertex(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    vertex::expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Derive `render::buffers::uniform::AsBindGroup` from annotated fields.
+///
+/// Both the layout and the bind group are generated from the same attributes, so they can not
+/// disagree on binding order:
+/// - `#[uniform(0)]` uploads the field, which implements `render::layout::WgslType`, into a new
+///   uniform buffer. Buffers are kept in `render::buffers::uniform::PreparedBindGroup` and
+///   `write_uniforms` uploads the fields again.
+/// - `#[texture(1)]` binds `render::buffers::uniform::AsTextureView`, with optional
+///   `dimension = "2d"`, `sample_type = "float"`, `filterable = false` and `multisampled`.
+/// - `#[sampler(2)]` binds `render::buffers::uniform::AsSampler`, with optional
+///   `sampler_type = "filtering" | "non_filtering" | "comparison"`.
+/// - `#[storage(3, read_only)]` binds `render::buffers::uniform::AsStorageBinding`.
+///
+/// Every attribute accepts `visibility(vertex, fragment, compute)`, vertex and fragment stages are
+/// used by default. A texture field may carry a `#[sampler(..)]` too. Binding indices need not be
+/// contiguous, but each may be used only once.
+#[proc_macro_derive(AsBindGroup, attributes(uniform, texture, sampler, storage))]
+pub fn derive_as_bind_group(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    as_bind_group::expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
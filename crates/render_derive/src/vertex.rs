@@ -0,0 +1,224 @@
+//!
+//! `#[derive(Vertex)]` implementation
+//!
+use proc_macro2::TokenStream;
+use quote::{quote, quote_spanned};
+use syn::{spanned::Spanned, Data, DeriveInput, Fields, Ident, LitInt};
+
+#[derive(Default)]
+struct StructAttrs {
+    instance: bool,
+    location: Option<u32>,
+}
+
+#[derive(Default)]
+struct FieldAttrs {
+    skip: bool,
+    location: Option<u32>,
+    format: Option<Ident>,
+}
+
+fn struct_attrs(input: &DeriveInput) -> syn::Result<StructAttrs> {
+    let mut attrs = StructAttrs::default();
+    for attr in input.attrs.iter().filter(|a| a.path().is_ident("vertex")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("step_mode") {
+                let mode: Ident = meta.value()?.parse()?;
+                attrs.instance = match mode.to_string().as_str() {
+                    "vertex" => false,
+                    "instance" => true,
+                    _ => {
+                        return Err(syn::Error::new(
+                            mode.span(),
+                            "expected `vertex` or `instance`",
+                        ))
+                    }
+                };
+                Ok(())
+            } else if meta.path.is_ident("location") {
+                let lit: LitInt = meta.value()?.parse()?;
+                attrs.location = Some(lit.base10_parse()?);
+                Ok(())
+            } else {
+                Err(meta.error("expected `step_mode = vertex|instance` or `location = N`"))
+            }
+        })?;
+    }
+    Ok(attrs)
+}
+
+fn field_attrs(field: &syn::Field) -> syn::Result<FieldAttrs> {
+    let mut attrs = FieldAttrs::default();
+    for attr in field.attrs.iter().filter(|a| a.path().is_ident("vertex")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("skip") {
+                attrs.skip = true;
+                Ok(())
+            } else if meta.path.is_ident("location") {
+                let lit: LitInt = meta.value()?.parse()?;
+                attrs.location = Some(lit.base10_parse()?);
+                Ok(())
+            } else if meta.path.is_ident("format") {
+                attrs.format = Some(meta.value()?.parse()?);
+                Ok(())
+            } else {
+                Err(meta.error("expected `skip`, `location = N` or `format = Format`"))
+            }
+        })?;
+    }
+    if attrs.skip && (attrs.location.is_some() || attrs.format.is_some()) {
+        return Err(syn::Error::new(
+            field.span(),
+            "skipped field can not have `location` or `format`",
+        ));
+    }
+    Ok(attrs)
+}
+
+pub fn expand(input: DeriveInput) -> syn::Result<TokenStream> {
+    let ident = &input.ident;
+    let wgpu = quote!(::render::wgpu);
+    let field_trait = quote!(::render::buffers::vertices::VertexField);
+
+    if !input.generics.params.is_empty() {
+        return Err(syn::Error::new(
+            input.generics.span(),
+            "Vertex can not be derived for generic structs",
+        ));
+    }
+
+    let fields: Vec<_> = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => fields.named.iter().collect(),
+            Fields::Unnamed(fields) => fields.unnamed.iter().collect(),
+            Fields::Unit => Vec::new(),
+        },
+        _ => {
+            return Err(syn::Error::new(
+                ident.span(),
+                "Vertex can only be derived for structs",
+            ))
+        }
+    };
+
+    let attrs = struct_attrs(&input)?;
+    let start_location = attrs.location.unwrap_or(0);
+    let step_mode = if attrs.instance {
+        quote!(#wgpu::VertexStepMode::Instance)
+    } else {
+        quote!(#wgpu::VertexStepMode::Vertex)
+    };
+
+    let mut counts = Vec::new();
+    let mut fills = Vec::new();
+    for (i, field) in fields.iter().enumerate() {
+        let field_attrs = field_attrs(field)?;
+        if field_attrs.skip {
+            continue;
+        }
+
+        let ty = &field.ty;
+        let span = ty.span();
+        let member = match &field.ident {
+            Some(ident) => quote!(#ident),
+            None => {
+                let index = syn::Index::from(i);
+                quote!(#index)
+            }
+        };
+
+        let formats = match &field_attrs.format {
+            Some(format) => {
+                let msg = format!(
+                    "size of `{}` does not match `VertexFormat::{}`",
+                    quote!(#ty),
+                    format
+                );
+                fills.push(quote! {
+                    assert!(
+                        #wgpu::VertexFormat::#format.size()
+                            == ::core::mem::size_of::<#ty>() as u64,
+                        #msg
+                    );
+                });
+                counts.push(quote!(1));
+                quote!(&[#wgpu::VertexFormat::#format])
+            }
+            None => {
+                let formats = quote_spanned!(span=> <#ty as #field_trait>::FORMATS);
+                counts.push(quote!(#formats.len()));
+                formats
+            }
+        };
+
+        let set_location = field_attrs
+            .location
+            .map(|location| quote!(location = #location;));
+        fills.push(quote! {
+            {
+                let formats: &[#wgpu::VertexFormat] = #formats;
+                let mut offset = ::core::mem::offset_of!(#ident, #member) as u64;
+                #set_location
+                let mut j = 0;
+                while j < formats.len() {
+                    attributes[i] = #wgpu::VertexAttribute {
+                        format: formats[j],
+                        offset,
+                        shader_location: location,
+                    };
+                    offset += formats[j].size();
+                    location += 1;
+                    i += 1;
+                    j += 1;
+                }
+            }
+        });
+    }
+
+    let count = if counts.is_empty() {
+        quote!(0)
+    } else {
+        quote!(#(#counts)+*)
+    };
+    let duplicate_msg = format!("`{}` has two attributes at the same shader location", ident);
+
+    Ok(quote! {
+        impl ::render::buffers::vertices::Vertex for #ident {
+            fn desc<'a>() -> #wgpu::VertexBufferLayout<'a> {
+                const COUNT: usize = #count;
+                #[allow(unused_mut, unused_assignments, unused_variables)]
+                const ATTRIBUTES: [#wgpu::VertexAttribute; COUNT] = {
+                    let mut attributes = [#wgpu::VertexAttribute {
+                        format: #wgpu::VertexFormat::Float32,
+                        offset: 0,
+                        shader_location: 0,
+                    }; COUNT];
+                    let mut i = 0;
+                    let mut location: u32 = #start_location;
+                    #(#fills)*
+
+                    let mut a = 0;
+                    while a < COUNT {
+                        let mut b = a + 1;
+                        while b < COUNT {
+                            assert!(
+                                attributes[a].shader_location != attributes[b].shader_location,
+                                #duplicate_msg
+                            );
+                            b += 1;
+                        }
+                        a += 1;
+                    }
+
+                    attributes
+                };
+
+                #wgpu::VertexBufferLayout {
+                    array_stride: ::core::mem::size_of::<#ident>() as #wgpu::BufferAddress,
+                    step_mode: #step_mode,
+                    attributes: &ATTRIBUTES,
+                }
+            }
+        }
+    })
+}
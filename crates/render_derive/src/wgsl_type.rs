@@ -0,0 +1,298 @@
+//!
+//! `#[derive(WgslType)]` implementation
+//!
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote, quote_spanned};
+use syn::{spanned::Spanned, Data, DeriveInput, Fields, LitInt, LitStr, Type};
+
+#[derive(Default)]
+struct StructAttrs {
+    name: Option<String>,
+    uniform: bool,
+}
+
+#[derive(Default)]
+struct FieldAttrs {
+    array: bool,
+    align: Option<u64>,
+    size: Option<u64>,
+}
+
+fn struct_attrs(input: &DeriveInput) -> syn::Result<StructAttrs> {
+    let mut attrs = StructAttrs::default();
+    for attr in input.attrs.iter().filter(|a| a.path().is_ident("wgsl")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("name") {
+                let name: LitStr = meta.value()?.parse()?;
+                attrs.name = Some(name.value());
+                Ok(())
+            } else if meta.path.is_ident("uniform") {
+                attrs.uniform = true;
+                Ok(())
+            } else {
+                Err(meta.error("expected `name = \"...\"` or `uniform`"))
+            }
+        })?;
+    }
+    Ok(attrs)
+}
+
+fn power_of_two(meta: &syn::meta::ParseNestedMeta) -> syn::Result<u64> {
+    let lit: LitInt = meta.value()?.parse()?;
+    let value = lit.base10_parse::<u64>()?;
+    if !value.is_power_of_two() {
+        return Err(syn::Error::new(lit.span(), "value must be a power of two"));
+    }
+    Ok(value)
+}
+
+fn field_attrs(field: &syn::Field) -> syn::Result<FieldAttrs> {
+    let mut attrs = FieldAttrs::default();
+    for attr in field.attrs.iter().filter(|a| a.path().is_ident("wgsl")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("array") {
+                attrs.array = true;
+                Ok(())
+            } else if meta.path.is_ident("align") {
+                attrs.align = Some(power_of_two(&meta)?);
+                Ok(())
+            } else if meta.path.is_ident("size") {
+                let lit: LitInt = meta.value()?.parse()?;
+                attrs.size = Some(lit.base10_parse()?);
+                Ok(())
+            } else {
+                Err(meta.error("expected `array`, `align = N` or `size = N`"))
+            }
+        })?;
+    }
+    Ok(attrs)
+}
+
+pub fn expand(input: DeriveInput) -> syn::Result<TokenStream> {
+    let ident = &input.ident;
+    let krate = quote!(::render::layout);
+
+    if !input.generics.params.is_empty() {
+        return Err(syn::Error::new(
+            input.generics.span(),
+            "WgslType can not be derived for generic structs",
+        ));
+    }
+
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(syn::Error::new(
+                    ident.span(),
+                    "WgslType requires named fields to name WGSL struct members",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new(
+                ident.span(),
+                "WgslType can only be derived for structs",
+            ))
+        }
+    };
+    if fields.is_empty() {
+        return Err(syn::Error::new(
+            ident.span(),
+            "WGSL structs must have at least one member",
+        ));
+    }
+
+    let attrs = struct_attrs(&input)?;
+    let wgsl_name = attrs.name.unwrap_or_else(|| ident.to_string());
+
+    let mut offsets = Vec::new();
+    let mut members = Vec::new();
+    let mut aligns = Vec::new();
+    let mut uniform_checks = Vec::new();
+    let mut writes = Vec::new();
+    let mut dependencies = Vec::new();
+    let mut declarations = Vec::new();
+    let mut assertions = Vec::new();
+
+    for (i, field) in fields.iter().enumerate() {
+        let field_ident = field.ident.as_ref().expect("named field");
+        let field_name = field_ident.to_string();
+        let field_name = field_name.trim_start_matches("r#");
+        let field_attrs = field_attrs(field)?;
+        let ty = &field.ty;
+        let span = ty.span();
+
+        // Natural alignment and size of the member, and how to write and name it.
+        let (natural_align, natural_size, element, wgsl_ty, write) = if field_attrs.array {
+            let array = match ty {
+                Type::Array(array) => array,
+                _ => {
+                    return Err(syn::Error::new(
+                        span,
+                        "`#[wgsl(array)]` can only be used on `[T; N]` fields",
+                    ))
+                }
+            };
+            let elem = &array.elem;
+            let len = &array.len;
+            let elem_trait = quote_spanned!(span=> <#elem as #krate::WgslType>);
+            let stride = quote!(#krate::array_stride::<#elem>());
+            let write = quote! {
+                for (i, element) in self.#field_ident.iter().enumerate() {
+                    let offset = (members[#i].offset + i as u64 * #stride) as usize;
+                    #krate::WgslType::write_bytes(
+                        element,
+                        &mut bytes[offset..][..#elem_trait::SIZE as usize],
+                    );
+                }
+            };
+            uniform_checks.push(quote! {
+                #elem_trait::UNIFORM_COMPATIBLE
+                    && Self::MEMBERS[#i].offset % 16 == 0
+                    && #stride % 16 == 0
+            });
+            (
+                quote!(#elem_trait::ALIGN),
+                quote!((#len) as u64 * #stride),
+                elem_trait.clone(),
+                quote!(format!("array<{}, {}>", #elem_trait::WGSL_NAME, #len)),
+                write,
+            )
+        } else {
+            let ty_trait = quote_spanned!(span=> <#ty as #krate::WgslType>);
+            let write = quote! {
+                #krate::WgslType::write_bytes(
+                    &self.#field_ident,
+                    &mut bytes[members[#i].offset as usize..][..#ty_trait::SIZE as usize],
+                );
+            };
+            // Nested struct must start at 16 byte boundary and take at least roundUp(16, size).
+            let gap = if i + 1 < fields.len() {
+                let next = i + 1;
+                quote! {
+                    && Self::MEMBERS[#next].offset - Self::MEMBERS[#i].offset
+                        >= #krate::round_up(16, #ty_trait::SIZE)
+                }
+            } else {
+                quote!()
+            };
+            uniform_checks.push(quote! {
+                #ty_trait::UNIFORM_COMPATIBLE
+                    && (#ty_trait::MEMBERS.is_empty()
+                        || (Self::MEMBERS[#i].offset % 16 == 0 #gap))
+            });
+            (
+                quote!(#ty_trait::ALIGN),
+                quote!(#ty_trait::SIZE),
+                ty_trait.clone(),
+                quote!(#ty_trait::WGSL_NAME.to_string()),
+                write,
+            )
+        };
+
+        let mut prefix = String::new();
+        let align = match field_attrs.align {
+            Some(align) => {
+                prefix.push_str(&format!("@align({}) ", align));
+                let msg = format!(
+                    "`@align({})` of `{}` is not a multiple of its alignment",
+                    align, field_name
+                );
+                assertions.push(quote!(assert!(#align % #natural_align == 0, #msg);));
+                quote!(#align)
+            }
+            None => natural_align,
+        };
+        let size = match field_attrs.size {
+            Some(size) => {
+                prefix.push_str(&format!("@size({}) ", size));
+                let msg = format!(
+                    "`@size({})` of `{}` is less than its size",
+                    size, field_name
+                );
+                assertions.push(quote!(assert!(#size >= #natural_size, #msg);));
+                quote!(#size)
+            }
+            None => natural_size,
+        };
+
+        let offset = format_ident!("offset_{}", i);
+        let previous_end = if i == 0 {
+            quote!(0)
+        } else {
+            let previous = format_ident!("offset_{}", i - 1);
+            let previous_size = &members[i - 1];
+            quote!(#previous + #previous_size)
+        };
+        offsets.push(quote!(let #offset = #krate::round_up(#align, #previous_end);));
+        let member_size = size.clone();
+        members.push(member_size);
+        aligns.push(quote!(align = #krate::max(align, #align);));
+        writes.push(write);
+        dependencies.push(quote!(#element::wgsl_declarations(declarations);));
+        declarations.push(quote! {
+            declaration.push_str(&format!("    {}{}: {},\n", #prefix, #field_name, #wgsl_ty));
+        });
+    }
+
+    let member_items = fields.iter().enumerate().map(|(i, field)| {
+        let name = field.ident.as_ref().expect("named field").to_string();
+        let name = name.trim_start_matches("r#").to_string();
+        let offset = format_ident!("offset_{}", i);
+        let size = &members[i];
+        quote!(#krate::WgslMember { name: #name, offset: #offset, size: #size })
+    });
+    let last = fields.len() - 1;
+
+    let uniform_assertion = if attrs.uniform {
+        let msg = format!(
+            "`{}` breaks WGSL uniform address space layout rules: \
+             struct and array members need 16 byte alignment and array stride",
+            ident
+        );
+        quote!(assert!(<#ident as #krate::WgslType>::UNIFORM_COMPATIBLE, #msg);)
+    } else {
+        quote!()
+    };
+
+    Ok(quote! {
+        impl #krate::WgslType for #ident {
+            const WGSL_NAME: &'static str = #wgsl_name;
+            const ALIGN: u64 = {
+                let mut align = 1;
+                #(#aligns)*
+                align
+            };
+            const SIZE: u64 = #krate::round_up(
+                Self::ALIGN,
+                Self::MEMBERS[#last].offset + Self::MEMBERS[#last].size,
+            );
+            const MEMBERS: &'static [#krate::WgslMember] = &{
+                #(#offsets)*
+                [#(#member_items),*]
+            };
+            const UNIFORM_COMPATIBLE: bool = #(#uniform_checks)&&*;
+
+            fn write_bytes(&self, bytes: &mut [u8]) {
+                let members = <Self as #krate::WgslType>::MEMBERS;
+                #(#writes)*
+            }
+
+            fn wgsl_declarations(declarations: &mut Vec<String>) {
+                #(#dependencies)*
+                let mut declaration = format!("struct {} {{\n", #wgsl_name);
+                #(#declarations)*
+                declaration.push_str("};\n");
+                if !declarations.contains(&declaration) {
+                    declarations.push(declaration);
+                }
+            }
+        }
+
+        const _: () = {
+            #(#assertions)*
+            #uniform_assertion
+        };
+    })
+}
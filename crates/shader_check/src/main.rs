@@ -0,0 +1,467 @@
+//!
+//! Offline validation of WGSL shaders
+//!
+//! Walks shader directories and runs every `.wgsl` file through the engine's
+//! [`Preprocessor`] and naga validation, once per permutation of the defines it checks with
+//! `#ifdef` and `#ifndef`. Errors are printed with their original file and line, and the exit code
+//! is non-zero if any shader fails.
+//!
+//! ```text
+//! cargo run -p shader_check -- crates/render/src/mesh/assets/shaders
+//! cargo run -p shader_check -- -D MAX_LIGHTS=8 --module post::common=assets/post/common.wgsl assets
+//! ```
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use render::shader_preprocessor::{Preprocessor, SourceLocation, WgslError};
+
+/// Files with at most this many conditions are checked in every combination of them.
+const MAX_EXHAUSTIVE_CONDITIONS: usize = 6;
+
+const USAGE: &str = "\
+Usage: shader_check [OPTIONS] <DIR>...
+
+Validate every .wgsl file under DIRs, in every permutation of its #ifdef conditions.
+
+Options:
+  -D, --define NAME[=VALUE]   Define NAME for every shader
+      --module NAME=PATH      Register file at PATH as module NAME for #import
+  -h, --help                  Print this message";
+
+struct Args {
+    defines: Vec<(String, String)>,
+    modules: Vec<(String, PathBuf)>,
+    directories: Vec<PathBuf>,
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(Some(args)) => args,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Err(err) => {
+            eprintln!("error: {}\n\n{}", err, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+
+    let mut preprocessor = render::mesh::shader_modules(Preprocessor::new());
+    for (name, value) in &args.defines {
+        preprocessor = preprocessor.define(name, value);
+    }
+    for (name, path) in &args.modules {
+        match std::fs::read_to_string(path) {
+            Ok(source) => preprocessor = preprocessor.module(name, &source),
+            Err(err) => {
+                eprintln!("error: failed to read module {}: {}", path.display(), err);
+                return ExitCode::from(2);
+            }
+        }
+    }
+
+    let mut files = Vec::new();
+    for directory in &args.directories {
+        if let Err(err) = collect_shaders(directory, &mut files) {
+            eprintln!("error: failed to read {}: {}", directory.display(), err);
+            return ExitCode::from(2);
+        }
+    }
+    // Modules are checked as part of the shaders importing them.
+    let modules: Vec<_> = args
+        .modules
+        .iter()
+        .filter_map(|(_, path)| path.canonicalize().ok())
+        .collect();
+    files.retain(|file| {
+        file.canonicalize()
+            .map_or(true, |file| !modules.contains(&file))
+    });
+    files.sort();
+
+    let mut permutations = 0;
+    let mut failed = 0;
+    for file in &files {
+        let checked = check_file(&preprocessor, file);
+        permutations += checked.permutations;
+        if !checked.errors.is_empty() {
+            failed += 1;
+            for error in &checked.errors {
+                print_error(&preprocessor, file, error);
+            }
+        }
+    }
+
+    println!(
+        "checked {} shaders in {} permutations, {} failed",
+        files.len(),
+        permutations,
+        failed
+    );
+    if failed == 0 {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Option<Args>, String> {
+    let mut parsed = Args {
+        defines: Vec::new(),
+        modules: Vec::new(),
+        directories: Vec::new(),
+    };
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "-D" | "--define" => {
+                let define = args.next().ok_or("`--define` requires NAME[=VALUE]")?;
+                let (name, value) = define.split_once('=').unwrap_or((&define, ""));
+                parsed.defines.push((name.to_string(), value.to_string()));
+            }
+            "--module" => {
+                let module = args.next().ok_or("`--module` requires NAME=PATH")?;
+                let (name, path) = module
+                    .split_once('=')
+                    .ok_or("`--module` requires NAME=PATH")?;
+                parsed.modules.push((name.to_string(), PathBuf::from(path)));
+            }
+            _ if arg.starts_with('-') => return Err(format!("unknown option `{}`", arg)),
+            _ => parsed.directories.push(PathBuf::from(arg)),
+        }
+    }
+    if parsed.directories.is_empty() {
+        return Err("no shader directory given".to_string());
+    }
+    Ok(Some(parsed))
+}
+
+fn collect_shaders(directory: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(directory)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_shaders(&path, files)?;
+        } else if path
+            .extension()
+            .is_some_and(|extension| extension == "wgsl")
+        {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Failure of a shader, with the permutations it occurs in.
+struct CheckError {
+    message: String,
+    location: Option<SourceLocation>,
+    column: Option<u32>,
+    labels: Vec<String>,
+    permutations: Vec<Vec<String>>,
+}
+
+struct Checked {
+    permutations: usize,
+    errors: Vec<CheckError>,
+}
+
+fn check_file(preprocessor: &Preprocessor, file: &Path) -> Checked {
+    let name = file.to_string_lossy();
+    let source = match std::fs::read_to_string(file) {
+        Ok(source) => source,
+        Err(err) => {
+            return Checked {
+                permutations: 0,
+                errors: vec![CheckError {
+                    message: format!("failed to read file: {}", err),
+                    location: None,
+                    column: None,
+                    labels: Vec::new(),
+                    permutations: vec![Vec::new()],
+                }],
+            }
+        }
+    };
+
+    let conditions: Vec<_> = preprocessor
+        .conditions(&name, &source)
+        .into_iter()
+        .filter(|condition| !preprocessor.defines().contains_key(condition))
+        .collect();
+    let permutations = permutations(&conditions);
+
+    // The same error usually shows up in many permutations, report it once.
+    let mut errors: Vec<CheckError> = Vec::new();
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    for defines in &permutations {
+        let error = check_permutation(preprocessor, &name, &source, defines);
+        let Some(mut error) = error else { continue };
+        let key = format!("{:?}{}", error.location, error.message);
+        match seen.get(&key) {
+            Some(&i) => errors[i].permutations.push(defines.clone()),
+            None => {
+                seen.insert(key, errors.len());
+                error.permutations.push(defines.clone());
+                errors.push(error);
+            }
+        }
+    }
+    Checked {
+        permutations: permutations.len(),
+        errors,
+    }
+}
+
+fn check_permutation(
+    preprocessor: &Preprocessor,
+    name: &str,
+    source: &str,
+    defines: &[String],
+) -> Option<CheckError> {
+    let preprocessor = defines
+        .iter()
+        .fold(preprocessor.clone(), |preprocessor, define| {
+            preprocessor.define(define, "")
+        });
+    let processed = match preprocessor.process(name, source) {
+        Ok(processed) => processed,
+        Err(err) => {
+            let root = err.root();
+            return Some(CheckError {
+                message: root.kind.to_string(),
+                location: root.location.clone(),
+                column: None,
+                labels: err
+                    .includes()
+                    .iter()
+                    .map(|location| format!("included from {}", location))
+                    .collect(),
+                permutations: Vec::new(),
+            });
+        }
+    };
+    processed.validate().err().map(|err| {
+        let WgslError {
+            location,
+            column,
+            message,
+            labels,
+        } = err;
+        CheckError {
+            message,
+            location,
+            column,
+            labels,
+            permutations: Vec::new(),
+        }
+    })
+}
+
+// Every combination of `conditions` if there are few, otherwise none, each alone and all.
+fn permutations(conditions: &[String]) -> Vec<Vec<String>> {
+    if conditions.len() <= MAX_EXHAUSTIVE_CONDITIONS {
+        return (0..1u32 << conditions.len())
+            .map(|mask| {
+                conditions
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, _)| mask & (1 << i) != 0)
+                    .map(|(_, condition)| condition.clone())
+                    .collect()
+            })
+            .collect();
+    }
+    let mut permutations = vec![Vec::new()];
+    permutations.extend(conditions.iter().map(|condition| vec![condition.clone()]));
+    permutations.push(conditions.to_vec());
+    permutations
+}
+
+fn print_error(preprocessor: &Preprocessor, file: &Path, error: &CheckError) {
+    println!(
+        "error: {}",
+        error.message.lines().next().unwrap_or_default()
+    );
+    for line in error.message.lines().skip(1) {
+        println!("{}", line);
+    }
+
+    let number = error
+        .location
+        .as_ref()
+        .map_or_else(String::new, |location| location.line.to_string());
+    let gutter = " ".repeat(number.len());
+    match &error.location {
+        Some(location) => {
+            match error.column {
+                Some(column) => println!("{}--> {}:{}", gutter, location, column),
+                None => println!("{}--> {}", gutter, location),
+            }
+            if let Some(text) = source_line(preprocessor, location) {
+                println!("{} |", gutter);
+                println!("{} | {}", number, text);
+                if let Some(column) = error.column {
+                    let padding: String = text
+                        .chars()
+                        .take(column.saturating_sub(1) as usize)
+                        .map(|c| if c == '\t' { '\t' } else { ' ' })
+                        .collect();
+                    println!("{} | {}^", gutter, padding);
+                }
+            }
+            // Errors in modules don't tell which shader imported them.
+            if *location.file != *file.to_string_lossy() {
+                println!("{} = note: in shader {}", gutter, file.display());
+            }
+        }
+        None => println!("{}--> {}", gutter, file.display()),
+    }
+    for label in &error.labels {
+        println!("{} = note: {}", gutter, label);
+    }
+
+    let describe = |defines: &Vec<String>| {
+        if defines.is_empty() {
+            "no defines".to_string()
+        } else {
+            defines.join(", ")
+        }
+    };
+    let first = describe(&error.permutations[0]);
+    match error.permutations.len() {
+        1 => println!("{} = with {}", gutter, first),
+        n => println!(
+            "{} = with {} and {} other permutations",
+            gutter,
+            first,
+            n - 1
+        ),
+    }
+    println!();
+}
+
+// Line of the original file or registered module.
+fn source_line(preprocessor: &Preprocessor, location: &SourceLocation) -> Option<String> {
+    let source = match preprocessor.module_source(&location.file) {
+        Some(source) => source.to_string(),
+        None => std::fs::read_to_string(&*location.file).ok()?,
+    };
+    source
+        .lines()
+        .nth(location.line.checked_sub(1)?)
+        .map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Option<Args>, String> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    fn parse_error(args: &[&str]) -> String {
+        parse(args).err().expect("arguments should be rejected")
+    }
+
+    fn names(conditions: &[&str]) -> Vec<String> {
+        conditions.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn parses_defines_modules_and_directories() {
+        let args = parse(&[
+            "-D",
+            "SHADOWS",
+            "--define",
+            "MAX_LIGHTS=8",
+            "--module",
+            "post::common=assets/post/common.wgsl",
+            "assets",
+            "shaders",
+        ])
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            args.defines,
+            [
+                ("SHADOWS".to_string(), String::new()),
+                ("MAX_LIGHTS".to_string(), "8".to_string()),
+            ]
+        );
+        assert_eq!(
+            args.modules,
+            [(
+                "post::common".to_string(),
+                PathBuf::from("assets/post/common.wgsl")
+            )]
+        );
+        assert_eq!(
+            args.directories,
+            [PathBuf::from("assets"), PathBuf::from("shaders")]
+        );
+    }
+
+    #[test]
+    fn help_stops_parsing() {
+        assert!(parse(&["--help", "--unknown"]).unwrap().is_none());
+        assert!(parse(&["assets", "-h"]).unwrap().is_none());
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        assert_eq!(
+            parse_error(&["assets", "-D"]),
+            "`--define` requires NAME[=VALUE]"
+        );
+        assert_eq!(
+            parse_error(&["assets", "--module"]),
+            "`--module` requires NAME=PATH"
+        );
+        assert_eq!(
+            parse_error(&["--module", "common.wgsl", "assets"]),
+            "`--module` requires NAME=PATH"
+        );
+        assert_eq!(
+            parse_error(&["--verbose", "assets"]),
+            "unknown option `--verbose`"
+        );
+        assert_eq!(parse_error(&["-D", "A"]), "no shader directory given");
+    }
+
+    #[test]
+    fn checks_every_combination_of_few_conditions() {
+        assert_eq!(permutations(&[]), [Vec::<String>::new()]);
+
+        let permutations = permutations(&names(&["A", "B"]));
+        assert_eq!(
+            permutations,
+            [names(&[]), names(&["A"]), names(&["B"]), names(&["A", "B"])]
+        );
+
+        let conditions: Vec<_> = (0..MAX_EXHAUSTIVE_CONDITIONS)
+            .map(|i| i.to_string())
+            .collect();
+        assert_eq!(
+            super::permutations(&conditions).len(),
+            1 << MAX_EXHAUSTIVE_CONDITIONS
+        );
+    }
+
+    #[test]
+    fn falls_back_to_single_conditions() {
+        let conditions: Vec<_> = (0..=MAX_EXHAUSTIVE_CONDITIONS)
+            .map(|i| format!("C{}", i))
+            .collect();
+        let permutations = permutations(&conditions);
+
+        assert_eq!(permutations.len(), conditions.len() + 2);
+        assert_eq!(permutations[0], names(&[]));
+        for (permutation, condition) in permutations[1..].iter().zip(&conditions) {
+            assert_eq!(permutation, std::slice::from_ref(condition));
+        }
+        assert_eq!(permutations.last(), Some(&conditions));
+    }
+}
@@ -0,0 +1,21 @@
+#import render::bindings
+#import render::material
+#import mesh::vertex_output
+
+@vertex
+fn vertex(
+    @location(0) position: vec3<f32>,
+    @location(1) tex_coord: vec2<f32>,
+    @location(2) normal: vec3<f32>,
+) -> VertexOutput {
+    var result: VertexOutput;
+    result.tex_coord = tex_coord;
+    result.position = view.view_projection * object.model * vec4(position, 1.0);
+    return result;
+}
+
+@fragment
+fn fragment(vertex: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(logo, logo_sampler, vertex.tex_coord) * material.tint;
+    return vec4(color.rgb + vec3(material.glow), color.a);
+}
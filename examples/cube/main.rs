@@ -1,3 +1,6 @@
+use std::sync::Arc;
+use std::time::Instant;
+
 use bytemuck::{Pod, Zeroable};
 use winit::{
     event::{Event, WindowEvent},
@@ -5,10 +8,7 @@ use winit::{
     window::Window,
 };
 
-use render::{
-    mesh::material::{AsMaterial, ObjectGpu},
-    prelude::*,
-};
+use render::{bindings::IDENTITY, mesh::material::ObjectGpu, prelude::*};
 
 #[repr(C)]
 #[derive(Copy, Clone, Zeroable, Pod)]
@@ -69,7 +69,7 @@ async fn run(event_loop: EventLoop<()>, window: Window) {
         .await
         .expect("Failed to create device");
 
-    let mut config = wgpu::SurfaceConfiguration {
+    let config = wgpu::SurfaceConfiguration {
         usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
         format: swapchain_format,
         width: size.width,
@@ -77,8 +77,6 @@ async fn run(event_loop: EventLoop<()>, window: Window) {
         present_mode: wgpu::PresentMode::Fifo,
     };
 
-    surface.configure(&device, &config);
-
     struct Kek {
         min_x: f32,
         max_x: f32,
@@ -168,14 +166,51 @@ async fn run(event_loop: EventLoop<()>, window: Window) {
     ];
 
     // user side
-    let mat = BaseMaterial::new([0.0, 1.0, 0.0], MX_REF.mat);
+    let mat = BaseMaterial::new([0.0, 1.0, 0.0]);
     let mesh = Mesh::new(verticies, Some(indices.clone()));
     let mesh2 = Mesh::new(anime, Some(indices));
 
     // render extract
     let ayay = mesh.into_gpu(&device);
     let ayay2 = mesh2.into_gpu(&device);
-    let mut ы = ObjectGpu::new(vec![ayay, ayay2], mat.material(&device));
+    let mut bind_group_cache = BindGroupCache::new();
+    let mut pipeline_cache = PipelineCache::new();
+    let base_material =
+        MaterialType::<BaseMaterial>::new(&device, &mut bind_group_cache, &mut pipeline_cache);
+    let material = Arc::new(base_material.instance(&device, &mat));
+    let object = ObjectGpu::new(
+        &device,
+        &mut bind_group_cache,
+        vec![ayay],
+        material.clone(),
+        IDENTITY,
+    );
+
+    let logo_material = DynamicMaterialType::load(
+        &device,
+        &queue,
+        &mut bind_group_cache,
+        &mut pipeline_cache,
+        concat!(env!("CARGO_MANIFEST_DIR"), "/examples/cube/logo.ron"),
+    )
+    .unwrap_or_else(|err| panic!("Failed to load logo material: {}", err));
+    let logo = Arc::new(logo_material.instance(&device));
+    let logo_object = ObjectGpu::new(
+        &device,
+        &mut bind_group_cache,
+        vec![ayay2],
+        logo.clone(),
+        IDENTITY,
+    );
+
+    let mut renderer = Renderer::new(&device, &mut bind_group_cache, surface, config);
+    renderer.set_view(
+        &queue,
+        &ViewUniform::new(IDENTITY, bytemuck::cast(MX_REF.mat), [0.0; 3]),
+    );
+    renderer.add(Box::new(object));
+    renderer.add(Box::new(logo_object));
+    let start = Instant::now();
 
     event_loop.run(move |event, _, control_flow| {
         // Have the closure take ownership of the resources.
@@ -190,30 +225,20 @@ async fn run(event_loop: EventLoop<()>, window: Window) {
                 ..
             } => {
                 // Reconfigure the surface with the new size
-                config.width = size.width;
-                config.height = size.height;
-                surface.configure(&device, &config);
+                renderer.resize(&device, size.width, size.height);
                 // On macos the window needs to be redrawn manually after resizing
                 window.request_redraw();
             }
             Event::RedrawRequested(_) => {
-                let frame = surface
-                    .get_current_texture()
+                let pulse = 0.5 + 0.5 * start.elapsed().as_secs_f32().sin();
+                material.set_color(&queue, [0.0, pulse, 1.0 - pulse]);
+                logo.set(&queue, "glow", 0.25 * pulse)
+                    .expect("Logo material has a float `glow`");
+                renderer
+                    .render(&device, &mut queue)
                     .expect("Failed to acquire next swap chain texture");
-                let view = frame
-                    .texture
-                    .create_view(&wgpu::TextureViewDescriptor::default());
-
-                let mut ctx = RenderingContext {
-                    device: &device,
-                    queue: &mut queue,
-                    output: &view,
-                };
-
-                ы.render(&mut ctx);
-
-                frame.present();
             }
+            Event::MainEventsCleared => window.request_redraw(),
             Event::WindowEvent {
                 event: WindowEvent::CloseRequested,
                 ..
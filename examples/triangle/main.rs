@@ -7,7 +7,7 @@ use winit::{
     window::Window,
 };
 
-use render::revengine_wgpu::prelude::*;
+use render::prelude::*;
 
 #[repr(C)]
 #[derive(Copy, Clone, Debug, Pod, Zeroable)]
@@ -58,38 +58,30 @@ impl Renderable for TriangleObj {
         self.uniforms.copy_to_gpu(context.queue, &self.color)
     }
 
-    fn render(&mut self, context: &mut RenderingContext) {
-        let mut encoder = context.create_encoder("Triangle Encoder");
-
-        {
-            let mut rend_pass = RenderPassBuilder::new()
-                .color_attachment(context.output, |col_builer| {
-                    col_builer
-                        .load_op(wgpu::LoadOp::Clear(wgpu::Color::BLUE))
-                        .store_op(true)
-                })
-                .begin(&mut encoder);
-
-            rend_pass.set_pipeline(&self.pipeline);
-            rend_pass.set_bind_group(0, self.uniforms.get_bind_group(), &[]);
-            rend_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-            rend_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-            rend_pass.draw_indexed(0..3, 0, 0..1);
-        }
-
-        context.submit(encoder);
+    fn render<'a>(&'a self, queue: &mut RenderQueue<'a>) {
+        let key = SortKey::new(RenderPhase::Opaque, 0, 0, 0.0);
+        queue.push(
+            Draw::new(key, &self.pipeline, &self.vertex_buffer, 0..3)
+                .bind_group(MATERIAL_GROUP, &self.uniforms.bind_group)
+                .indexed(&self.index_buffer, wgpu::IndexFormat::Uint32, 0..3),
+        );
     }
 }
 
 impl TriangleObj {
-    fn new(device: &Device, color: Color) -> Self {
+    fn new(device: &Device, cache: &mut BindGroupCache, color: Color) -> Self {
         let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: None,
             source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("shader.wgsl"))),
         });
 
-        let uniforms =
-            UniformBuffer::<Color>::new(device, wgpu::ShaderStages::FRAGMENT, "Triangle Uniform");
+        let uniforms = UniformBuffer::init_cached(
+            device,
+            cache,
+            color,
+            wgpu::ShaderStages::FRAGMENT,
+            "Triangle Uniform",
+        );
 
         let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             contents: bytemuck::cast_slice(VERTICES),
@@ -103,16 +95,15 @@ impl TriangleObj {
             label: Some("Triangle indices"),
         });
 
-        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: Some("Trianle pipline layout"),
-            bind_group_layouts: &[uniforms.get_layout()],
-            push_constant_ranges: &[],
-        });
+        // The triangle draws no object group, so the layout ends with the material group.
+        let layouts = BindingLayouts::new(device, cache).with_material(&uniforms.bind_group_layout);
+        let layouts = &layouts[..=MATERIAL_GROUP as usize];
 
-        let pipeline = RenderPipelineBuilder::from_layout(&pipeline_layout, &shader)
+        let pipeline = RenderPipelineBuilder::from_shared_layouts(layouts, &shader)
             .add_vertex_buffer_layout(Vertex::desc())
             .fragment_shader(&shader)
             .color_format(wgpu::TextureFormat::Bgra8UnormSrgb)
+            .depth_format(Renderer::DEPTH_FORMAT)
             .build(device, Some("Triangle pipeline"));
 
         Self {
@@ -156,7 +147,7 @@ async fn run(event_loop: EventLoop<()>, window: Window) {
 
     let swapchain_format = surface.get_supported_formats(&adapter)[0];
 
-    let mut config = wgpu::SurfaceConfiguration {
+    let config = wgpu::SurfaceConfiguration {
         usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
         format: swapchain_format,
         width: size.width,
@@ -164,12 +155,15 @@ async fn run(event_loop: EventLoop<()>, window: Window) {
         present_mode: wgpu::PresentMode::Fifo,
     };
 
-    surface.configure(&device, &config);
-
     let color = Color {
         color: [1.0, 0.0, 1.0],
     };
-    let mut triangle = TriangleObj::new(&device, color);
+    let mut bind_group_cache = BindGroupCache::new();
+    let triangle = TriangleObj::new(&device, &mut bind_group_cache, color);
+
+    let mut renderer = Renderer::new(&device, &mut bind_group_cache, surface, config);
+    renderer.set_clear_color(wgpu::Color::BLUE);
+    renderer.add(Box::new(triangle));
 
     event_loop.run(move |event, _, control_flow| {
         // Have the closure take ownership of the resources.
@@ -184,30 +178,14 @@ async fn run(event_loop: EventLoop<()>, window: Window) {
                 ..
             } => {
                 // Reconfigure the surface with the new size
-                config.width = size.width;
-                config.height = size.height;
-                surface.configure(&device, &config);
+                renderer.resize(&device, size.width, size.height);
                 // On macos the window needs to be redrawn manually after resizing
                 window.request_redraw();
             }
             Event::RedrawRequested(_) => {
-                let frame = surface
-                    .get_current_texture()
+                renderer
+                    .render(&device, &mut queue)
                     .expect("Failed to acquire next swap chain texture");
-                let view = frame
-                    .texture
-                    .create_view(&wgpu::TextureViewDescriptor::default());
-
-                let mut ctx = RenderingContext {
-                    device: &device,
-                    queue: &mut queue,
-                    output: &view,
-                };
-
-                triangle.update(&mut ctx);
-                triangle.render(&mut ctx);
-
-                frame.present();
             }
             Event::WindowEvent {
                 event: WindowEvent::CloseRequested,
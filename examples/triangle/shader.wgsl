@@ -7,7 +7,7 @@ fn vertex(@location(0) pos: vec2<f32>) -> @builtin(position) vec4<f32> {
     return vec4<f32>(pos, 0.0, 1.0);
 }
 
-@group(0)
+@group(2)
 @binding(0)
 var<uniform> color: vec3<f32>;
 